@@ -0,0 +1,29 @@
+use bc::number::Number;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn number(s: &str) -> Number {
+    Number::parse(s, 10).unwrap()
+}
+
+fn bench_pow(c: &mut Criterion) {
+    let cases = [
+        ("1.0001^100000000", "1.0001", "100000000", 20),
+        ("2^-100000000", "2", "-100000000", 20),
+        ("3^200000", "3", "200000", 0),
+        ("7^1000000", "7", "1000000", 0),
+    ];
+    for (name, base, exponent, scale) in cases {
+        let base = number(base);
+        let exponent = number(exponent);
+        c.bench_function(name, |b| {
+            b.iter(|| black_box(base.clone()).pow(&exponent, scale).unwrap())
+        });
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_pow
+}
+criterion_main!(benches);
@@ -0,0 +1,855 @@
+//! Compilation of programs to the flat instruction stream
+//! that is executed by the `Interpreter`.
+//!
+//! Every operation keeps the index of the statement it belongs to, counted
+//! as in `Program::source_locations`, so that runtime errors are reported
+//! at the same lines as in the source.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::instructions::{
+    BuiltinFunction, ConditionInstruction, ExprInstruction, Function, FunctionArgument, NamedExpr,
+    PrintItem, Program, Register, Span, StmtInstruction, Variable,
+};
+use crate::number::Number;
+
+/// Where a variable or an array is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Slot {
+    Global(usize),
+    /// a parameter or `auto` variable of the function being executed.
+    /// Parameters without an argument refer to the global with the same name
+    Local {
+        index: usize,
+        global: usize,
+    },
+}
+
+/// The operand of the operations that read or modify a variable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Named {
+    Variable(Slot),
+    Last,
+    /// an item of the array, the index is popped from the stack
+    Item(Slot),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Leq,
+    Gt,
+    Geq,
+}
+
+impl Comparison {
+    pub(crate) fn compare(self, lhs: &Number, rhs: &Number) -> bool {
+        match self {
+            Comparison::Eq => lhs == rhs,
+            Comparison::Ne => lhs != rhs,
+            Comparison::Lt => lhs < rhs,
+            Comparison::Leq => lhs <= rhs,
+            Comparison::Gt => lhs > rhs,
+            Comparison::Geq => lhs >= rhs,
+        }
+    }
+}
+
+/// An operation of the stack machine. Operands are popped from the value
+/// stack, with the right operand on top, and results are pushed on it.
+/// Jump targets are indexes in `Code::ops`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Op {
+    /// counts an executed statement, and checks the execution limits
+    Tick,
+    /// pushes the literal with this index, parsed with the current `ibase`
+    Literal(usize),
+    /// pushes the constant with this index
+    Constant(usize),
+    /// pushes 1 for true and 0 for false
+    Bool(bool),
+    Register(Register),
+    /// sets the register to the value on top of the stack, which is kept
+    SetRegister(Register),
+    Load(Named),
+    /// sets the variable to the value on top of the stack, which is kept
+    Store(Named),
+    PreIncrement(Named),
+    PreDecrement(Named),
+    PostIncrement(Named),
+    PostDecrement(Named),
+    /// calls the builtin, `rand` doesn't pop an argument
+    Builtin(BuiltinFunction),
+    Negate,
+    Add,
+    Sub,
+    Mul,
+    // the operators that can fail keep the span of the operator
+    Div(Span),
+    Mod(Span),
+    Pow(Span),
+    BitAnd(Span),
+    BitOr(Span),
+    BitXor(Span),
+    BitNot(Span),
+    Shl(Span),
+    Shr(Span),
+    Compare(Comparison),
+    Not,
+    Jump(usize),
+    /// pops a value and jumps if it is zero
+    JumpIfZero(usize),
+    /// pops a value and jumps if it is not zero
+    JumpIfNotZero(usize),
+    /// pops two values and jumps if the comparison is false
+    JumpUnless(Comparison, usize),
+    Pop,
+    /// pops and prints the value of an expression statement, which becomes `last`
+    PrintValue,
+    /// pops and prints a number of a `print` statement
+    PrintNumber,
+    /// prints the string with this index
+    PrintString(usize),
+    Limits,
+    /// resolves the function of the call site with this index,
+    /// before its arguments are evaluated
+    BeginCall(usize),
+    /// precedes the evaluation of an argument of the call site `call`.
+    /// Jumps to `end` if the function has no parameter for the argument
+    Argument {
+        call: usize,
+        index: usize,
+        end: usize,
+    },
+    /// passes a copy of the global array as an argument
+    ArrayArgument(usize),
+    /// calls the function resolved by `BeginCall`, with
+    /// the arguments that have been evaluated
+    Call(usize),
+    /// returns the value on top of the stack to the caller
+    Return,
+    ReturnZero,
+    /// defines the function with this index
+    Define(usize),
+    Quit,
+    Halt,
+    End,
+}
+
+/// Where an operation comes from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Location {
+    /// index of the statement in `Code::source_locations`
+    pub statement: usize,
+    /// the innermost call whose arguments contain the operation,
+    /// errors without a span are reported there
+    pub call: Option<Span>,
+}
+
+/// A number literal, which is parsed when it is executed
+/// because it depends on `ibase`.
+#[derive(Debug)]
+pub(crate) struct Literal {
+    pub text: String,
+    /// the value when `ibase` is 10, if the literal is valid in base 10
+    pub decimal: Option<Number>,
+}
+
+#[derive(Debug)]
+pub(crate) struct CallSite {
+    pub name: Rc<str>,
+    /// whether each argument is an array
+    pub array_arguments: Vec<bool>,
+}
+
+/// A parameter or `auto` variable, with its index among the
+/// local variables or the local arrays of the function.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LocalVariable {
+    Number(usize),
+    Array(usize),
+}
+
+impl LocalVariable {
+    pub(crate) fn is_array(self) -> bool {
+        matches!(self, LocalVariable::Array(_))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct CompiledFunction {
+    /// the function as it was parsed
+    pub definition: Function,
+    pub parameters: Vec<LocalVariable>,
+    pub locals: Vec<LocalVariable>,
+    pub variable_count: usize,
+    pub array_count: usize,
+    pub code: Rc<Code>,
+}
+
+/// The compiled program or function body.
+#[derive(Debug, Default)]
+pub(crate) struct Code {
+    pub ops: Vec<Op>,
+    /// the location of each operation
+    pub locations: Vec<Location>,
+    pub literals: Vec<Literal>,
+    pub constants: Vec<Number>,
+    pub strings: Vec<String>,
+    pub calls: Vec<CallSite>,
+    /// functions defined by the program, with their names
+    pub functions: Vec<(Rc<str>, Rc<CompiledFunction>)>,
+    pub source_locations: Rc<[usize]>,
+    pub file: Rc<str>,
+}
+
+impl Code {
+    /// the line of the statement of the operation at `pc`
+    pub(crate) fn line(&self, pc: usize) -> usize {
+        self.source_locations
+            .get(self.locations[pc].statement)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Indexes of the global variables and arrays, which are assigned as
+/// the names are compiled and never change afterwards.
+#[derive(Debug, Default)]
+pub(crate) struct GlobalNames {
+    pub variables: HashMap<Rc<str>, usize>,
+    pub arrays: HashMap<Rc<str>, usize>,
+}
+
+fn slot_index(slots: &mut HashMap<Rc<str>, usize>, name: &Rc<str>) -> usize {
+    let next = slots.len();
+    *slots.entry(name.clone()).or_insert(next)
+}
+
+impl GlobalNames {
+    fn variable(&mut self, name: &Rc<str>) -> usize {
+        slot_index(&mut self.variables, name)
+    }
+
+    fn array(&mut self, name: &Rc<str>) -> usize {
+        slot_index(&mut self.arrays, name)
+    }
+}
+
+fn contains_quit(stmt: &StmtInstruction) -> bool {
+    match stmt {
+        StmtInstruction::Quit => true,
+        StmtInstruction::If {
+            body, else_body, ..
+        } => body.iter().chain(else_body).any(contains_quit),
+        StmtInstruction::While { body, .. } => body.iter().any(contains_quit),
+        _ => false,
+    }
+}
+
+fn should_print(expr: &ExprInstruction) -> bool {
+    // assignments should not be printed:
+    // https://pubs.opengroup.org/onlinepubs/9699919799/utilities/bc.html#tag_20_09_10
+    !matches!(
+        expr,
+        ExprInstruction::Assignment { .. } | ExprInstruction::SetRegister { .. }
+    )
+}
+
+/// number of statements in `stmt`, counting the ones in its body
+fn statement_count(stmt: &StmtInstruction) -> usize {
+    match stmt {
+        StmtInstruction::If {
+            instruction_count,
+            else_instruction_count,
+            ..
+        } => instruction_count + else_instruction_count + 1,
+        StmtInstruction::While {
+            instruction_count, ..
+        }
+        | StmtInstruction::For {
+            instruction_count, ..
+        } => instruction_count + 1,
+        StmtInstruction::DefineFunction { .. } => 0,
+        _ => 1,
+    }
+}
+
+// jumps of `break` and `continue` statements, patched at the end of the loop
+#[derive(Default)]
+struct Loop {
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+struct Compiler<'a> {
+    globals: &'a mut GlobalNames,
+    // parameters and `auto` variables of the function being compiled
+    variables: HashMap<Rc<str>, usize>,
+    arrays: HashMap<Rc<str>, usize>,
+    code: Code,
+    statement: usize,
+    call: Option<Span>,
+    loops: Vec<Loop>,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(globals: &'a mut GlobalNames, source_locations: Rc<[usize]>, file: Rc<str>) -> Self {
+        Self {
+            globals,
+            variables: HashMap::new(),
+            arrays: HashMap::new(),
+            code: Code {
+                source_locations,
+                file,
+                ..Default::default()
+            },
+            statement: 0,
+            call: None,
+            loops: Vec::new(),
+        }
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.code.ops.push(op);
+        self.code.locations.push(Location {
+            statement: self.statement,
+            call: self.call,
+        });
+        self.code.ops.len() - 1
+    }
+
+    fn position(&self) -> usize {
+        self.code.ops.len()
+    }
+
+    /// sets the target of the jump at `pc` to the next operation
+    fn patch(&mut self, pc: usize) {
+        let target = self.position();
+        match &mut self.code.ops[pc] {
+            Op::Jump(t) | Op::JumpIfZero(t) | Op::JumpIfNotZero(t) | Op::JumpUnless(_, t) => {
+                *t = target
+            }
+            op => panic!("patching {op:?}, which is not a jump"),
+        }
+    }
+
+    fn declare(&mut self, variable: &Variable) -> LocalVariable {
+        match variable {
+            Variable::Number(name) => LocalVariable::Number(slot_index(&mut self.variables, name)),
+            Variable::Array(name) => LocalVariable::Array(slot_index(&mut self.arrays, name)),
+        }
+    }
+
+    fn variable_slot(&mut self, name: &Rc<str>) -> Slot {
+        let global = self.globals.variable(name);
+        match self.variables.get(name) {
+            Some(&index) => Slot::Local { index, global },
+            None => Slot::Global(global),
+        }
+    }
+
+    fn array_slot(&mut self, name: &Rc<str>) -> Slot {
+        let global = self.globals.array(name);
+        match self.arrays.get(name) {
+            Some(&index) => Slot::Local { index, global },
+            None => Slot::Global(global),
+        }
+    }
+
+    /// compiles the statements of a block, the first of which has index `start`
+    fn block(&mut self, stmts: &[StmtInstruction], start: usize) {
+        let mut index = start;
+        for stmt in stmts {
+            self.stmt(stmt, index);
+            index += statement_count(stmt);
+        }
+    }
+
+    /// compiles a condition that jumps when it is false,
+    /// returns the position of the jump
+    fn condition(&mut self, condition: &ConditionInstruction) -> usize {
+        let (lhs, rhs, comparison) = match condition {
+            ConditionInstruction::Expr(expr) => {
+                self.expr(expr);
+                return self.emit(Op::JumpIfZero(0));
+            }
+            ConditionInstruction::Eq(lhs, rhs) => (lhs, rhs, Comparison::Eq),
+            ConditionInstruction::Ne(lhs, rhs) => (lhs, rhs, Comparison::Ne),
+            ConditionInstruction::Lt(lhs, rhs) => (lhs, rhs, Comparison::Lt),
+            ConditionInstruction::Leq(lhs, rhs) => (lhs, rhs, Comparison::Leq),
+            ConditionInstruction::Gt(lhs, rhs) => (lhs, rhs, Comparison::Gt),
+            ConditionInstruction::Geq(lhs, rhs) => (lhs, rhs, Comparison::Geq),
+        };
+        self.expr(lhs);
+        self.expr(rhs);
+        self.emit(Op::JumpUnless(comparison, 0))
+    }
+
+    fn stmt(&mut self, stmt: &StmtInstruction, index: usize) {
+        self.statement = index;
+        self.emit(Op::Tick);
+        match stmt {
+            StmtInstruction::Break => {
+                let jump = self.emit(Op::Jump(0));
+                let innermost = self.loops.last_mut().expect("break outside of loop");
+                innermost.breaks.push(jump);
+            }
+            StmtInstruction::Continue => {
+                let jump = self.emit(Op::Jump(0));
+                let innermost = self.loops.last_mut().expect("continue outside of loop");
+                innermost.continues.push(jump);
+            }
+            StmtInstruction::Quit => {
+                self.emit(Op::Quit);
+            }
+            StmtInstruction::Halt => {
+                self.emit(Op::Halt);
+            }
+            StmtInstruction::Limits => {
+                self.emit(Op::Limits);
+            }
+            StmtInstruction::Return => {
+                self.emit(Op::ReturnZero);
+            }
+            StmtInstruction::ReturnExpr(expr) => {
+                self.expr(expr);
+                self.emit(Op::Return);
+            }
+            StmtInstruction::If {
+                condition,
+                instruction_count,
+                body,
+                else_body,
+                ..
+            } => {
+                let to_else = self.condition(condition);
+                self.block(body, index + 1);
+                self.statement = index;
+                if else_body.is_empty() {
+                    self.patch(to_else);
+                } else {
+                    let to_end = self.emit(Op::Jump(0));
+                    self.patch(to_else);
+                    self.block(else_body, index + 1 + instruction_count);
+                    self.patch(to_end);
+                }
+            }
+            StmtInstruction::While {
+                condition, body, ..
+            } => {
+                let start = self.position();
+                let to_end = self.condition(condition);
+                self.emit(Op::Tick);
+                self.loops.push(Loop::default());
+                self.block(body, index + 1);
+                self.statement = index;
+                self.emit(Op::Jump(start));
+                self.end_loop(start);
+                self.patch(to_end);
+            }
+            StmtInstruction::For {
+                init,
+                condition,
+                update,
+                body,
+                ..
+            } => {
+                self.expr(init);
+                self.emit(Op::Pop);
+                let start = self.position();
+                let to_end = self.condition(condition);
+                self.emit(Op::Tick);
+                self.loops.push(Loop::default());
+                self.block(body, index + 1);
+                self.statement = index;
+                let update_start = self.position();
+                self.expr(update);
+                self.emit(Op::Pop);
+                self.emit(Op::Jump(start));
+                self.end_loop(update_start);
+                self.patch(to_end);
+            }
+            StmtInstruction::String(s) => {
+                self.code.strings.push(s.clone());
+                self.emit(Op::PrintString(self.code.strings.len() - 1));
+            }
+            StmtInstruction::Print(items) => {
+                for item in items {
+                    match item {
+                        PrintItem::String(s) => {
+                            self.code.strings.push(s.clone());
+                            self.emit(Op::PrintString(self.code.strings.len() - 1));
+                        }
+                        PrintItem::Expr(expr) => {
+                            self.expr(expr);
+                            self.emit(Op::PrintNumber);
+                        }
+                    }
+                }
+            }
+            StmtInstruction::Expr(expr) => {
+                self.expr(expr);
+                if should_print(expr) {
+                    self.emit(Op::PrintValue);
+                } else {
+                    self.emit(Op::Pop);
+                }
+            }
+            StmtInstruction::DefineFunction { .. } => {
+                // the language grammar ensures that this is never reached
+                panic!("function definition outside of the global scope")
+            }
+        }
+    }
+
+    /// patches the `break` jumps of the innermost loop to the next
+    /// operation, and the `continue` jumps to `continue_target`
+    fn end_loop(&mut self, continue_target: usize) {
+        let innermost = self.loops.pop().expect("no loop to end");
+        for jump in innermost.continues {
+            self.code.ops[jump] = Op::Jump(continue_target);
+        }
+        for jump in innermost.breaks {
+            self.patch(jump);
+        }
+    }
+
+    /// compiles the index of an array item, and returns the operand
+    /// of the operations on `named`
+    fn named(&mut self, named: &NamedExpr) -> Named {
+        match named {
+            NamedExpr::VariableNumber(name) => Named::Variable(self.variable_slot(name)),
+            NamedExpr::Last => Named::Last,
+            NamedExpr::ArrayItem { name, index } => {
+                self.expr(index);
+                Named::Item(self.array_slot(name))
+            }
+        }
+    }
+
+    fn literal(&mut self, text: &str) {
+        self.code.literals.push(Literal {
+            text: text.to_string(),
+            decimal: Number::parse(text, 10),
+        });
+        self.emit(Op::Literal(self.code.literals.len() - 1));
+    }
+
+    fn binary(&mut self, lhs: &ExprInstruction, rhs: &ExprInstruction, op: Op) {
+        self.expr(lhs);
+        self.expr(rhs);
+        self.emit(op);
+    }
+
+    /// `And` and `Or` don't evaluate their right operand if the left one
+    /// decides the result, which is `short_circuit_value` in that case
+    fn boolean(&mut self, lhs: &ExprInstruction, rhs: &ExprInstruction, short_circuit_value: bool) {
+        let short_circuit = |target| {
+            if short_circuit_value {
+                Op::JumpIfNotZero(target)
+            } else {
+                Op::JumpIfZero(target)
+            }
+        };
+        self.expr(lhs);
+        let lhs_jump = self.emit(short_circuit(0));
+        self.expr(rhs);
+        let rhs_jump = self.emit(short_circuit(0));
+        self.emit(Op::Bool(!short_circuit_value));
+        let to_end = self.emit(Op::Jump(0));
+        self.patch(lhs_jump);
+        self.patch(rhs_jump);
+        self.emit(Op::Bool(short_circuit_value));
+        self.patch(to_end);
+    }
+
+    fn call(&mut self, name: &Rc<str>, args: &[FunctionArgument], span: Span) {
+        let call = self.code.calls.len();
+        self.code.calls.push(CallSite {
+            name: name.clone(),
+            array_arguments: args
+                .iter()
+                .map(|arg| matches!(arg, FunctionArgument::ArrayVariable(_)))
+                .collect(),
+        });
+        let enclosing_call = self.call.replace(span);
+        self.emit(Op::BeginCall(call));
+        let mut arguments = Vec::with_capacity(args.len());
+        for (index, arg) in args.iter().enumerate() {
+            arguments.push(self.emit(Op::Argument {
+                call,
+                index,
+                end: 0,
+            }));
+            match arg {
+                FunctionArgument::Expr(expr) => self.expr(expr),
+                FunctionArgument::ArrayVariable(name) => {
+                    // arrays are passed by value
+                    let global = self.globals.array(name);
+                    self.emit(Op::ArrayArgument(global));
+                }
+            }
+        }
+        let end = self.emit(Op::Call(call));
+        for pc in arguments {
+            if let Op::Argument { end: e, .. } = &mut self.code.ops[pc] {
+                *e = end;
+            }
+        }
+        self.call = enclosing_call;
+    }
+
+    fn expr(&mut self, expr: &ExprInstruction) {
+        match expr {
+            ExprInstruction::Number(x) => self.literal(x),
+            ExprInstruction::GetRegister(register) => {
+                self.emit(Op::Register(*register));
+            }
+            ExprInstruction::Named(named) => {
+                let named = self.named(named);
+                self.emit(Op::Load(named));
+            }
+            ExprInstruction::Builtin { function, arg } => {
+                self.expr(arg);
+                self.emit(Op::Builtin(*function));
+            }
+            ExprInstruction::PreIncrement(named) => {
+                let named = self.named(named);
+                self.emit(Op::PreIncrement(named));
+            }
+            ExprInstruction::PreDecrement(named) => {
+                let named = self.named(named);
+                self.emit(Op::PreDecrement(named));
+            }
+            ExprInstruction::PostIncrement(named) => {
+                let named = self.named(named);
+                self.emit(Op::PostIncrement(named));
+            }
+            ExprInstruction::PostDecrement(named) => {
+                let named = self.named(named);
+                self.emit(Op::PostDecrement(named));
+            }
+            ExprInstruction::Call { name, args, span } => self.call(name, args, *span),
+            ExprInstruction::Assignment { named, value } => {
+                // the value is evaluated before the index of an array item
+                self.expr(value);
+                let named = self.named(named);
+                self.emit(Op::Store(named));
+            }
+            ExprInstruction::SetRegister { register, value } => {
+                match value.as_ref() {
+                    // if the value is a single digit it has to be interpreted
+                    // as an hexadecimal number, regardless of the value of ibase
+                    ExprInstruction::Number(n) if n.len() == 1 => {
+                        // this cannot fail because the parser ensures that
+                        // the value is a valid hexadecimal number
+                        self.code.constants.push(Number::parse(n, 16).unwrap());
+                        self.emit(Op::Constant(self.code.constants.len() - 1));
+                    }
+                    _ => self.expr(value),
+                }
+                self.emit(Op::SetRegister(*register));
+            }
+            ExprInstruction::UnaryMinus(expr) => {
+                self.expr(expr);
+                self.emit(Op::Negate);
+            }
+            ExprInstruction::Add(lhs, rhs) => self.binary(lhs, rhs, Op::Add),
+            ExprInstruction::Sub(lhs, rhs) => self.binary(lhs, rhs, Op::Sub),
+            ExprInstruction::Mul(lhs, rhs) => self.binary(lhs, rhs, Op::Mul),
+            ExprInstruction::Div(lhs, rhs, span) => self.binary(lhs, rhs, Op::Div(*span)),
+            ExprInstruction::Mod(lhs, rhs, span) => self.binary(lhs, rhs, Op::Mod(*span)),
+            ExprInstruction::Pow(lhs, rhs, span) => self.binary(lhs, rhs, Op::Pow(*span)),
+            ExprInstruction::BitAnd(lhs, rhs, span) => self.binary(lhs, rhs, Op::BitAnd(*span)),
+            ExprInstruction::BitOr(lhs, rhs, span) => self.binary(lhs, rhs, Op::BitOr(*span)),
+            ExprInstruction::BitXor(lhs, rhs, span) => self.binary(lhs, rhs, Op::BitXor(*span)),
+            ExprInstruction::BitNot(expr, span) => {
+                self.expr(expr);
+                self.emit(Op::BitNot(*span));
+            }
+            ExprInstruction::Shl(lhs, rhs, span) => self.binary(lhs, rhs, Op::Shl(*span)),
+            ExprInstruction::Shr(lhs, rhs, span) => self.binary(lhs, rhs, Op::Shr(*span)),
+            ExprInstruction::Eq(lhs, rhs) => self.binary(lhs, rhs, Op::Compare(Comparison::Eq)),
+            ExprInstruction::Ne(lhs, rhs) => self.binary(lhs, rhs, Op::Compare(Comparison::Ne)),
+            ExprInstruction::Lt(lhs, rhs) => self.binary(lhs, rhs, Op::Compare(Comparison::Lt)),
+            ExprInstruction::Leq(lhs, rhs) => self.binary(lhs, rhs, Op::Compare(Comparison::Leq)),
+            ExprInstruction::Gt(lhs, rhs) => self.binary(lhs, rhs, Op::Compare(Comparison::Gt)),
+            ExprInstruction::Geq(lhs, rhs) => self.binary(lhs, rhs, Op::Compare(Comparison::Geq)),
+            ExprInstruction::Not(expr) => {
+                self.expr(expr);
+                self.emit(Op::Not);
+            }
+            ExprInstruction::And(lhs, rhs) => self.boolean(lhs, rhs, false),
+            ExprInstruction::Or(lhs, rhs) => self.boolean(lhs, rhs, true),
+        }
+    }
+}
+
+/// Compiles the statements of `program`. Function definitions are compiled
+/// too, and the functions are defined when their `Op::Define` is executed.
+/// Execution stops after a statement that contains `quit`, even if it is
+/// not executed, and before the definition of a function that contains it.
+pub(crate) fn compile_program(globals: &mut GlobalNames, program: &Program) -> Code {
+    let mut compiler = Compiler::new(
+        globals,
+        program.source_locations.as_slice().into(),
+        program.file.clone(),
+    );
+    let mut index = 0;
+    for stmt in &program.instructions {
+        if let StmtInstruction::DefineFunction { name, function } = stmt {
+            if function.body.iter().any(contains_quit) {
+                compiler.emit(Op::Quit);
+                break;
+            }
+            let function = compile_function(compiler.globals, function);
+            compiler
+                .code
+                .functions
+                .push((name.clone(), Rc::new(function)));
+            compiler.emit(Op::Define(compiler.code.functions.len() - 1));
+        } else {
+            compiler.stmt(stmt, index);
+            index += statement_count(stmt);
+            if contains_quit(stmt) {
+                compiler.emit(Op::Quit);
+                break;
+            }
+        }
+    }
+    compiler.emit(Op::End);
+    compiler.code
+}
+
+pub(crate) fn compile_function(globals: &mut GlobalNames, function: &Function) -> CompiledFunction {
+    let mut compiler = Compiler::new(
+        globals,
+        function.source_locations.clone(),
+        function.file.clone(),
+    );
+    let parameters: Vec<_> = function
+        .parameters
+        .iter()
+        .map(|variable| compiler.declare(variable))
+        .collect();
+    let locals: Vec<_> = function
+        .locals
+        .iter()
+        .map(|variable| compiler.declare(variable))
+        .collect();
+    compiler.block(&function.body, 0);
+    // from the POSIX standard:
+    // > the value of the function shall be the value of the expression
+    // > in the parentheses of the return statement or shall be zero
+    // > if no expression is provided or if there is no return statement
+    compiler.emit(Op::ReturnZero);
+    CompiledFunction {
+        definition: function.clone(),
+        parameters,
+        locals,
+        variable_count: compiler.variables.len(),
+        array_count: compiler.arrays.len(),
+        code: Rc::new(compiler.code),
+    }
+}
+
+/// Compiles `expr` to code that returns its value.
+pub(crate) fn compile_expression(globals: &mut GlobalNames, expr: &ExprInstruction) -> Code {
+    let mut compiler = Compiler::new(globals, Rc::new([]), Rc::from(""));
+    compiler.expr(expr);
+    compiler.emit(Op::Return);
+    compiler.code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_program;
+
+    fn compile(text: &str) -> (Code, GlobalNames) {
+        let mut globals = GlobalNames::default();
+        let code = compile_program(&mut globals, &parse_program(text, None).unwrap());
+        (code, globals)
+    }
+
+    fn store_lines(code: &Code) -> Vec<usize> {
+        (0..code.ops.len())
+            .filter(|&pc| matches!(code.ops[pc], Op::Store(_)))
+            .map(|pc| code.line(pc))
+            .collect()
+    }
+
+    #[test]
+    fn test_operations_map_to_the_lines_of_their_statements() {
+        let (code, _) =
+            compile("x = 1\nwhile (x < 3) {\nx = x + 1\n}\nif (x) y = 1 else y = 2\nz = 3\n");
+        assert_eq!(store_lines(&code), [1, 3, 5, 5, 6]);
+        let condition = code
+            .ops
+            .iter()
+            .position(|op| matches!(op, Op::JumpUnless(Comparison::Lt, _)))
+            .unwrap();
+        assert_eq!(code.line(condition), 2);
+    }
+
+    #[test]
+    fn test_parameters_and_auto_variables_are_local() {
+        let (code, globals) = compile("define f(x, a[]) {\nauto y\ny = x + z + a[0]\n}\n");
+        let (name, function) = &code.functions[0];
+        assert_eq!(name.as_ref(), "f");
+        assert_eq!(
+            function.parameters,
+            [LocalVariable::Number(0), LocalVariable::Array(0)]
+        );
+        assert_eq!(function.locals, [LocalVariable::Number(1)]);
+        let loads: Vec<_> = function
+            .code
+            .ops
+            .iter()
+            .filter_map(|op| match op {
+                Op::Load(named) => Some(*named),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            loads,
+            [
+                Named::Variable(Slot::Local {
+                    index: 0,
+                    global: globals.variables["x"]
+                }),
+                Named::Variable(Slot::Global(globals.variables["z"])),
+                Named::Item(Slot::Local {
+                    index: 0,
+                    global: globals.arrays["a"]
+                }),
+            ]
+        );
+        assert_eq!(function.code.ops.last(), Some(&Op::ReturnZero));
+    }
+
+    #[test]
+    fn test_continue_in_for_jumps_to_the_update() {
+        let (code, _) = compile("for (i = 0; i < 3; ++i) { if (i == 1) continue; i }\n");
+        let update = code
+            .ops
+            .iter()
+            .position(|op| matches!(op, Op::PreIncrement(_)))
+            .unwrap();
+        assert!(code.ops.contains(&Op::Jump(update)));
+    }
+
+    #[test]
+    fn test_nothing_is_compiled_after_quit() {
+        let (code, _) = compile("1\nif (0) quit\n2\n");
+        assert_eq!(
+            code.ops.iter().filter(|op| **op == Op::PrintValue).count(),
+            1
+        );
+        assert_eq!(&code.ops[code.ops.len() - 2..], [Op::Quit, Op::End]);
+    }
+}
@@ -0,0 +1,94 @@
+//! Diagnostics that locate an error in the source text, and their
+//! rendering as the offending line underlined with `^~~~`.
+
+use crate::instructions::Span;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    /// line of the start of the span, starting from 1
+    pub line: usize,
+    /// column of the start of the span in characters, starting from 1
+    pub column: usize,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic for `span`, a part of `source`.
+    pub fn new(message: impl Into<String>, span: Span, source: &str) -> Self {
+        let start = span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        Diagnostic {
+            message: message.into(),
+            span,
+            line: source[..start].matches('\n').count() + 1,
+            column: source[line_start..start].chars().count() + 1,
+        }
+    }
+
+    /// Renders the message, followed by the line of `source` where the
+    /// span starts with the span underlined. A span that continues on
+    /// the next lines is underlined up to the end of its first line.
+    pub fn render(&self, source: &str) -> String {
+        let mut start = self.span.start.min(source.len());
+        if start == source.len() && source.ends_with('\n') {
+            // an error at the end of the input is shown at the end of the last line
+            start -= 1;
+        }
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line = &source[line_start..line_end];
+
+        // tabs are kept so that the underline lines up with the text
+        let padding: String = source[line_start..start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let end = self.span.end.clamp(start, line_end);
+        let width = source[start..end].chars().count().max(1);
+        format!(
+            "{}\n{line}\n{padding}^{}",
+            self.message,
+            "~".repeat(width - 1)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_and_column() {
+        let source = "x = 1\n  y = x / 0\n";
+        let diagnostic = Diagnostic::new("division by zero", Span::new(14, 15), source);
+        assert_eq!(diagnostic.line, 2);
+        assert_eq!(diagnostic.column, 9);
+    }
+
+    #[test]
+    fn test_render_underlines_span() {
+        let source = "1 + foo(2)\n";
+        let diagnostic = Diagnostic::new("undefined function", Span::new(4, 7), source);
+        assert_eq!(
+            diagnostic.render(source),
+            "undefined function\n1 + foo(2)\n    ^~~"
+        );
+    }
+
+    #[test]
+    fn test_render_empty_span() {
+        let source = "1 +\n";
+        let diagnostic = Diagnostic::new("expected expression", Span::new(4, 4), source);
+        assert_eq!(diagnostic.render(source), "expected expression\n1 +\n   ^");
+    }
+
+    #[test]
+    fn test_render_keeps_tabs_and_stops_at_end_of_line() {
+        let source = "a\n\tb = {\n}\n";
+        let diagnostic = Diagnostic::new("error", Span::new(7, 10), source);
+        assert_eq!(diagnostic.render(source), "error\n\tb = {\n\t    ^");
+    }
+}
@@ -0,0 +1,133 @@
+//! The errors of bc.
+
+use std::rc::Rc;
+
+use crate::number::Number;
+
+/// An error of a numeric operation or of the execution of a program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BcError {
+    DivisionByZero,
+    NonIntegerExponent(Number),
+    ExponentTooLarge(Number),
+    NegativeSquareRoot(Number),
+    NonPositiveLogarithm(Number),
+    ArrayIndexTooLarge(Number),
+    UndefinedFunction(Rc<str>),
+    /// the arguments of a call don't match the parameters of `function`
+    ArgumentMismatch {
+        function: Rc<str>,
+    },
+    /// `number` has a digit that is not valid in `ibase`
+    InvalidDigit {
+        number: String,
+        ibase: u64,
+    },
+    ScaleTooLarge(Number),
+    InvalidIBase(Number),
+    InvalidOBase(Number),
+    OBaseTooLarge(Number),
+    /// the argument of `irand` is negative or too large
+    InvalidRandomBound(Number),
+    InvalidSeed(Number),
+    /// a fractional operand of a bitwise operator
+    NonIntegerOperand(Number),
+    InvalidShift(Number),
+    /// an error returned by a function registered with
+    /// `Interpreter::register_function`, or by an output sink
+    Custom(String),
+
+    // the execution was stopped by one of the `ExecutionLimits`
+    StatementLimitExceeded,
+    TimeLimitExceeded,
+    Cancelled,
+    TooManyDigits,
+    CallDepthLimitExceeded,
+}
+
+impl std::fmt::Display for BcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let message = match self {
+            BcError::DivisionByZero => "division by zero",
+            BcError::NonIntegerExponent(_) => "exponent has to be an integer",
+            BcError::ExponentTooLarge(_) => "exponent is too large",
+            BcError::NegativeSquareRoot(_) => "square root of negative number",
+            BcError::NonPositiveLogarithm(_) => "logarithm of a non-positive number",
+            BcError::ArrayIndexTooLarge(_) => "array index is too large",
+            BcError::UndefinedFunction(_) => "undefined function",
+            BcError::ArgumentMismatch { .. } => "argument does not match parameter",
+            BcError::InvalidDigit { .. } => "invalid digit for the current ibase",
+            BcError::ScaleTooLarge(_) => "the value assigned to scale is too large",
+            BcError::InvalidIBase(_) => "ibase must be between 2 and 16",
+            BcError::InvalidOBase(_) => "obase must be greater than 1",
+            BcError::OBaseTooLarge(_) => "value assigned to obase is too large",
+            BcError::InvalidRandomBound(_) => {
+                "the argument of irand must be between 0 and 2^64 - 1"
+            }
+            BcError::InvalidSeed(_) => "seed must be between 0 and 2^64 - 1",
+            BcError::NonIntegerOperand(_) => "bitwise operators need integer operands",
+            BcError::InvalidShift(_) => "the shift amount must be between 0 and 2^32 - 1",
+            BcError::StatementLimitExceeded => "statement limit exceeded",
+            BcError::TimeLimitExceeded => "time limit exceeded",
+            BcError::Cancelled => "execution cancelled",
+            BcError::TooManyDigits => "number has too many digits",
+            BcError::CallDepthLimitExceeded => "call depth limit exceeded",
+            BcError::Custom(message) => message,
+        };
+        write!(f, "{message}")
+    }
+}
+
+impl std::error::Error for BcError {}
+
+/// An error of the embedding API: `Interpreter::eval` and
+/// the conversions between `Number` and Rust types.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalError {
+    /// the evaluated text has a syntax error, with this message
+    Syntax(String),
+    /// the evaluated text does not end with an expression
+    NotAnExpression,
+    /// the evaluated text executed `quit` or `halt`, so
+    /// the value of its last expression is not known
+    Halted,
+    /// the execution of the evaluated text failed
+    Execution(BcError),
+    /// a string or a `f64` that is not a number
+    InvalidNumber(String),
+    /// a number that is not an integer in the range of the target type
+    OutOfRange(Number),
+}
+
+impl From<BcError> for EvalError {
+    fn from(error: BcError) -> Self {
+        EvalError::Execution(error)
+    }
+}
+
+impl std::fmt::Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EvalError::Syntax(message) => write!(f, "{message}"),
+            EvalError::NotAnExpression => write!(f, "the last statement is not an expression"),
+            EvalError::Halted => write!(f, "the program halted"),
+            EvalError::Execution(error) => write!(f, "{error}"),
+            EvalError::InvalidNumber(_) => write!(f, "invalid number"),
+            EvalError::OutOfRange(_) => {
+                write!(
+                    f,
+                    "number is not an integer in the range of the target type"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EvalError::Execution(error) => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -9,7 +9,20 @@
 
 use std::rc::Rc;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// A part of the source text, as byte offsets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum BuiltinFunction {
     Length,
     Sqrt,
@@ -26,13 +39,18 @@ pub enum Register {
 #[derive(Clone, Debug, PartialEq)]
 pub enum StmtInstruction {
     Break,
+    Continue,
     Quit,
+    Halt,
+    Limits,
     Return,
     ReturnExpr(ExprInstruction),
     If {
         condition: ConditionInstruction,
         instruction_count: usize,
         body: Vec<StmtInstruction>,
+        else_instruction_count: usize,
+        else_body: Vec<StmtInstruction>,
     },
     While {
         condition: ConditionInstruction,
@@ -47,26 +65,36 @@ pub enum StmtInstruction {
         body: Vec<StmtInstruction>,
     },
     String(String),
+    Print(Vec<PrintItem>),
     Expr(ExprInstruction),
     DefineFunction {
-        name: char,
+        name: Rc<str>,
         function: Function,
     },
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum NamedExpr {
-    VariableNumber(char),
+    VariableNumber(Rc<str>),
+    Last,
     ArrayItem {
-        name: char,
+        name: Rc<str>,
         index: Box<ExprInstruction>,
     },
 }
 
+/// An item of a `print` statement. Escape sequences in
+/// strings have already been replaced by the parser.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PrintItem {
+    String(String),
+    Expr(ExprInstruction),
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum FunctionArgument {
     Expr(ExprInstruction),
-    ArrayVariable(char),
+    ArrayVariable(Rc<str>),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -82,9 +110,12 @@ pub enum ExprInstruction {
     PreDecrement(NamedExpr),
     PostIncrement(NamedExpr),
     PostDecrement(NamedExpr),
+    // `span` is the name of the function, errors of
+    // the call are reported there
     Call {
-        name: char,
+        name: Rc<str>,
         args: Vec<FunctionArgument>,
+        span: Span,
     },
     Assignment {
         named: NamedExpr,
@@ -98,9 +129,29 @@ pub enum ExprInstruction {
     Add(Box<ExprInstruction>, Box<ExprInstruction>),
     Sub(Box<ExprInstruction>, Box<ExprInstruction>),
     Mul(Box<ExprInstruction>, Box<ExprInstruction>),
-    Div(Box<ExprInstruction>, Box<ExprInstruction>),
-    Mod(Box<ExprInstruction>, Box<ExprInstruction>),
-    Pow(Box<ExprInstruction>, Box<ExprInstruction>),
+    // the operators that can fail keep the span of the operator
+    Div(Box<ExprInstruction>, Box<ExprInstruction>, Span),
+    Mod(Box<ExprInstruction>, Box<ExprInstruction>, Span),
+    Pow(Box<ExprInstruction>, Box<ExprInstruction>, Span),
+    // relational and boolean operators evaluate to 0 or 1,
+    // `And` and `Or` don't evaluate their right operand if
+    // the left one decides the result
+    Eq(Box<ExprInstruction>, Box<ExprInstruction>),
+    Ne(Box<ExprInstruction>, Box<ExprInstruction>),
+    Lt(Box<ExprInstruction>, Box<ExprInstruction>),
+    Leq(Box<ExprInstruction>, Box<ExprInstruction>),
+    Gt(Box<ExprInstruction>, Box<ExprInstruction>),
+    Geq(Box<ExprInstruction>, Box<ExprInstruction>),
+    Not(Box<ExprInstruction>),
+    And(Box<ExprInstruction>, Box<ExprInstruction>),
+    Or(Box<ExprInstruction>, Box<ExprInstruction>),
+    // bitwise operators, only parsed when enabled in `ParseOptions`
+    BitAnd(Box<ExprInstruction>, Box<ExprInstruction>, Span),
+    BitOr(Box<ExprInstruction>, Box<ExprInstruction>, Span),
+    BitXor(Box<ExprInstruction>, Box<ExprInstruction>, Span),
+    BitNot(Box<ExprInstruction>, Span),
+    Shl(Box<ExprInstruction>, Box<ExprInstruction>, Span),
+    Shr(Box<ExprInstruction>, Box<ExprInstruction>, Span),
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -116,8 +167,8 @@ pub enum ConditionInstruction {
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Variable {
-    Number(char),
-    Array(char),
+    Number(Rc<str>),
+    Array(Rc<str>),
 }
 
 /// A bc function.  
@@ -126,23 +177,26 @@ pub enum Variable {
 /// described in the documentation for `Program`.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Function {
-    pub name: char,
+    pub name: Rc<str>,
     pub parameters: Rc<[Variable]>,
     pub locals: Rc<[Variable]>,
     pub body: Rc<[StmtInstruction]>,
     pub source_locations: Rc<[usize]>,
     pub file: Rc<str>,
+    /// line of the `define` keyword
+    pub line: usize,
 }
 
 impl Default for Function {
     fn default() -> Self {
         Function {
-            name: '\0',
+            name: Rc::from(""),
             parameters: Rc::new([]),
             locals: Rc::new([]),
             body: Rc::new([]),
             source_locations: Rc::new([]),
             file: Rc::from(""),
+            line: 0,
         }
     }
 }
@@ -173,3 +227,305 @@ impl From<Vec<StmtInstruction>> for Program {
         }
     }
 }
+
+// The following implementations print instructions as bc source code, which
+// when parsed produces the same instructions. Compound assignments are printed
+// in their expanded form (`a += 1` is printed as `a = a + 1`).
+
+impl std::fmt::Display for Register {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Register::IBase => write!(f, "ibase"),
+            Register::OBase => write!(f, "obase"),
+            Register::Scale => write!(f, "scale"),
+        }
+    }
+}
+
+impl std::fmt::Display for BuiltinFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BuiltinFunction::Length => write!(f, "length"),
+            BuiltinFunction::Sqrt => write!(f, "sqrt"),
+            BuiltinFunction::Scale => write!(f, "scale"),
+        }
+    }
+}
+
+impl std::fmt::Display for NamedExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NamedExpr::VariableNumber(name) => write!(f, "{name}"),
+            NamedExpr::Last => write!(f, "last"),
+            NamedExpr::ArrayItem { name, index } => write!(f, "{name}[{index}]"),
+        }
+    }
+}
+
+impl std::fmt::Display for Variable {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Variable::Number(name) => write!(f, "{name}"),
+            Variable::Array(name) => write!(f, "{name}[]"),
+        }
+    }
+}
+
+impl std::fmt::Display for FunctionArgument {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FunctionArgument::Expr(expr) => write!(f, "{expr}"),
+            FunctionArgument::ArrayVariable(name) => write!(f, "{name}[]"),
+        }
+    }
+}
+
+impl std::fmt::Display for PrintItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PrintItem::String(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '"' => write!(f, "\\\"")?,
+                        c => write!(f, "{c}")?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            PrintItem::Expr(expr) => write!(f, "{expr}"),
+        }
+    }
+}
+
+impl ExprInstruction {
+    /// binding strength of the expression, used to decide where
+    /// parentheses are needed when printing it
+    fn precedence(&self) -> u8 {
+        match self {
+            ExprInstruction::Assignment { .. } | ExprInstruction::SetRegister { .. } => 0,
+            ExprInstruction::Or(..) => 1,
+            ExprInstruction::And(..) => 2,
+            ExprInstruction::Not(..) => 3,
+            ExprInstruction::Eq(..)
+            | ExprInstruction::Ne(..)
+            | ExprInstruction::Lt(..)
+            | ExprInstruction::Leq(..)
+            | ExprInstruction::Gt(..)
+            | ExprInstruction::Geq(..) => 4,
+            ExprInstruction::BitOr(..) => 5,
+            ExprInstruction::BitXor(..) => 6,
+            ExprInstruction::BitAnd(..) => 7,
+            ExprInstruction::Shl(..) | ExprInstruction::Shr(..) => 8,
+            ExprInstruction::Add(..) | ExprInstruction::Sub(..) => 9,
+            ExprInstruction::Mul(..) | ExprInstruction::Div(..) | ExprInstruction::Mod(..) => 10,
+            ExprInstruction::Pow(..) => 11,
+            _ => 12,
+        }
+    }
+
+    fn fmt_operand(&self, f: &mut std::fmt::Formatter, min_precedence: u8) -> std::fmt::Result {
+        if self.precedence() < min_precedence {
+            write!(f, "({self})")
+        } else {
+            write!(f, "{self}")
+        }
+    }
+
+    fn fmt_binary(
+        f: &mut std::fmt::Formatter,
+        lhs: &ExprInstruction,
+        op: &str,
+        rhs: &ExprInstruction,
+        precedence: u8,
+        right_associative: bool,
+    ) -> std::fmt::Result {
+        let (lhs_min, rhs_min) = if right_associative {
+            (precedence + 1, precedence)
+        } else {
+            (precedence, precedence + 1)
+        };
+        lhs.fmt_operand(f, lhs_min)?;
+        write!(f, " {op} ")?;
+        rhs.fmt_operand(f, rhs_min)
+    }
+}
+
+impl std::fmt::Display for ExprInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ExprInstruction::Number(n) => write!(f, "{n}"),
+            ExprInstruction::Named(named) => write!(f, "{named}"),
+            ExprInstruction::GetRegister(register) => write!(f, "{register}"),
+            ExprInstruction::Builtin { function, arg } => write!(f, "{function}({arg})"),
+            ExprInstruction::PreIncrement(named) => write!(f, "++{named}"),
+            ExprInstruction::PreDecrement(named) => write!(f, "--{named}"),
+            ExprInstruction::PostIncrement(named) => write!(f, "{named}++"),
+            ExprInstruction::PostDecrement(named) => write!(f, "{named}--"),
+            ExprInstruction::Call { name, args, .. } => {
+                write!(f, "{name}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{arg}")?;
+                }
+                write!(f, ")")
+            }
+            ExprInstruction::Assignment { named, value } => write!(f, "{named} = {value}"),
+            ExprInstruction::SetRegister { register, value } => {
+                write!(f, "{register} = {value}")
+            }
+            ExprInstruction::UnaryMinus(expr) => {
+                write!(f, "-")?;
+                expr.fmt_operand(f, 12)
+            }
+            ExprInstruction::Add(lhs, rhs) => Self::fmt_binary(f, lhs, "+", rhs, 9, false),
+            ExprInstruction::Sub(lhs, rhs) => Self::fmt_binary(f, lhs, "-", rhs, 9, false),
+            ExprInstruction::Mul(lhs, rhs) => Self::fmt_binary(f, lhs, "*", rhs, 10, false),
+            ExprInstruction::Div(lhs, rhs, _) => Self::fmt_binary(f, lhs, "/", rhs, 10, false),
+            ExprInstruction::Mod(lhs, rhs, _) => Self::fmt_binary(f, lhs, "%", rhs, 10, false),
+            ExprInstruction::Pow(lhs, rhs, _) => Self::fmt_binary(f, lhs, "^", rhs, 11, true),
+            ExprInstruction::Eq(lhs, rhs) => Self::fmt_binary(f, lhs, "==", rhs, 4, false),
+            ExprInstruction::Ne(lhs, rhs) => Self::fmt_binary(f, lhs, "!=", rhs, 4, false),
+            ExprInstruction::Lt(lhs, rhs) => Self::fmt_binary(f, lhs, "<", rhs, 4, false),
+            ExprInstruction::Leq(lhs, rhs) => Self::fmt_binary(f, lhs, "<=", rhs, 4, false),
+            ExprInstruction::Gt(lhs, rhs) => Self::fmt_binary(f, lhs, ">", rhs, 4, false),
+            ExprInstruction::Geq(lhs, rhs) => Self::fmt_binary(f, lhs, ">=", rhs, 4, false),
+            ExprInstruction::Not(expr) => {
+                write!(f, "!")?;
+                expr.fmt_operand(f, 3)
+            }
+            ExprInstruction::And(lhs, rhs) => Self::fmt_binary(f, lhs, "&&", rhs, 2, false),
+            ExprInstruction::Or(lhs, rhs) => Self::fmt_binary(f, lhs, "||", rhs, 1, false),
+            ExprInstruction::BitOr(lhs, rhs, _) => Self::fmt_binary(f, lhs, "|", rhs, 5, false),
+            ExprInstruction::BitXor(lhs, rhs, _) => Self::fmt_binary(f, lhs, "^^", rhs, 6, false),
+            ExprInstruction::BitAnd(lhs, rhs, _) => Self::fmt_binary(f, lhs, "&", rhs, 7, false),
+            ExprInstruction::Shl(lhs, rhs, _) => Self::fmt_binary(f, lhs, "<<", rhs, 8, false),
+            ExprInstruction::Shr(lhs, rhs, _) => Self::fmt_binary(f, lhs, ">>", rhs, 8, false),
+            ExprInstruction::BitNot(expr, _) => {
+                write!(f, "~")?;
+                expr.fmt_operand(f, 12)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ConditionInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConditionInstruction::Expr(expr) => write!(f, "{expr}"),
+            ConditionInstruction::Eq(lhs, rhs) => write!(f, "{lhs} == {rhs}"),
+            ConditionInstruction::Ne(lhs, rhs) => write!(f, "{lhs} != {rhs}"),
+            ConditionInstruction::Lt(lhs, rhs) => write!(f, "{lhs} < {rhs}"),
+            ConditionInstruction::Leq(lhs, rhs) => write!(f, "{lhs} <= {rhs}"),
+            ConditionInstruction::Gt(lhs, rhs) => write!(f, "{lhs} > {rhs}"),
+            ConditionInstruction::Geq(lhs, rhs) => write!(f, "{lhs} >= {rhs}"),
+        }
+    }
+}
+
+fn fmt_body(
+    f: &mut std::fmt::Formatter,
+    body: &[StmtInstruction],
+    indent: usize,
+) -> std::fmt::Result {
+    writeln!(f, "{{")?;
+    for stmt in body {
+        stmt.fmt_indented(f, indent + 1)?;
+    }
+    write!(f, "{:width$}}}", "", width = indent * 4)
+}
+
+impl StmtInstruction {
+    fn fmt_indented(&self, f: &mut std::fmt::Formatter, indent: usize) -> std::fmt::Result {
+        write!(f, "{:width$}", "", width = indent * 4)?;
+        match self {
+            StmtInstruction::Break => write!(f, "break")?,
+            StmtInstruction::Continue => write!(f, "continue")?,
+            StmtInstruction::Quit => write!(f, "quit")?,
+            StmtInstruction::Halt => write!(f, "halt")?,
+            StmtInstruction::Limits => write!(f, "limits")?,
+            StmtInstruction::Return => write!(f, "return")?,
+            StmtInstruction::ReturnExpr(expr) => write!(f, "return ({expr})")?,
+            StmtInstruction::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                write!(f, "if ({condition}) ")?;
+                fmt_body(f, body, indent)?;
+                if !else_body.is_empty() {
+                    write!(f, " else ")?;
+                    fmt_body(f, else_body, indent)?;
+                }
+            }
+            StmtInstruction::While {
+                condition, body, ..
+            } => {
+                write!(f, "while ({condition}) ")?;
+                fmt_body(f, body, indent)?;
+            }
+            StmtInstruction::For {
+                init,
+                condition,
+                update,
+                body,
+                ..
+            } => {
+                write!(f, "for ({init}; {condition}; {update}) ")?;
+                fmt_body(f, body, indent)?;
+            }
+            StmtInstruction::String(s) => write!(f, "\"{s}\"")?,
+            StmtInstruction::Print(items) => {
+                write!(f, "print ")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+            }
+            StmtInstruction::Expr(expr) => write!(f, "{expr}")?,
+            StmtInstruction::DefineFunction { function, .. } => write!(f, "{function}")?,
+        }
+        writeln!(f)
+    }
+}
+
+impl std::fmt::Display for StmtInstruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+impl std::fmt::Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "define {}(", self.name)?;
+        for (i, param) in self.parameters.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{param}")?;
+        }
+        writeln!(f, ") {{")?;
+        if !self.locals.is_empty() {
+            write!(f, "    auto ")?;
+            for (i, local) in self.locals.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{local}")?;
+            }
+            writeln!(f)?;
+        }
+        for stmt in self.body.iter() {
+            stmt.fmt_indented(f, 1)?;
+        }
+        write!(f, "}}")
+    }
+}
@@ -7,34 +7,73 @@
 // SPDX-License-Identifier: MIT
 //
 
-use std::{fmt::Write, rc::Rc};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    ops::Range,
+    path::Path,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use crate::instructions::Variable;
+use crate::instructions::Span;
 
 use super::{
-    instructions::{
-        BuiltinFunction, ConditionInstruction, ExprInstruction, Function, FunctionArgument,
-        NamedExpr, Program, Register, StmtInstruction,
+    bytecode::{
+        compile_expression, compile_program, CallSite, Code, CompiledFunction, GlobalNames,
+        LocalVariable, Named, Op, Slot,
     },
-    number::Number,
+    diagnostic::Diagnostic,
+    error::{BcError, EvalError},
+    instructions::{BuiltinFunction, Program, Register, StmtInstruction},
+    number::{wrap_lines, Number, NumericResult, ScientificFormat},
+    output::OutputSink,
+    parser::{parse_program, parse_program_with_options, ParseError, ParseOptions},
+    random::Random,
 };
 
 #[derive(Debug)]
 struct ErrorCall {
-    function_name: char,
+    // empty for the global scope
+    function_name: Rc<str>,
     line: usize,
     file: Rc<str>,
 }
 
 #[derive(Debug)]
 pub struct ExecutionError {
-    message: &'static str,
+    // boxed, to keep results with an `ExecutionError` small
+    error: Box<BcError>,
     call_stack: Vec<ErrorCall>,
+    // where the error occurred in the source of the executed program,
+    // for errors of an operation that keeps its span
+    span: Option<Span>,
     partial_output: String,
+    aborted: bool,
 }
 
 impl ExecutionError {
-    fn add_call(mut self, function_name: char, line: usize, file: Rc<str>) -> Self {
+    fn aborted(error: BcError) -> Self {
+        Self {
+            aborted: true,
+            ..Self::from(error)
+        }
+    }
+
+    /// sets the span of the error, unless an inner operation already set it
+    fn at(mut self, span: Span) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+
+    fn add_call(mut self, function_name: Rc<str>, line: usize, file: Rc<str>) -> Self {
+        // a span inside of the function body refers to the text where the
+        // function was defined, the error is reported at the call instead
+        self.span = None;
         self.call_stack.push(ErrorCall {
             function_name,
             line,
@@ -45,24 +84,51 @@ impl ExecutionError {
 
     fn global_source(mut self, line: usize, file: Rc<str>) -> Self {
         self.call_stack.push(ErrorCall {
-            function_name: '\0',
+            function_name: Rc::from(""),
             line,
             file,
         });
         self
     }
 
+    /// The part of the source of the executed program where the error
+    /// occurred. For an error inside of a function this is the call of
+    /// the function, `None` if the error cannot be located.
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// Returns the diagnostic of the error, `source` is the
+    /// text from which the executed program was parsed.
+    pub fn diagnostic(&self, source: &str) -> Option<Diagnostic> {
+        self.span
+            .map(|span| Diagnostic::new(self.error.to_string(), span, source))
+    }
+
+    pub fn error(&self) -> &BcError {
+        &self.error
+    }
+
     pub fn partial_output(&self) -> &str {
         &self.partial_output
     }
+
+    /// Returns true if execution was stopped because it hit one of
+    /// the `ExecutionLimits` of the interpreter, rather than because
+    /// of an error in the program.
+    pub fn is_aborted(&self) -> bool {
+        self.aborted
+    }
 }
 
-impl From<&'static str> for ExecutionError {
-    fn from(message: &'static str) -> Self {
+impl From<BcError> for ExecutionError {
+    fn from(error: BcError) -> Self {
         Self {
-            message,
+            error: Box::new(error),
             call_stack: Vec::new(),
+            span: None,
             partial_output: String::new(),
+            aborted: false,
         }
     }
 }
@@ -73,15 +139,15 @@ impl std::fmt::Display for ExecutionError {
             let line = self.call_stack[0].line;
             let file = &self.call_stack[0].file;
             if file.is_empty() {
-                return write!(f, "runtime error (line {line}): {}", self.message);
+                return write!(f, "runtime error (line {line}): {}", self.error);
             }
-            return write!(f, "runtime error ({file} - line {line}): {}", self.message);
+            return write!(f, "runtime error ({file} - line {line}): {}", self.error);
         }
 
-        writeln!(f, "runtime error: {}", self.message)?;
+        writeln!(f, "runtime error: {}", self.error)?;
         writeln!(f, "call trace:",)?;
         for call in &self.call_stack {
-            let function_name = if call.function_name == '\0' {
+            let function_name = if call.function_name.is_empty() {
                 "<global scope>".to_string()
             } else {
                 format!("'{}'", call.function_name)
@@ -100,32 +166,79 @@ impl std::fmt::Display for ExecutionError {
     }
 }
 
-pub type ExecutionResult<T> = Result<T, ExecutionError>;
+impl std::error::Error for ExecutionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
 
-type NameMap<T> = [T; 26];
+pub type ExecutionResult<T> = Result<T, ExecutionError>;
 
-fn name_index(name: char) -> usize {
-    (name as u8 - b'a') as usize
+/// Error returned when loading a bc file into an interpreter
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Parse(ParseError),
+    Execution(ExecutionError),
 }
 
-fn contains_quit(stmt: &StmtInstruction) -> bool {
-    match stmt {
-        StmtInstruction::Quit => true,
-        StmtInstruction::If { body, .. } => body.iter().any(contains_quit),
-        StmtInstruction::While { body, .. } => body.iter().any(contains_quit),
-        _ => false,
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{e}"),
+            LoadError::Parse(e) => write!(f, "{e}"),
+            LoadError::Execution(e) => write!(f, "{e}"),
+        }
     }
 }
 
-fn should_print(expr: &ExprInstruction) -> bool {
-    // assignments should not be printed:
-    // https://pubs.opengroup.org/onlinepubs/9699919799/utilities/bc.html#tag_20_09_10
-    !matches!(
-        expr,
-        ExprInstruction::Assignment { .. } | ExprInstruction::SetRegister { .. }
-    )
+impl std::error::Error for LoadError {}
+
+type NameMap<T> = HashMap<Rc<str>, T>;
+
+/// largest value of `obase`, the digits of the output are `u64`
+pub const BC_BASE_MAX: u64 = u64::MAX;
+/// largest array index
+pub const BC_DIM_MAX: u64 = usize::MAX as u64;
+/// largest value of `scale` when `ExecutionLimits::max_digits` is not set,
+/// larger scales are only bounded by the memory
+pub const BC_SCALE_MAX: u64 = u64::MAX;
+/// longest string
+pub const BC_STRING_MAX: u64 = usize::MAX as u64;
+/// largest value returned by `rand()`
+pub const BC_RAND_MAX: u64 = u32::MAX as u64;
+/// length of the lines of printed numbers, see `Interpreter::set_line_length`
+pub const DEFAULT_LINE_LENGTH: usize = 70;
+
+const MATH_LIBRARY_SOURCE: &str = include_str!("math_functions.bc");
+const MATH_LIBRARY_FILE: &str = "math_functions.bc";
+
+thread_local! {
+    // the library is parsed once per thread, and then shared between all
+    // interpreters created with `Interpreter::with_math_library`
+    static MATH_LIBRARY: Program = parse_program(MATH_LIBRARY_SOURCE, Some(MATH_LIBRARY_FILE))
+        .expect("error parsing the math library");
 }
 
+/// math library functions computed natively,
+/// instead of running their bc definitions
+#[derive(Clone, Copy, Debug)]
+enum MathFunction {
+    Sin,
+    Cos,
+    Atan,
+    Ln,
+    Exp,
+}
+
+const NATIVE_MATH_FUNCTIONS: [(&str, MathFunction); 5] = [
+    ("s", MathFunction::Sin),
+    ("c", MathFunction::Cos),
+    ("a", MathFunction::Atan),
+    ("l", MathFunction::Ln),
+    ("e", MathFunction::Exp),
+];
+
 fn get_or_extend(array: &mut Vec<Number>, index: usize) -> &mut Number {
     if index >= array.len() {
         array.resize_with(index + 1, Number::zero);
@@ -133,31 +246,118 @@ fn get_or_extend(array: &mut Vec<Number>, index: usize) -> &mut Number {
     &mut array[index]
 }
 
+/// parameters and `auto` variables of all the active calls,
+/// `None` for parameters without an argument
+#[derive(Default)]
+struct Locals {
+    variables: Vec<Option<Number>>,
+    arrays: Vec<Option<Vec<Number>>>,
+}
+
+/// the execution of the global scope or of a function call
+struct Frame {
+    // `None` for the global scope
+    function: Option<Rc<CompiledFunction>>,
+    code: Rc<Code>,
+    pc: usize,
+    // start of the parameters and `auto` variables of the function in `Locals`
+    variables: usize,
+    arrays: usize,
+}
+
+/// the functions of the random number generator, they are called
+/// like functions only when no function of the same name is defined
+#[derive(Clone, Copy)]
+enum RandomFunction {
+    Rand,
+    IRand,
+    Seed,
+}
+
+enum Callee {
+    Host(HostFunction),
+    Native(MathFunction),
+    Defined(Rc<CompiledFunction>),
+    Random(RandomFunction),
+}
+
+/// a call whose function has been resolved, and whose
+/// arguments are being evaluated
+struct PendingCall {
+    callee: Callee,
+    // sizes of the value stack and of the array arguments before the arguments
+    stack: usize,
+    arrays: usize,
+}
+
+/// the state of the stack machine that executes compiled code
 #[derive(Default)]
-struct CallFrame {
-    variables: NameMap<Option<Number>>,
-    array_variables: NameMap<Option<Vec<Number>>>,
+struct Machine {
+    stack: Vec<Number>,
+    locals: Locals,
+    // arrays passed to the functions whose arguments are being evaluated
+    array_arguments: Vec<Vec<Number>>,
+    calls: Vec<PendingCall>,
+    // the frames of the callers of the current frame
+    callers: Vec<Frame>,
+}
+
+fn pop(stack: &mut Vec<Number>) -> Number {
+    stack
+        .pop()
+        .expect("the compiler ensures that operands are on the stack")
 }
 
-#[derive(Debug, PartialEq)]
-enum ControlFlow {
-    Return(Number),
-    Quit,
-    Break,
-    None,
+/// A function implemented in Rust, see `Interpreter::register_function`.
+pub type HostFunction = Rc<dyn Fn(&[Number]) -> NumericResult>;
+
+/// Limits on the resources a program can use. Hitting any of them stops
+/// execution with an error for which `ExecutionError::is_aborted` is true.
+/// `None` means no limit, which is the default.
+#[derive(Clone, Debug, Default)]
+pub struct ExecutionLimits {
+    /// maximum number of statements executed by a single call to `exec`.
+    /// Every evaluation of a loop condition counts as a statement.
+    pub max_statements: Option<u64>,
+    /// maximum depth of nested function calls
+    pub max_call_depth: Option<usize>,
+    /// maximum number of digits in the result of an arithmetic operation
+    pub max_digits: Option<u64>,
+    /// maximum wall-clock time for a single call to `exec`
+    pub timeout: Option<Duration>,
+    /// when set to true by another thread, execution stops as soon as possible
+    pub cancel: Option<Arc<AtomicBool>>,
 }
 
 pub struct Interpreter {
-    variables: NameMap<Number>,
-    array_variables: NameMap<Vec<Number>>,
-    functions: NameMap<Function>,
-    call_frames: Vec<CallFrame>,
+    // global variables and arrays, indexed as in `global_names`
+    variables: Vec<Number>,
+    array_variables: Vec<Vec<Number>>,
+    global_names: GlobalNames,
+    functions: NameMap<Rc<CompiledFunction>>,
+    // math library functions that have not been redefined
+    native_functions: NameMap<MathFunction>,
+    // functions registered by the embedder, they take precedence over `define`
+    host_functions: NameMap<HostFunction>,
+    // the last value printed by an expression statement
+    last: Number,
     scale: u64,
     ibase: u64,
     obase: u64,
     output: String,
+    // the length of the output of the current `exec`, and where the
+    // last value of an expression statement is in it
+    output_length: usize,
+    last_value_output: Option<Range<usize>>,
     has_quit: bool,
-    instruction_counter: usize,
+    limits: ExecutionLimits,
+    executed_statements: u64,
+    deadline: Option<Instant>,
+    // generator of `rand()` and `irand(n)`, its state is read and set with `seed()`
+    random: Random,
+    scientific_output: Option<ScientificFormat>,
+    output_sink: Option<Box<dyn OutputSink>>,
+    line_length: usize,
 }
 
 impl Default for Interpreter {
@@ -165,417 +365,965 @@ impl Default for Interpreter {
         Self {
             variables: Default::default(),
             array_variables: Default::default(),
+            global_names: Default::default(),
             functions: Default::default(),
-            call_frames: Vec::new(),
+            native_functions: Default::default(),
+            host_functions: Default::default(),
+            last: Number::zero(),
             scale: 0,
             ibase: 10,
             obase: 10,
             output: String::new(),
+            output_length: 0,
+            last_value_output: None,
             has_quit: false,
-            instruction_counter: 0,
+            limits: ExecutionLimits::default(),
+            executed_statements: 0,
+            deadline: None,
+            random: Random::from_os(),
+            scientific_output: None,
+            output_sink: None,
+            line_length: DEFAULT_LINE_LENGTH,
         }
     }
 }
 
 impl Interpreter {
+    /// Creates an interpreter with the math library (`s`, `c`, `a`, `l`, `e`
+    /// and `j`) already loaded, the same as running `bc -l`.
+    /// `s`, `c`, `a`, `l` and `e` are computed natively, unless redefined.
+    pub fn with_math_library() -> Self {
+        let mut interpreter = Self::default();
+        let code = MATH_LIBRARY
+            .with(|library| interpreter.compile(|names| compile_program(names, library)));
+        interpreter
+            .run(code)
+            .expect("error executing the math library");
+        for (name, function) in NATIVE_MATH_FUNCTIONS {
+            interpreter.native_functions.insert(name.into(), function);
+        }
+        interpreter
+    }
+
+    /// The base of the numbers in the programs, the `ibase` register.
+    pub fn ibase(&self) -> u64 {
+        self.ibase
+    }
+
+    pub fn limits(&self) -> &ExecutionLimits {
+        &self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: ExecutionLimits) {
+        self.limits = limits;
+    }
+
+    /// Sends the output of the programs to `sink` as soon as it is printed,
+    /// instead of returning it from `exec` when the program ends. With `None`
+    /// the output is returned by `exec` again.
+    pub fn set_output_sink(&mut self, sink: Option<Box<dyn OutputSink>>) {
+        self.output_sink = sink;
+    }
+
+    pub fn line_length(&self) -> usize {
+        self.line_length
+    }
+
+    /// Sets the length of the lines of printed numbers, longer numbers are
+    /// split with a backslash at the end of each line, see `wrap_lines`.
+    /// 0 means no wrapping. The default is 70, as in POSIX bc.
+    pub fn set_line_length(&mut self, line_length: usize) {
+        self.line_length = line_length;
+    }
+
+    pub fn scientific_output(&self) -> Option<&ScientificFormat> {
+        self.scientific_output.as_ref()
+    }
+
+    /// When set, numbers printed with `obase` 10 are written in scientific or
+    /// engineering notation if they are very large or very small, as with
+    /// `%g` in C: if their exponent is below -4, or at least the number of
+    /// significant digits.
+    pub fn set_scientific_output(&mut self, format: Option<ScientificFormat>) {
+        self.scientific_output = format;
+    }
+
+    /// Registers `function` as the implementation of the calls to `name`,
+    /// so that programs can use functions implemented in Rust.
+    /// It replaces a function defined with `define`, and a later `define`
+    /// of `name` replaces it. The arguments must be numbers, the errors
+    /// returned by `function` are runtime errors of the program.
+    pub fn register_function<F>(&mut self, name: &str, function: F)
+    where
+        F: Fn(&[Number]) -> NumericResult + 'static,
+    {
+        self.host_functions.insert(name.into(), Rc::new(function));
+    }
+
+    /// Removes the function registered as `name`.
+    /// # Returns
+    /// true if there was such a function
+    pub fn unregister_function(&mut self, name: &str) -> bool {
+        self.host_functions.remove(name).is_some()
+    }
+
+    /// counts one executed statement, and checks the limits
+    /// that are not tied to a specific operation
+    fn tick(&mut self) -> ExecutionResult<()> {
+        self.executed_statements += 1;
+        if let Some(max_statements) = self.limits.max_statements {
+            if self.executed_statements > max_statements {
+                return Err(ExecutionError::aborted(BcError::StatementLimitExceeded));
+            }
+        }
+        self.check_interrupt().map_err(ExecutionError::aborted)
+    }
+
+    /// checks the timeout and the cancel flag, also
+    /// during long computations of the math functions
+    fn check_interrupt(&self) -> Result<(), BcError> {
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Err(BcError::TimeLimitExceeded);
+            }
+        }
+        if let Some(cancel) = &self.limits.cancel {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(BcError::Cancelled);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_digits(&self, digits: u64) -> ExecutionResult<()> {
+        match self.limits.max_digits {
+            Some(max_digits) if digits > max_digits => {
+                Err(ExecutionError::aborted(BcError::TooManyDigits))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn check_number(&self, value: Number) -> ExecutionResult<Number> {
+        self.check_digits(value.digit_count())?;
+        Ok(value)
+    }
+
+    /// Writes `value` as the programs print it, in `obase` and with the
+    /// scientific output, but without splitting it into lines.
+    pub fn format_number(&self, value: &Number) -> String {
+        if let (Some(format), 10) = (&self.scientific_output, self.obase) {
+            let is_large_or_small = value
+                .decimal_exponent()
+                .is_some_and(|e| e < -4 || e >= format.significant_digits as i64);
+            if is_large_or_small {
+                return value.to_scientific_string(format);
+            }
+        }
+        value.to_string_radix(self.obase)
+    }
+
+    /// the text of a printed number, split into lines of `line_length`
+    fn number_text(&self, value: &Number) -> String {
+        wrap_lines(&self.format_number(value), self.line_length)
+    }
+
+    /// Writes `text` to the output sink, or appends it to
+    /// the output returned by `exec` if there is none.
+    fn print(&mut self, text: &str) -> ExecutionResult<()> {
+        self.output_length += text.len();
+        match &mut self.output_sink {
+            Some(sink) => sink.write_str(text).map_err(ExecutionError::aborted),
+            None => {
+                self.output.push_str(text);
+                Ok(())
+            }
+        }
+    }
+
     fn take_and_clear_output(&mut self) -> String {
         let mut string = String::new();
         std::mem::swap(&mut self.output, &mut string);
         string
     }
 
-    fn eval_named(&mut self, named: &NamedExpr) -> ExecutionResult<&mut Number> {
+    /// compiles with the names of the global variables of the
+    /// interpreter, and allocates the variables that are new
+    fn compile(&mut self, compile: impl FnOnce(&mut GlobalNames) -> Code) -> Rc<Code> {
+        let code = compile(&mut self.global_names);
+        self.variables
+            .resize_with(self.global_names.variables.len(), Number::zero);
+        self.array_variables
+            .resize_with(self.global_names.arrays.len(), Vec::new);
+        Rc::new(code)
+    }
+
+    fn variable<'a>(
+        &'a mut self,
+        locals: &'a mut Locals,
+        frame: &Frame,
+        stack: &mut Vec<Number>,
+        named: Named,
+    ) -> ExecutionResult<&'a mut Number> {
         match named {
-            NamedExpr::VariableNumber(c) => {
-                if let Some(call_frame) = self.call_frames.last_mut() {
-                    if let Some(value) = &mut call_frame.variables[name_index(*c)] {
-                        return Ok(value);
-                    }
+            Named::Variable(Slot::Global(global)) => Ok(&mut self.variables[global]),
+            Named::Variable(Slot::Local { index, global }) => {
+                match &mut locals.variables[frame.variables + index] {
+                    Some(value) => Ok(value),
+                    None => Ok(&mut self.variables[global]),
                 }
-                Ok(&mut self.variables[name_index(*c)])
             }
-            NamedExpr::ArrayItem { name, index } => {
-                let index = self
-                    .eval_expr(index)?
+            Named::Last => Ok(&mut self.last),
+            Named::Item(slot) => {
+                let index = pop(stack);
+                let index = index
                     .as_u64()
-                    .ok_or("array index is too large")? as usize;
-                if let Some(call_frame) = self.call_frames.last_mut() {
-                    if let Some(array) = &mut call_frame.array_variables[name_index(*name)] {
-                        return Ok(get_or_extend(array, index as usize));
-                    }
-                }
-                Ok(get_or_extend(
-                    &mut self.array_variables[name_index(*name)],
-                    index,
-                ))
+                    .ok_or(BcError::ArrayIndexTooLarge(index.clone()))?
+                    as usize;
+                let array = match slot {
+                    Slot::Global(global) => &mut self.array_variables[global],
+                    Slot::Local {
+                        index: local,
+                        global,
+                    } => match &mut locals.arrays[frame.arrays + local] {
+                        Some(array) => array,
+                        None => &mut self.array_variables[global],
+                    },
+                };
+                Ok(get_or_extend(array, index))
             }
         }
     }
 
-    fn call_function(&mut self, name: char, args: &[FunctionArgument]) -> ExecutionResult<Number> {
-        let saved_instruction_counter = self.instruction_counter;
-        let function = &self.functions[name_index(name)].clone();
-        if function.name == '\0' {
-            return Err("undefined function".into());
-        }
-        let mut call_frame = CallFrame::default();
-
-        for (arg, param) in args.iter().zip(function.parameters.iter()) {
-            // check if argument and parameter match
-            match (arg, param) {
-                (FunctionArgument::Expr(expr), Variable::Number(name)) => {
-                    let value = self.eval_expr(expr)?;
-                    call_frame.variables[name_index(*name)] = Some(value);
-                }
-                (FunctionArgument::ArrayVariable(arg_name), Variable::Array(param_name)) => {
-                    // arrays are passed by value
-                    let array = self.array_variables[name_index(*arg_name)].clone();
-                    call_frame.array_variables[name_index(*param_name)] = Some(array)
-                }
-                _ => return Err("argument does not match parameter".into()),
+    fn set_register(&mut self, register: Register, value: &Number) -> ExecutionResult<()> {
+        match register {
+            Register::Scale => {
+                let scale = value
+                    .as_u64()
+                    .ok_or_else(|| BcError::ScaleTooLarge(value.clone()))?;
+                self.check_digits(scale)?;
+                self.scale = scale;
             }
+            Register::IBase => match value.as_u64() {
+                Some(new_ibase) if (2..=16).contains(&new_ibase) => self.ibase = new_ibase,
+                _ => return Err(BcError::InvalidIBase(value.clone()).into()),
+            },
+            Register::OBase => match value.as_u64() {
+                Some(new_obase) if new_obase >= 2 => self.obase = new_obase,
+                Some(_) => return Err(BcError::InvalidOBase(value.clone()).into()),
+                None => return Err(BcError::OBaseTooLarge(value.clone()).into()),
+            },
         }
-        // set the instruction counter to 0 only after the arguments have been processed.
-        // this way errors in the argument expression will be reported at the call site
-        self.instruction_counter = 0;
-
-        for local in function.locals.iter() {
-            match local {
-                Variable::Number(name) => {
-                    call_frame.variables[name_index(*name)] = Some(0.into());
-                }
-                Variable::Array(name) => {
-                    call_frame.array_variables[name_index(*name)] = Some(Vec::new());
+        Ok(())
+    }
+
+    fn call_native_function(
+        &mut self,
+        function: MathFunction,
+        x: Number,
+    ) -> ExecutionResult<Number> {
+        let interrupt = || self.check_interrupt();
+        let result = match function {
+            MathFunction::Sin => x.sin(self.scale, &interrupt),
+            MathFunction::Cos => x.cos(self.scale, &interrupt),
+            MathFunction::Atan => x.atan(self.scale, &interrupt),
+            MathFunction::Ln => match x.ln(self.scale, &interrupt) {
+                Err(BcError::NonPositiveLogarithm(_)) => {
+                    // the bc definition returns 1 - 10^scale,
+                    // this matches the behavior of GNU bc
+                    self.check_digits(self.scale)?;
+                    let power = Number::from(10).pow(&self.scale.into(), 0)?;
+                    Ok(Number::from(1) - &power)
                 }
+                result => result,
+            },
+            MathFunction::Exp => {
+                // check before computing the result, which could take a very long time
+                self.check_digits(x.exp_digits_estimate())?;
+                x.exp(self.scale, &interrupt)
             }
+        };
+        // the other errors of the math functions come from the limits
+        let value = result.map_err(ExecutionError::aborted)?;
+        self.check_number(value)
+    }
+
+    /// finds the function of a call, before its arguments are evaluated.
+    /// `depth` is the number of active calls of defined functions
+    fn resolve_call(&self, call: &CallSite, depth: usize) -> ExecutionResult<Callee> {
+        if let Some(function) = self.host_functions.get(&call.name) {
+            return Ok(Callee::Host(function.clone()));
         }
-        let body = function.body.clone();
-
-        self.call_frames.push(call_frame);
-        for stmt in body.iter() {
-            let evaluated_statement = self.eval_stmt(stmt).map_err(|e| {
-                e.add_call(
-                    function.name,
-                    function.source_locations[self.instruction_counter],
-                    function.file.clone(),
-                )
-            });
-            match evaluated_statement {
-                Err(e) => {
-                    self.call_frames.pop();
-                    self.instruction_counter = saved_instruction_counter;
-                    return Err(e);
-                }
-                Ok(ControlFlow::Return(value)) => {
-                    self.call_frames.pop();
-                    self.instruction_counter = saved_instruction_counter;
-                    return Ok(value);
+        if let Some(&function) = self.native_functions.get(&call.name) {
+            if call.array_arguments != [false] {
+                return Err(BcError::ArgumentMismatch {
+                    function: call.name.clone(),
                 }
-                Ok(ControlFlow::Break) | Ok(ControlFlow::Quit) => {
-                    // both of these should never happen.
-                    // A quit inside of a function definition
-                    // should stop execution, and we can only call
-                    // a function after its definition has been processed
-                    // A break outside of a loop is a parser bug.
-                    panic!("reached quit or break in function call")
-                }
-                _ => {}
+                .into());
             }
+            return Ok(Callee::Native(function));
         }
-        self.call_frames.pop();
-        // from the POSIX standard:
-        // > the value of the function shall be the value of the expression
-        // > in the parentheses of the return statement or shall be zero
-        // > if no expression is provided or if there is no return statement
-        Ok(Number::zero())
-    }
-
-    fn eval_expr(&mut self, expr: &ExprInstruction) -> ExecutionResult<Number> {
-        match expr {
-            ExprInstruction::Number(x) => {
-                Number::parse(x, self.ibase).ok_or("invalid digit for the current ibase".into())
-            }
-            ExprInstruction::GetRegister(reg) => match reg {
-                Register::Scale => Ok(self.scale.into()),
-                Register::IBase => Ok(self.ibase.into()),
-                Register::OBase => Ok(self.obase.into()),
-            },
-            ExprInstruction::Named(named) => self.eval_named(named).cloned(),
-            ExprInstruction::Builtin { function, arg } => match function {
-                BuiltinFunction::Length => Ok(self.eval_expr(arg)?.length().into()),
-                BuiltinFunction::Sqrt => self
-                    .eval_expr(arg)?
-                    .sqrt(self.scale)
-                    .map_err(ExecutionError::from),
-                BuiltinFunction::Scale => Ok(self.eval_expr(arg)?.scale().into()),
-            },
-            ExprInstruction::PreIncrement(named) => {
-                let value = self.eval_named(named)?;
-                value.inc();
-                Ok(value.clone())
+        let Some(function) = self.functions.get(&call.name).cloned() else {
+            return Self::resolve_random_call(call);
+        };
+        if let Some(max_call_depth) = self.limits.max_call_depth {
+            if depth >= max_call_depth {
+                return Err(ExecutionError::aborted(BcError::CallDepthLimitExceeded));
             }
-            ExprInstruction::PreDecrement(named) => {
-                let value = self.eval_named(named)?;
-                value.dec();
-                Ok(value.clone())
-            }
-            ExprInstruction::PostIncrement(named) => {
-                let value = self.eval_named(named)?;
-                let result = value.clone();
-                value.inc();
-                Ok(result)
-            }
-            ExprInstruction::PostDecrement(named) => {
-                let value = self.eval_named(named)?;
-                let result = value.clone();
-                value.dec();
-                Ok(result)
+        }
+        Ok(Callee::Defined(function))
+    }
+
+    fn resolve_random_call(call: &CallSite) -> ExecutionResult<Callee> {
+        let (function, arities): (_, &[&[bool]]) = match &*call.name {
+            "rand" => (RandomFunction::Rand, &[&[]]),
+            "irand" => (RandomFunction::IRand, &[&[false]]),
+            "seed" => (RandomFunction::Seed, &[&[], &[false]]),
+            _ => return Err(BcError::UndefinedFunction(call.name.clone()).into()),
+        };
+        if !arities.contains(&&*call.array_arguments) {
+            return Err(BcError::ArgumentMismatch {
+                function: call.name.clone(),
             }
-            ExprInstruction::Call { name, args } => {
-                let ic = self.instruction_counter;
-                self.instruction_counter = 0;
-                let call_result = self.call_function(*name, args);
-                self.instruction_counter = ic;
-                call_result
+            .into());
+        }
+        Ok(Callee::Random(function))
+    }
+
+    fn call_random_function(
+        &mut self,
+        function: RandomFunction,
+        arguments: Vec<Number>,
+    ) -> ExecutionResult<Number> {
+        let value = match (function, arguments.first()) {
+            (RandomFunction::Rand, _) => (self.random.next_u64() >> 32).into(),
+            (RandomFunction::IRand, Some(bound)) => {
+                let bound = bound
+                    .as_u64()
+                    .ok_or_else(|| BcError::InvalidRandomBound(bound.clone()))?;
+                self.random.below(bound).into()
             }
-            ExprInstruction::Assignment { named, value } => {
-                let value = self.eval_expr(value)?;
-                self.eval_named(named)?.clone_from(&value);
-                Ok(value)
+            (RandomFunction::Seed, None) => self.random.seed().into(),
+            (RandomFunction::Seed, Some(seed)) => {
+                let value = seed
+                    .as_u64()
+                    .ok_or_else(|| BcError::InvalidSeed(seed.clone()))?;
+                self.random = Random::new(value);
+                seed.clone()
             }
-            ExprInstruction::SetRegister { register, value } => {
-                // if the value is a single digit it has to be interpreted
-                // as an hexadecimal number, regardless of the value of ibase
-                let value = match value.as_ref() {
-                    ExprInstruction::Number(n) if n.len() == 1 => {
-                        // this cannot fail because the parser ensures that
-                        // the value is a valid hexadecimal number
-                        Number::parse(n, 16).unwrap()
-                    }
-                    _ => self.eval_expr(value)?,
-                };
+            (RandomFunction::IRand, None) => unreachable!("irand is resolved with one argument"),
+        };
+        Ok(value)
+    }
 
-                match register {
-                    Register::Scale => {
-                        self.scale = value
-                            .as_u64()
-                            .ok_or("the value assigned to scale is too large")?
-                    }
-                    Register::IBase => {
-                        if let Some(new_ibase) = value.as_u64() {
-                            if (2..=16).contains(&new_ibase) {
-                                self.ibase = new_ibase;
-                                return Ok(value);
-                            }
-                        }
-                        return Err("ibase must be between 2 and 16".into());
-                    }
-                    Register::OBase => {
-                        if let Some(new_obase) = value.as_u64() {
-                            if new_obase >= 2 {
-                                self.obase = new_obase;
-                            } else {
-                                return Err("obase must be greater than 1".into());
-                            }
-                        } else {
-                            return Err("value assigned to obase is too large".into());
-                        }
-                    }
+    /// makes `frame` a call of `function`, with the arguments
+    /// that have been evaluated since `call` was resolved
+    fn enter_function(
+        machine: &mut Machine,
+        frame: &mut Frame,
+        function: Rc<CompiledFunction>,
+        call: &PendingCall,
+        argument_count: usize,
+    ) {
+        let variables = machine.locals.variables.len();
+        let arrays = machine.locals.arrays.len();
+        machine
+            .locals
+            .variables
+            .resize(variables + function.variable_count, None);
+        machine
+            .locals
+            .arrays
+            .resize(arrays + function.array_count, None);
+        let mut values = machine.stack.drain(call.stack..);
+        let mut array_values = machine.array_arguments.drain(call.arrays..);
+        // arguments without a parameter have not been evaluated, and parameters
+        // without an argument are left unset
+        for parameter in function.parameters.iter().take(argument_count) {
+            match *parameter {
+                LocalVariable::Number(index) => {
+                    machine.locals.variables[variables + index] = values.next();
+                }
+                LocalVariable::Array(index) => {
+                    machine.locals.arrays[arrays + index] = array_values.next();
                 }
-                Ok(value)
             }
-            ExprInstruction::UnaryMinus(expr) => Ok(self.eval_expr(expr)?.negate()),
-            ExprInstruction::Add(lhs, rhs) => Ok(self.eval_expr(lhs)?.add(&self.eval_expr(rhs)?)),
-            ExprInstruction::Sub(lhs, rhs) => Ok(self.eval_expr(lhs)?.sub(&self.eval_expr(rhs)?)),
-            ExprInstruction::Mul(lhs, rhs) => {
-                Ok(self.eval_expr(lhs)?.mul(&self.eval_expr(rhs)?, self.scale))
+        }
+        for local in &function.locals {
+            match *local {
+                LocalVariable::Number(index) => {
+                    machine.locals.variables[variables + index] = Some(Number::zero());
+                }
+                LocalVariable::Array(index) => {
+                    machine.locals.arrays[arrays + index] = Some(Vec::new());
+                }
             }
-            ExprInstruction::Div(lhs, rhs) => Ok(self
-                .eval_expr(lhs)?
-                .div(&self.eval_expr(rhs)?, self.scale)?),
-            ExprInstruction::Mod(lhs, rhs) => self
-                .eval_expr(lhs)?
-                .modulus(&self.eval_expr(rhs)?, self.scale)
-                .map_err(ExecutionError::from),
-            ExprInstruction::Pow(lhs, rhs) => self
-                .eval_expr(lhs)?
-                .pow(&self.eval_expr(rhs)?, self.scale)
-                .map_err(ExecutionError::from),
         }
+        let code = function.code.clone();
+        let caller = std::mem::replace(
+            frame,
+            Frame {
+                function: Some(function),
+                code,
+                pc: 0,
+                variables,
+                arrays,
+            },
+        );
+        machine.callers.push(caller);
     }
 
-    fn eval_condition(&mut self, condition: &ConditionInstruction) -> ExecutionResult<bool> {
-        match condition {
-            ConditionInstruction::Expr(expr) => self.eval_expr(expr).map(|val| !val.is_zero()),
-            ConditionInstruction::Eq(lhs, rhs) => Ok(self.eval_expr(lhs)? == self.eval_expr(rhs)?),
-            ConditionInstruction::Ne(lhs, rhs) => Ok(self.eval_expr(lhs)? != self.eval_expr(rhs)?),
-            ConditionInstruction::Lt(lhs, rhs) => Ok(self.eval_expr(lhs)? < self.eval_expr(rhs)?),
-            ConditionInstruction::Gt(lhs, rhs) => Ok(self.eval_expr(lhs)? > self.eval_expr(rhs)?),
-            ConditionInstruction::Leq(lhs, rhs) => Ok(self.eval_expr(lhs)? <= self.eval_expr(rhs)?),
-            ConditionInstruction::Geq(lhs, rhs) => Ok(self.eval_expr(lhs)? >= self.eval_expr(rhs)?),
-        }
+    fn arithmetic(
+        &mut self,
+        stack: &mut Vec<Number>,
+        span: Option<Span>,
+        op: impl FnOnce(Number, &Number, u64) -> NumericResult,
+    ) -> ExecutionResult<()> {
+        let rhs = pop(stack);
+        let lhs = pop(stack);
+        let value = op(lhs, &rhs, self.scale).map_err(|e| {
+            let error = ExecutionError::from(e);
+            match span {
+                Some(span) => error.at(span),
+                None => error,
+            }
+        })?;
+        stack.push(self.check_number(value)?);
+        Ok(())
     }
 
-    fn eval_stmt(&mut self, stmt: &StmtInstruction) -> ExecutionResult<ControlFlow> {
-        let instruction_counter_start = self.instruction_counter;
-        let mut stmt_instruction_count = 1;
-        match stmt {
-            StmtInstruction::Break => {
-                return Ok(ControlFlow::Break);
-            }
-            StmtInstruction::Quit => {
-                return Ok(ControlFlow::Quit);
-            }
-            StmtInstruction::Return => {
-                return Ok(ControlFlow::Return(Number::zero()));
-            }
-            StmtInstruction::ReturnExpr(expr) => {
-                let value = self.eval_expr(expr)?;
-                return Ok(ControlFlow::Return(value));
-            }
-            StmtInstruction::If {
-                condition,
-                instruction_count,
-                body,
-            } => {
-                stmt_instruction_count = *instruction_count + 1;
-                if self.eval_condition(condition)? {
-                    // count the condition
-                    self.instruction_counter += 1;
-                    for stmt in body {
-                        let control_flow = self.eval_stmt(stmt)?;
-                        // any control flow instruction in the body of the
-                        // if needs to be handled by the caller
-                        if control_flow != ControlFlow::None {
-                            return Ok(control_flow);
+    /// executes the operations of `frame` and of the functions it calls,
+    /// until the end of the code of the global scope. When it fails,
+    /// `frame` and `machine.callers` are where the error occurred
+    fn execute(
+        &mut self,
+        machine: &mut Machine,
+        frame: &mut Frame,
+    ) -> ExecutionResult<Option<Number>> {
+        loop {
+            let op = frame.code.ops[frame.pc];
+            match op {
+                Op::Tick => self.tick()?,
+                Op::Literal(index) => {
+                    let literal = &frame.code.literals[index];
+                    let value = match (&literal.decimal, self.ibase) {
+                        (Some(value), 10) => value.clone(),
+                        _ => Number::parse(&literal.text, self.ibase).ok_or_else(|| {
+                            BcError::InvalidDigit {
+                                number: literal.text.clone(),
+                                ibase: self.ibase,
+                            }
+                        })?,
+                    };
+                    // numbers with an exponent can have a lot of digits
+                    machine.stack.push(self.check_number(value)?);
+                }
+                Op::Constant(index) => machine.stack.push(frame.code.constants[index].clone()),
+                Op::Bool(value) => machine.stack.push(Number::from(value as u64)),
+                Op::Register(register) => machine.stack.push(match register {
+                    Register::Scale => self.scale.into(),
+                    Register::IBase => self.ibase.into(),
+                    Register::OBase => self.obase.into(),
+                }),
+                Op::SetRegister(register) => {
+                    let value = pop(&mut machine.stack);
+                    self.set_register(register, &value)?;
+                    machine.stack.push(value);
+                }
+                Op::Load(named) => {
+                    let value =
+                        self.variable(&mut machine.locals, frame, &mut machine.stack, named)?;
+                    machine.stack.push(value.clone());
+                }
+                Op::Store(named) => {
+                    let variable =
+                        self.variable(&mut machine.locals, frame, &mut machine.stack, named)?;
+                    variable.clone_from(machine.stack.last().expect("no value to store"));
+                }
+                Op::PreIncrement(named) => {
+                    let value =
+                        self.variable(&mut machine.locals, frame, &mut machine.stack, named)?;
+                    value.inc();
+                    machine.stack.push(value.clone());
+                }
+                Op::PreDecrement(named) => {
+                    let value =
+                        self.variable(&mut machine.locals, frame, &mut machine.stack, named)?;
+                    value.dec();
+                    machine.stack.push(value.clone());
+                }
+                Op::PostIncrement(named) => {
+                    let value =
+                        self.variable(&mut machine.locals, frame, &mut machine.stack, named)?;
+                    let result = value.clone();
+                    value.inc();
+                    machine.stack.push(result);
+                }
+                Op::PostDecrement(named) => {
+                    let value =
+                        self.variable(&mut machine.locals, frame, &mut machine.stack, named)?;
+                    let result = value.clone();
+                    value.dec();
+                    machine.stack.push(result);
+                }
+                Op::Builtin(function) => {
+                    let value = pop(&mut machine.stack);
+                    let result = match function {
+                        BuiltinFunction::Length => value.length().into(),
+                        BuiltinFunction::Sqrt => {
+                            let value = value.sqrt(self.scale)?;
+                            self.check_number(value)?
                         }
+                        BuiltinFunction::Scale => value.scale().into(),
+                    };
+                    machine.stack.push(result);
+                }
+                Op::Negate => {
+                    let value = pop(&mut machine.stack).negate();
+                    machine.stack.push(value);
+                }
+                Op::Add => {
+                    self.arithmetic(&mut machine.stack, None, |lhs, rhs, _| Ok(lhs + rhs))?
+                }
+                Op::Sub => {
+                    self.arithmetic(&mut machine.stack, None, |lhs, rhs, _| Ok(lhs - rhs))?
+                }
+                Op::Mul => self.arithmetic(&mut machine.stack, None, |lhs, rhs, scale| {
+                    Ok(lhs.mul(rhs, scale))
+                })?,
+                Op::Div(span) => self.arithmetic(&mut machine.stack, Some(span), Number::div)?,
+                Op::Mod(span) => {
+                    self.arithmetic(&mut machine.stack, Some(span), Number::modulus)?
+                }
+                Op::Pow(span) => {
+                    let exponent = pop(&mut machine.stack);
+                    let base = machine.stack.last().expect("no base");
+                    // check before computing the power, which could take a very long time
+                    self.check_digits(base.pow_digits_estimate(&exponent))?;
+                    machine.stack.push(exponent);
+                    self.arithmetic(&mut machine.stack, Some(span), Number::pow)?;
+                }
+                Op::BitAnd(span) => {
+                    self.arithmetic(&mut machine.stack, Some(span), |lhs, rhs, _| {
+                        lhs.bit_and(rhs)
+                    })?
+                }
+                Op::BitOr(span) => {
+                    self.arithmetic(&mut machine.stack, Some(span), |lhs, rhs, _| {
+                        lhs.bit_or(rhs)
+                    })?
+                }
+                Op::BitXor(span) => {
+                    self.arithmetic(&mut machine.stack, Some(span), |lhs, rhs, _| {
+                        lhs.bit_xor(rhs)
+                    })?
+                }
+                Op::BitNot(span) => {
+                    let value = pop(&mut machine.stack)
+                        .bit_not()
+                        .map_err(|e| ExecutionError::from(e).at(span))?;
+                    machine.stack.push(value);
+                }
+                Op::Shl(span) => {
+                    let bits = pop(&mut machine.stack);
+                    let value = machine.stack.last().expect("no value to shift");
+                    // like for powers, check before computing the result
+                    self.check_digits(value.shift_left_digits_estimate(&bits))?;
+                    machine.stack.push(bits);
+                    self.arithmetic(&mut machine.stack, Some(span), |lhs, rhs, _| {
+                        lhs.shift_left(rhs)
+                    })?;
+                }
+                Op::Shr(span) => {
+                    self.arithmetic(&mut machine.stack, Some(span), |lhs, rhs, _| {
+                        lhs.shift_right(rhs)
+                    })?
+                }
+                Op::Compare(comparison) => {
+                    let rhs = pop(&mut machine.stack);
+                    let lhs = pop(&mut machine.stack);
+                    let value = comparison.compare(&lhs, &rhs);
+                    machine.stack.push(Number::from(value as u64));
+                }
+                Op::Not => {
+                    let value = pop(&mut machine.stack).is_zero();
+                    machine.stack.push(Number::from(value as u64));
+                }
+                Op::Jump(target) => {
+                    frame.pc = target;
+                    continue;
+                }
+                Op::JumpIfZero(target) => {
+                    if pop(&mut machine.stack).is_zero() {
+                        frame.pc = target;
+                        continue;
                     }
                 }
-            }
-            StmtInstruction::While {
-                condition,
-                instruction_count,
-                body,
-            } => {
-                stmt_instruction_count = *instruction_count + 1;
-                'while_loop: while self.eval_condition(condition)? {
-                    // count the condition
-                    self.instruction_counter += 1;
-                    for stmt in body {
-                        let control_flow = self.eval_stmt(stmt)?;
-                        if control_flow == ControlFlow::Break {
-                            break 'while_loop;
-                        }
-                        if control_flow != ControlFlow::None {
-                            // we either hit a return or quit
-                            // so we need to pass that up the stack
-                            return Ok(control_flow);
+                Op::JumpIfNotZero(target) => {
+                    if !pop(&mut machine.stack).is_zero() {
+                        frame.pc = target;
+                        continue;
+                    }
+                }
+                Op::JumpUnless(comparison, target) => {
+                    let rhs = pop(&mut machine.stack);
+                    let lhs = pop(&mut machine.stack);
+                    if !comparison.compare(&lhs, &rhs) {
+                        frame.pc = target;
+                        continue;
+                    }
+                }
+                Op::Pop => {
+                    pop(&mut machine.stack);
+                }
+                Op::PrintValue => {
+                    let value = pop(&mut machine.stack);
+                    let start = self.output_length;
+                    let text = self.number_text(&value) + "\n";
+                    self.print(&text)?;
+                    self.last_value_output = Some(start..self.output_length);
+                    self.last = value;
+                }
+                Op::PrintNumber => {
+                    let value = pop(&mut machine.stack);
+                    let text = self.number_text(&value);
+                    self.print(&text)?;
+                }
+                Op::PrintString(index) => self.print(&frame.code.strings[index])?,
+                Op::Limits => self.write_limits()?,
+                Op::BeginCall(call) => {
+                    let callee =
+                        self.resolve_call(&frame.code.calls[call], machine.callers.len())?;
+                    machine.calls.push(PendingCall {
+                        callee,
+                        stack: machine.stack.len(),
+                        arrays: machine.array_arguments.len(),
+                    });
+                }
+                Op::Argument { call, index, end } => {
+                    let call = &frame.code.calls[call];
+                    let is_array = call.array_arguments[index];
+                    let pending = machine.calls.last().expect("argument outside of a call");
+                    let matches = match &pending.callee {
+                        Callee::Defined(function) => match function.parameters.get(index) {
+                            // the arguments without a parameter are not evaluated
+                            None => {
+                                frame.pc = end;
+                                continue;
+                            }
+                            Some(parameter) => parameter.is_array() == is_array,
+                        },
+                        Callee::Host(_) => !is_array,
+                        // the arguments have been checked when resolving the call
+                        Callee::Native(_) | Callee::Random(_) => true,
+                    };
+                    if !matches {
+                        return Err(BcError::ArgumentMismatch {
+                            function: call.name.clone(),
                         }
+                        .into());
                     }
-                    // reset the instruction counter to the start of the loop
-                    self.instruction_counter = instruction_counter_start;
                 }
-            }
-            StmtInstruction::For {
-                init,
-                condition,
-                update,
-                instruction_count,
-                body,
-            } => {
-                stmt_instruction_count = *instruction_count + 1;
-                self.eval_expr(init)?;
-                'for_loop: while self.eval_condition(condition)? {
-                    // count init condition and update
-                    self.instruction_counter += 1;
-                    for stmt in body {
-                        let control_flow = self.eval_stmt(stmt)?;
-                        if control_flow == ControlFlow::Break {
-                            break 'for_loop;
+                Op::ArrayArgument(global) => {
+                    // arrays are passed by value
+                    let array = self.array_variables[global].clone();
+                    machine.array_arguments.push(array);
+                }
+                Op::Call(call) => {
+                    let pending = machine.calls.pop().expect("call without arguments");
+                    match &pending.callee {
+                        Callee::Host(function) => {
+                            let values: Vec<Number> =
+                                machine.stack.drain(pending.stack..).collect();
+                            let value = function(&values)?;
+                            machine.stack.push(self.check_number(value)?);
+                        }
+                        &Callee::Native(function) => {
+                            let x = pop(&mut machine.stack);
+                            let value = self.call_native_function(function, x)?;
+                            machine.stack.push(value);
                         }
-                        if control_flow != ControlFlow::None {
-                            return Ok(control_flow);
+                        &Callee::Random(function) => {
+                            let values: Vec<Number> =
+                                machine.stack.drain(pending.stack..).collect();
+                            let value = self.call_random_function(function, values)?;
+                            machine.stack.push(value);
+                        }
+                        Callee::Defined(function) => {
+                            let argument_count = frame.code.calls[call].array_arguments.len();
+                            let function = function.clone();
+                            Self::enter_function(
+                                machine,
+                                frame,
+                                function,
+                                &pending,
+                                argument_count,
+                            );
+                            continue;
                         }
                     }
-                    // reset the instruction counter to the start of the loop
-                    self.instruction_counter = instruction_counter_start;
-                    self.eval_expr(update)?;
                 }
-            }
-            StmtInstruction::String(s) => self.output.push_str(s),
-            StmtInstruction::Expr(expr) => {
-                let value = self.eval_expr(expr)?;
-                if should_print(expr) {
-                    // this should never fail
-                    writeln!(&mut self.output, "{}", value.to_string(self.obase))
-                        .expect("error appending to string");
+                Op::Return | Op::ReturnZero => {
+                    let value = if op == Op::Return {
+                        pop(&mut machine.stack)
+                    } else {
+                        Number::zero()
+                    };
+                    let Some(caller) = machine.callers.pop() else {
+                        return Ok(Some(value));
+                    };
+                    machine.locals.variables.truncate(frame.variables);
+                    machine.locals.arrays.truncate(frame.arrays);
+                    *frame = caller;
+                    machine.stack.push(value);
+                }
+                Op::Define(index) => {
+                    let (name, function) = &frame.code.functions[index];
+                    self.native_functions.remove(name);
+                    self.host_functions.remove(name);
+                    self.functions.insert(name.clone(), function.clone());
+                }
+                Op::Quit | Op::Halt => {
+                    self.has_quit = true;
+                    return Ok(None);
                 }
+                Op::End => return Ok(None),
             }
-            StmtInstruction::DefineFunction { .. } => {
-                // the language grammar ensures that this is never reached
-                panic!("function definition outside of the global scope")
+            frame.pc += 1;
+        }
+    }
+
+    /// Executes `code` in the global scope, and returns the value returned
+    /// by it, which only the code of an expression does.
+    fn run(&mut self, code: Rc<Code>) -> ExecutionResult<Option<Number>> {
+        let mut machine = Machine::default();
+        let mut frame = Frame {
+            function: None,
+            code,
+            pc: 0,
+            variables: 0,
+            arrays: 0,
+        };
+        let mut error = match self.execute(&mut machine, &mut frame) {
+            Err(e) => e,
+            result => return result,
+        };
+        // the error is reported at each of the active calls, as they are left
+        loop {
+            if let Some(span) = frame.code.locations[frame.pc].call {
+                error = error.at(span);
             }
+            let line = frame.code.line(frame.pc);
+            let file = frame.code.file.clone();
+            let Some(function) = frame.function.take() else {
+                return Err(error.global_source(line, file));
+            };
+            error = error.add_call(function.definition.name.clone(), line, file);
+            frame = machine
+                .callers
+                .pop()
+                .expect("the global scope is the first caller");
         }
-        self.instruction_counter = instruction_counter_start + stmt_instruction_count;
-        Ok(ControlFlow::None)
     }
 
+    /// Executes `program` and returns its output, which is empty
+    /// if the output is sent to an output sink.
     pub fn exec(&mut self, program: Program) -> ExecutionResult<String> {
-        self.instruction_counter = 0;
-        for stmt in program.instructions {
-            if let StmtInstruction::DefineFunction { name, function } = stmt {
-                // we handle this here because we need to store the function.
-                // doing it in eval_stmt would not work because we would need
-                // to clone from the reference. Since functions can only be
-                // defined in the global scope, this is valid.
-
-                // first we need to check if the definition contains quit,
-                // in which case we need to stop execution
-                if function.body.iter().any(contains_quit) {
-                    self.has_quit = true;
-                    return Ok(self.take_and_clear_output());
-                }
-
-                self.functions[name_index(name)] = function;
-            } else {
-                let control_flow = self.eval_stmt(&stmt).map_err(|mut e| {
-                    e.partial_output = self.take_and_clear_output();
-                    e.global_source(
-                        program.source_locations[self.instruction_counter],
-                        program.file.clone(),
-                    )
-                })?;
-                match control_flow {
-                    // both of these should have been handled earlier
-                    // by the parser
-                    ControlFlow::Return(_) => {
-                        panic!("return outside of function");
-                    }
-                    ControlFlow::Break => {
-                        panic!("break outside of loop");
-                    }
-                    _ => {}
-                }
-                // we can't trust the return value of eval_stmt because
-                // unexecuted branches will not return ControlFlow::Quit,
-                // but we need still need to stop execution
-                if contains_quit(&stmt) {
-                    self.has_quit = true;
-                    return Ok(self.take_and_clear_output());
-                }
+        self.executed_statements = 0;
+        self.has_quit = false;
+        self.output_length = 0;
+        self.last_value_output = None;
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        let code = self.compile(|names| compile_program(names, &program));
+        match self.run(code) {
+            Err(mut e) => {
+                e.partial_output = self.take_and_clear_output();
+                Err(e)
             }
+            Ok(_) => Ok(self.take_and_clear_output()),
+        }
+    }
+
+    fn write_limits(&mut self) -> ExecutionResult<()> {
+        let mut limits = vec![
+            ("BC_BASE_MAX", BC_BASE_MAX),
+            ("BC_DIM_MAX", BC_DIM_MAX),
+            (
+                "BC_SCALE_MAX",
+                self.limits.max_digits.unwrap_or(BC_SCALE_MAX),
+            ),
+            ("BC_STRING_MAX", BC_STRING_MAX),
+            ("BC_RAND_MAX", BC_RAND_MAX),
+        ];
+        if let Some(max_statements) = self.limits.max_statements {
+            limits.push(("MAX_STATEMENTS", max_statements));
+        }
+        if let Some(max_call_depth) = self.limits.max_call_depth {
+            limits.push(("MAX_CALL_DEPTH", max_call_depth as u64));
+        }
+        if let Some(max_digits) = self.limits.max_digits {
+            limits.push(("MAX_DIGITS", max_digits));
+        }
+        for (name, value) in limits {
+            self.print(&format!("{name:<15} = {value}\n"))?;
+        }
+        Ok(())
+    }
+
+    /// The last value printed by an expression statement, the value of `last`.
+    pub fn last(&self) -> &Number {
+        &self.last
+    }
+
+    /// Sets `last`, for embedders that show a value computed
+    /// without the interpreter as a result.
+    pub fn set_last(&mut self, value: Number) {
+        self.last = value;
+    }
+
+    /// Where the last value printed by an expression statement of the last
+    /// `exec` is in its output, including the newline after it; `None`
+    /// if it printed no such value. This also counts the output sent
+    /// to an output sink.
+    pub fn last_value_output(&self) -> Option<Range<usize>> {
+        self.last_value_output.clone()
+    }
+
+    /// Evaluates `text` and returns the value of its last statement, which
+    /// must be an expression. The statements before it are executed as with
+    /// `exec` and their output is discarded, unless there is an output sink;
+    /// the value is not printed, so `last` does not change.
+    /// Returns `EvalError::Halted` if the statements execute `quit` or `halt`.
+    /// The extensions of `ParseOptions` are not enabled, so unlike
+    /// `Number::from_str`, `1e5` is an error; see `eval_with_options`.
+    pub fn eval(&mut self, text: &str) -> Result<Number, EvalError> {
+        self.eval_with_options(text, &ParseOptions::default())
+    }
+
+    /// Evaluates `text` like `eval`, accepting the extensions enabled in `options`.
+    pub fn eval_with_options(
+        &mut self,
+        text: &str,
+        options: &ParseOptions,
+    ) -> Result<Number, EvalError> {
+        let mut text = text.to_string();
+        if !text.ends_with('\n') {
+            text.push('\n');
         }
-        Ok(self.take_and_clear_output())
+        let mut program = parse_program_with_options(&text, None, options).map_err(|e| {
+            let diagnostics: Vec<_> = e
+                .diagnostics(&text)
+                .iter()
+                .map(|diagnostic| diagnostic.render(&text))
+                .collect();
+            EvalError::Syntax(diagnostics.join("\n"))
+        })?;
+        let Some(StmtInstruction::Expr(expr)) = program.instructions.pop() else {
+            return Err(EvalError::NotAnExpression);
+        };
+        self.exec(program).map_err(|e| *e.error)?;
+        if self.has_quit {
+            return Err(EvalError::Halted);
+        }
+        let code = self.compile(|names| compile_expression(names, &expr));
+        // the code of an expression returns its value, unless it halts
+        self.run(code)
+            .map_err(|e| *e.error)?
+            .ok_or(EvalError::Halted)
     }
 
+    /// Returns true if the last program executed `quit` or `halt`,
+    /// every `exec` and `eval` starts without it.
     pub fn has_quit(&self) -> bool {
         self.has_quit
     }
+
+    /// Names of the global variables that have a value other than zero.
+    pub fn global_variable_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .global_names
+            .variables
+            .iter()
+            .filter(|(_, &slot)| !self.variables[slot].is_zero())
+            .map(|(name, _)| name.to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn write_state(&self, state: &mut String) -> std::fmt::Result {
+        // all values are written in base 10, the original ibase is restored at the end
+        writeln!(state, "ibase = A")?;
+        for name in self.global_variable_names() {
+            writeln!(
+                state,
+                "{name} = {}",
+                self.variables[self.global_names.variables[name.as_str()]].to_string_radix(10)
+            )?;
+        }
+        let mut arrays: Vec<_> = self.global_names.arrays.iter().collect();
+        arrays.sort_by_key(|(name, _)| *name);
+        for (name, &slot) in arrays {
+            for (index, value) in self.array_variables[slot].iter().enumerate() {
+                if !value.is_zero() {
+                    writeln!(state, "{name}[{index}] = {}", value.to_string_radix(10))?;
+                }
+            }
+        }
+        let mut functions: Vec<_> = self
+            .functions
+            .values()
+            .map(|function| &function.definition)
+            .filter(|function| function.file.as_ref() != MATH_LIBRARY_FILE)
+            .collect();
+        functions.sort_by_key(|function| &function.name);
+        for function in functions {
+            writeln!(state, "{function}")?;
+        }
+        writeln!(state, "scale = {}", self.scale)?;
+        writeln!(state, "obase = {}", self.obase)?;
+        writeln!(state, "ibase = {}", self.ibase)
+    }
+
+    /// Returns a bc program that, when executed, restores the global variables,
+    /// arrays, user-defined functions and registers of the interpreter.
+    /// Functions from the math library are not included.
+    pub fn dump_state(&self) -> String {
+        let mut state = String::new();
+        // this should never fail
+        self.write_state(&mut state)
+            .expect("error appending to string");
+        state
+    }
+
+    /// Writes the program returned by `dump_state` to `path`.
+    pub fn save_state(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.dump_state())
+    }
+
+    /// Parses and executes the bc file at `path`, accepting the extensions
+    /// enabled in `options`. A file written by `save_state` needs the
+    /// options of the programs that defined its functions.
+    /// # Returns
+    /// the output of the program
+    pub fn load_file(&mut self, path: &Path, options: &ParseOptions) -> Result<String, LoadError> {
+        let mut text = std::fs::read_to_string(path).map_err(LoadError::Io)?;
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        let program = parse_program_with_options(&text, Some(&path.to_string_lossy()), options)
+            .map_err(LoadError::Parse)?;
+        self.exec(program).map_err(LoadError::Execution)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::instructions::{
+        ConditionInstruction, ExprInstruction, Function, FunctionArgument, NamedExpr, Variable,
+    };
+    use crate::number::Notation;
 
     #[test]
     fn test_print_number() {
@@ -603,7 +1351,7 @@ mod tests {
         let output = interpreter
             .exec(
                 vec![StmtInstruction::Expr(ExprInstruction::Named(
-                    NamedExpr::VariableNumber('a'),
+                    NamedExpr::VariableNumber("a".into()),
                 ))]
                 .into(),
             )
@@ -676,9 +1424,11 @@ mod tests {
             .exec(
                 vec![
                     StmtInstruction::Expr(ExprInstruction::PreIncrement(
-                        NamedExpr::VariableNumber('a'),
+                        NamedExpr::VariableNumber("a".into()),
                     )),
-                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
+                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into(),
+                    ))),
                 ]
                 .into(),
             )
@@ -697,9 +1447,11 @@ mod tests {
             .exec(
                 vec![
                     StmtInstruction::Expr(ExprInstruction::PreDecrement(
-                        NamedExpr::VariableNumber('a'),
+                        NamedExpr::VariableNumber("a".into()),
                     )),
-                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
+                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into(),
+                    ))),
                 ]
                 .into(),
             )
@@ -718,9 +1470,11 @@ mod tests {
             .exec(
                 vec![
                     StmtInstruction::Expr(ExprInstruction::PostIncrement(
-                        NamedExpr::VariableNumber('a'),
+                        NamedExpr::VariableNumber("a".into()),
                     )),
-                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
+                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into(),
+                    ))),
                 ]
                 .into(),
             )
@@ -739,9 +1493,11 @@ mod tests {
             .exec(
                 vec![
                     StmtInstruction::Expr(ExprInstruction::PostDecrement(
-                        NamedExpr::VariableNumber('a'),
+                        NamedExpr::VariableNumber("a".into()),
                     )),
-                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
+                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into(),
+                    ))),
                 ]
                 .into(),
             )
@@ -762,9 +1518,9 @@ mod tests {
             .exec(
                 vec![
                     StmtInstruction::DefineFunction {
-                        name: 'f',
+                        name: "f".into(),
                         function: Function {
-                            name: 'f',
+                            name: "f".into(),
                             body: [StmtInstruction::Expr(ExprInstruction::Number(
                                 "5".to_string(),
                             ))]
@@ -773,8 +1529,9 @@ mod tests {
                         },
                     },
                     StmtInstruction::Expr(ExprInstruction::Call {
-                        name: 'f',
+                        name: "f".into(),
                         args: vec![],
+                        span: Span::default(),
                     }),
                 ]
                 .into(),
@@ -794,10 +1551,12 @@ mod tests {
             .exec(
                 vec![
                     StmtInstruction::Expr(ExprInstruction::Assignment {
-                        named: NamedExpr::VariableNumber('a'),
+                        named: NamedExpr::VariableNumber("a".into()),
                         value: Box::new(ExprInstruction::Number("5".to_string())),
                     }),
-                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
+                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into(),
+                    ))),
                 ]
                 .into(),
             )
@@ -852,15 +1611,16 @@ mod tests {
             .exec(
                 vec![
                     StmtInstruction::DefineFunction {
-                        name: 'f',
+                        name: "f".into(),
                         function: Function {
-                            name: 'f',
+                            name: "f".into(),
                             ..Default::default()
                         },
                     },
                     StmtInstruction::Expr(ExprInstruction::Call {
-                        name: 'f',
+                        name: "f".into(),
                         args: vec![],
+                        span: Span::default(),
                     }),
                 ]
                 .into(),
@@ -882,9 +1642,9 @@ mod tests {
             .exec(
                 vec![
                     StmtInstruction::DefineFunction {
-                        name: 'f',
+                        name: "f".into(),
                         function: Function {
-                            name: 'f',
+                            name: "f".into(),
                             body: [StmtInstruction::ReturnExpr(ExprInstruction::Number(
                                 "5".to_string(),
                             ))]
@@ -893,8 +1653,9 @@ mod tests {
                         },
                     },
                     StmtInstruction::Expr(ExprInstruction::Call {
-                        name: 'f',
+                        name: "f".into(),
                         args: vec![],
+                        span: Span::default(),
                     }),
                 ]
                 .into(),
@@ -919,6 +1680,8 @@ mod tests {
                     body: vec![StmtInstruction::Expr(ExprInstruction::Number(
                         "5".to_string(),
                     ))],
+                    else_instruction_count: 0,
+                    else_body: vec![],
                 }]
                 .into(),
             )
@@ -942,6 +1705,8 @@ mod tests {
                     body: vec![StmtInstruction::Expr(ExprInstruction::Number(
                         "5".to_string(),
                     ))],
+                    else_instruction_count: 0,
+                    else_body: vec![],
                 }]
                 .into(),
             )
@@ -950,21 +1715,108 @@ mod tests {
     }
 
     #[test]
-    fn test_assignment_does_not_print() {
+    fn test_if_else_true_branch() {
         let mut interpreter = Interpreter::default();
         // ```
-        // a = 5
+        // if (1) 5 else 6
         // ```
         let output = interpreter
             .exec(
-                vec![StmtInstruction::Expr(ExprInstruction::Assignment {
-                    named: NamedExpr::VariableNumber('a'),
-                    value: Box::new(ExprInstruction::Number("5".to_string())),
-                })]
+                vec![StmtInstruction::If {
+                    condition: ConditionInstruction::Expr(ExprInstruction::Number("1".to_string())),
+                    instruction_count: 1,
+                    body: vec![StmtInstruction::Expr(ExprInstruction::Number(
+                        "5".to_string(),
+                    ))],
+                    else_instruction_count: 1,
+                    else_body: vec![StmtInstruction::Expr(ExprInstruction::Number(
+                        "6".to_string(),
+                    ))],
+                }]
                 .into(),
             )
             .unwrap();
-        assert_eq!(output, "");
+        assert_eq!(output, "5\n");
+    }
+
+    #[test]
+    fn test_if_else_false_branch() {
+        let mut interpreter = Interpreter::default();
+        // ```
+        // if (0) 5 else 6
+        // ```
+        let output = interpreter
+            .exec(
+                vec![StmtInstruction::If {
+                    condition: ConditionInstruction::Expr(ExprInstruction::Number("0".to_string())),
+                    instruction_count: 1,
+                    body: vec![StmtInstruction::Expr(ExprInstruction::Number(
+                        "5".to_string(),
+                    ))],
+                    else_instruction_count: 1,
+                    else_body: vec![StmtInstruction::Expr(ExprInstruction::Number(
+                        "6".to_string(),
+                    ))],
+                }]
+                .into(),
+            )
+            .unwrap();
+        assert_eq!(output, "6\n");
+    }
+
+    #[test]
+    fn test_else_if_chain() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program(
+            "x = 2\nif (x == 1) 10 else if (x == 2) 20 else 30\nif (x > 5) {\n 1\n} else\n{\n 2\n}\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "20\n2\n");
+    }
+
+    #[test]
+    fn test_relational_expressions_are_values() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program(
+            "a = 1; b = 2\nx = (a < b) + 1; x\na == b\na <= 1\n(a >= b) * 5\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "2\n0\n1\n0\n");
+    }
+
+    #[test]
+    fn test_boolean_operators() {
+        let mut interpreter = Interpreter::default();
+        let program =
+            parse_program("!0\n!5\n2 && 3\n2 && 0\n0 || 0\n0 || -1\n!1 == 1\n", None).unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "1\n0\n1\n0\n0\n1\n0\n");
+    }
+
+    #[test]
+    fn test_boolean_operators_short_circuit() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program("0 && 1 / 0\n1 || x++\nx\n1 && x++\nx\n", None).unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "0\n1\n0\n0\n1\n");
+    }
+
+    #[test]
+    fn test_assignment_does_not_print() {
+        let mut interpreter = Interpreter::default();
+        // ```
+        // a = 5
+        // ```
+        let output = interpreter
+            .exec(
+                vec![StmtInstruction::Expr(ExprInstruction::Assignment {
+                    named: NamedExpr::VariableNumber("a".into()),
+                    value: Box::new(ExprInstruction::Number("5".to_string())),
+                })]
+                .into(),
+            )
+            .unwrap();
+        assert_eq!(output, "");
     }
 
     #[test]
@@ -979,13 +1831,13 @@ mod tests {
                 vec![
                     StmtInstruction::Expr(ExprInstruction::Assignment {
                         named: NamedExpr::ArrayItem {
-                            name: 'a',
+                            name: "a".into(),
                             index: Box::new(ExprInstruction::Number("0".to_string())),
                         },
                         value: Box::new(ExprInstruction::Number("5".to_string())),
                     }),
                     StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::ArrayItem {
-                        name: 'a',
+                        name: "a".into(),
                         index: Box::new(ExprInstruction::Number("0".to_string())),
                     })),
                 ]
@@ -1008,9 +1860,9 @@ mod tests {
             .exec(
                 vec![
                     StmtInstruction::DefineFunction {
-                        name: 'f',
+                        name: "f".into(),
                         function: Function {
-                            name: 'f',
+                            name: "f".into(),
                             body: [StmtInstruction::Quit].into(),
                             ..Default::default()
                         },
@@ -1046,6 +1898,8 @@ mod tests {
                             StmtInstruction::Expr(ExprInstruction::Number("1".to_string())),
                             StmtInstruction::Quit,
                         ],
+                        else_instruction_count: 0,
+                        else_body: vec![],
                     },
                     StmtInstruction::Expr(ExprInstruction::Number("1".to_string())),
                 ]
@@ -1103,12 +1957,12 @@ mod tests {
             .exec(
                 vec![
                     StmtInstruction::DefineFunction {
-                        name: 'f',
+                        name: "f".into(),
                         function: Function {
-                            name: 'f',
-                            locals: [Variable::Number('a')].into(),
+                            name: "f".into(),
+                            locals: [Variable::Number("a".into())].into(),
                             body: [StmtInstruction::Expr(ExprInstruction::Assignment {
-                                named: NamedExpr::VariableNumber('a'),
+                                named: NamedExpr::VariableNumber("a".into()),
                                 value: Box::new(ExprInstruction::Number("5".to_string())),
                             })]
                             .into(),
@@ -1116,10 +1970,13 @@ mod tests {
                         },
                     },
                     StmtInstruction::Expr(ExprInstruction::Call {
-                        name: 'f',
+                        name: "f".into(),
                         args: vec![],
+                        span: Span::default(),
                     }),
-                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
+                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into(),
+                    ))),
                 ]
                 .into(),
             )
@@ -1142,12 +1999,12 @@ mod tests {
             .exec(
                 vec![
                     StmtInstruction::DefineFunction {
-                        name: 'f',
+                        name: "f".into(),
                         function: Function {
-                            name: 'f',
-                            parameters: [Variable::Number('a')].into(),
+                            name: "f".into(),
+                            parameters: [Variable::Number("a".into())].into(),
                             body: [StmtInstruction::Expr(ExprInstruction::Assignment {
-                                named: NamedExpr::VariableNumber('a'),
+                                named: NamedExpr::VariableNumber("a".into()),
                                 value: Box::new(ExprInstruction::Number("5".to_string())),
                             })]
                             .into(),
@@ -1155,16 +2012,19 @@ mod tests {
                         },
                     },
                     StmtInstruction::Expr(ExprInstruction::Assignment {
-                        named: NamedExpr::VariableNumber('a'),
+                        named: NamedExpr::VariableNumber("a".into()),
                         value: Box::new(ExprInstruction::Number("1".to_string())),
                     }),
                     StmtInstruction::Expr(ExprInstruction::Call {
-                        name: 'f',
+                        name: "f".into(),
                         args: vec![FunctionArgument::Expr(ExprInstruction::Named(
-                            NamedExpr::VariableNumber('a'),
+                            NamedExpr::VariableNumber("a".into()),
                         ))],
+                        span: Span::default(),
                     }),
-                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
+                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into(),
+                    ))),
                 ]
                 .into(),
             )
@@ -1185,22 +2045,23 @@ mod tests {
             .exec(
                 vec![
                     StmtInstruction::DefineFunction {
-                        name: 'f',
+                        name: "f".into(),
                         function: Function {
-                            name: 'f',
-                            parameters: [Variable::Number('a')].into(),
+                            name: "f".into(),
+                            parameters: [Variable::Number("a".into())].into(),
                             body: [StmtInstruction::ReturnExpr(ExprInstruction::Named(
-                                NamedExpr::VariableNumber('a'),
+                                NamedExpr::VariableNumber("a".into()),
                             ))]
                             .into(),
                             ..Default::default()
                         },
                     },
                     StmtInstruction::Expr(ExprInstruction::Call {
-                        name: 'f',
+                        name: "f".into(),
                         args: vec![FunctionArgument::Expr(ExprInstruction::Number(
                             "5".to_string(),
                         ))],
+                        span: Span::default(),
                     }),
                 ]
                 .into(),
@@ -1225,20 +2086,20 @@ mod tests {
             .exec(
                 vec![
                     StmtInstruction::DefineFunction {
-                        name: 'f',
+                        name: "f".into(),
                         function: Function {
-                            name: 'f',
-                            parameters: [Variable::Array('a')].into(),
+                            name: "f".into(),
+                            parameters: [Variable::Array("a".into())].into(),
                             body: [
                                 StmtInstruction::Expr(ExprInstruction::Named(
                                     NamedExpr::ArrayItem {
-                                        name: 'a',
+                                        name: "a".into(),
                                         index: Box::new(ExprInstruction::Number("0".to_string())),
                                     },
                                 )),
                                 StmtInstruction::Expr(ExprInstruction::Assignment {
                                     named: NamedExpr::ArrayItem {
-                                        name: 'a',
+                                        name: "a".into(),
                                         index: Box::new(ExprInstruction::Number("0".to_string())),
                                     },
                                     value: Box::new(ExprInstruction::Number("5".to_string())),
@@ -1250,17 +2111,18 @@ mod tests {
                     },
                     StmtInstruction::Expr(ExprInstruction::Assignment {
                         named: NamedExpr::ArrayItem {
-                            name: 'a',
+                            name: "a".into(),
                             index: Box::new(ExprInstruction::Number("0".to_string())),
                         },
                         value: Box::new(ExprInstruction::Number("1".to_string())),
                     }),
                     StmtInstruction::Expr(ExprInstruction::Call {
-                        name: 'f',
-                        args: vec![FunctionArgument::ArrayVariable('a')],
+                        name: "f".into(),
+                        args: vec![FunctionArgument::ArrayVariable("a".into())],
+                        span: Span::default(),
                     }),
                     StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::ArrayItem {
-                        name: 'a',
+                        name: "a".into(),
                         index: Box::new(ExprInstruction::Number("0".to_string())),
                     })),
                 ]
@@ -1300,8 +2162,9 @@ mod tests {
         // ```
         let output = interpreter.exec(Program {
             instructions: vec![StmtInstruction::Expr(ExprInstruction::Call {
-                name: 'f',
+                name: "f".into(),
                 args: vec![],
+                span: Span::default(),
             })],
             source_locations: vec![1],
             file: "".into(),
@@ -1324,12 +2187,12 @@ mod tests {
             .exec(Program {
                 instructions: vec![
                     StmtInstruction::Expr(ExprInstruction::Assignment {
-                        named: NamedExpr::VariableNumber('i'),
+                        named: NamedExpr::VariableNumber("i".into()),
                         value: Box::new(ExprInstruction::Number("1".to_string())),
                     }),
                     StmtInstruction::While {
                         condition: ConditionInstruction::Gt(
-                            ExprInstruction::Named(NamedExpr::VariableNumber('i')),
+                            ExprInstruction::Named(NamedExpr::VariableNumber("i".into())),
                             ExprInstruction::UnaryMinus(Box::new(ExprInstruction::Number(
                                 "1".to_string(),
                             ))),
@@ -1339,10 +2202,13 @@ mod tests {
                             StmtInstruction::Expr(ExprInstruction::Number("1".to_string())),
                             StmtInstruction::Expr(ExprInstruction::Div(
                                 Box::new(ExprInstruction::Number("1".to_string())),
-                                Box::new(ExprInstruction::Named(NamedExpr::VariableNumber('i'))),
+                                Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                                    "i".into(),
+                                ))),
+                                Span::default(),
                             )),
                             StmtInstruction::Expr(ExprInstruction::PreDecrement(
-                                NamedExpr::VariableNumber('i'),
+                                NamedExpr::VariableNumber("i".into()),
                             )),
                         ],
                     },
@@ -1368,22 +2234,23 @@ mod tests {
             .exec(Program {
                 instructions: vec![
                     StmtInstruction::Expr(ExprInstruction::Assignment {
-                        named: NamedExpr::VariableNumber('i'),
+                        named: NamedExpr::VariableNumber("i".into()),
                         value: Box::new(ExprInstruction::Number("0".to_string())),
                     }),
                     StmtInstruction::While {
                         condition: ConditionInstruction::Lt(
-                            ExprInstruction::Named(NamedExpr::VariableNumber('i')),
+                            ExprInstruction::Named(NamedExpr::VariableNumber("i".into())),
                             ExprInstruction::Number("10".to_string()),
                         ),
                         instruction_count: 1,
                         body: vec![StmtInstruction::Expr(ExprInstruction::PreIncrement(
-                            NamedExpr::VariableNumber('i'),
+                            NamedExpr::VariableNumber("i".into()),
                         ))],
                     },
                     StmtInstruction::Expr(ExprInstruction::Pow(
                         Box::new(ExprInstruction::Number("1".to_string())),
                         Box::new(ExprInstruction::Number("2.2".to_string())),
+                        Span::default(),
                     )),
                 ],
                 source_locations: vec![1, 2, 3, 4],
@@ -1423,6 +2290,7 @@ mod tests {
                     StmtInstruction::Expr(ExprInstruction::Pow(
                         Box::new(ExprInstruction::Number("1".to_string())),
                         Box::new(ExprInstruction::Number("2.2".to_string())),
+                        Span::default(),
                     )),
                 ],
                 source_locations: vec![1, 2, 3, 4, 5, 6],
@@ -1446,22 +2314,25 @@ mod tests {
             .exec(Program {
                 instructions: vec![StmtInstruction::For {
                     init: ExprInstruction::Assignment {
-                        named: NamedExpr::VariableNumber('a'),
+                        named: NamedExpr::VariableNumber("a".into()),
                         value: Box::new(ExprInstruction::Number("0".to_string())),
                     },
                     condition: ConditionInstruction::Gt(
-                        ExprInstruction::Named(NamedExpr::VariableNumber('a')),
+                        ExprInstruction::Named(NamedExpr::VariableNumber("a".into())),
                         ExprInstruction::UnaryMinus(Box::new(ExprInstruction::Number(
                             "1".to_string(),
                         ))),
                     ),
-                    update: ExprInstruction::PreDecrement(NamedExpr::VariableNumber('a')),
+                    update: ExprInstruction::PreDecrement(NamedExpr::VariableNumber("a".into())),
                     instruction_count: 3,
                     body: vec![
                         StmtInstruction::Expr(ExprInstruction::Number("1".to_string())),
                         StmtInstruction::Expr(ExprInstruction::Div(
                             Box::new(ExprInstruction::Number("1".to_string())),
-                            Box::new(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
+                            Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                                "a".into(),
+                            ))),
+                            Span::default(),
                         )),
                         StmtInstruction::Expr(ExprInstruction::Number("2".to_string())),
                     ],
@@ -1487,14 +2358,16 @@ mod tests {
                 instructions: vec![
                     StmtInstruction::For {
                         init: ExprInstruction::Assignment {
-                            named: NamedExpr::VariableNumber('a'),
+                            named: NamedExpr::VariableNumber("a".into()),
                             value: Box::new(ExprInstruction::Number("0".to_string())),
                         },
                         condition: ConditionInstruction::Lt(
-                            ExprInstruction::Named(NamedExpr::VariableNumber('a')),
+                            ExprInstruction::Named(NamedExpr::VariableNumber("a".into())),
                             ExprInstruction::Number("5".to_string()),
                         ),
-                        update: ExprInstruction::PostIncrement(NamedExpr::VariableNumber('a')),
+                        update: ExprInstruction::PostIncrement(NamedExpr::VariableNumber(
+                            "a".into(),
+                        )),
                         instruction_count: 1,
                         body: vec![StmtInstruction::Expr(ExprInstruction::Number(
                             "1".to_string(),
@@ -1503,6 +2376,7 @@ mod tests {
                     StmtInstruction::Expr(ExprInstruction::Pow(
                         Box::new(ExprInstruction::Number("1".to_string())),
                         Box::new(ExprInstruction::Number("2.2".to_string())),
+                        Span::default(),
                     )),
                 ],
                 source_locations: vec![1, 2, 4],
@@ -1529,13 +2403,15 @@ mod tests {
                 instructions: vec![
                     StmtInstruction::For {
                         init: ExprInstruction::Assignment {
-                            named: NamedExpr::VariableNumber('a'),
+                            named: NamedExpr::VariableNumber("a".into()),
                             value: Box::new(ExprInstruction::Number("0".to_string())),
                         },
                         condition: ConditionInstruction::Expr(ExprInstruction::Number(
                             "0".to_string(),
                         )),
-                        update: ExprInstruction::PostIncrement(NamedExpr::VariableNumber('a')),
+                        update: ExprInstruction::PostIncrement(NamedExpr::VariableNumber(
+                            "a".into(),
+                        )),
                         instruction_count: 4,
                         body: vec![
                             StmtInstruction::Expr(ExprInstruction::Number("1".to_string())),
@@ -1547,6 +2423,7 @@ mod tests {
                     StmtInstruction::Expr(ExprInstruction::Pow(
                         Box::new(ExprInstruction::Number("1".to_string())),
                         Box::new(ExprInstruction::Number("2.2".to_string())),
+                        Span::default(),
                     )),
                 ],
                 source_locations: vec![1, 2, 3, 4, 5, 7],
@@ -1575,8 +2452,11 @@ mod tests {
                         StmtInstruction::Expr(ExprInstruction::Div(
                             Box::new(ExprInstruction::Number("1".to_string())),
                             Box::new(ExprInstruction::Number("0".to_string())),
+                            Span::default(),
                         )),
                     ],
+                    else_instruction_count: 0,
+                    else_body: vec![],
                 }],
                 source_locations: vec![1, 2, 3],
                 file: "".into(),
@@ -1605,10 +2485,13 @@ mod tests {
                         body: vec![StmtInstruction::Expr(ExprInstruction::Number(
                             "1".to_string(),
                         ))],
+                        else_instruction_count: 0,
+                        else_body: vec![],
                     },
                     StmtInstruction::Expr(ExprInstruction::Pow(
                         Box::new(ExprInstruction::Number("1".to_string())),
                         Box::new(ExprInstruction::Number("2.2".to_string())),
+                        Span::default(),
                     )),
                 ],
                 source_locations: vec![1, 2, 3],
@@ -1644,10 +2527,13 @@ mod tests {
                             StmtInstruction::Expr(ExprInstruction::Number("3".to_string())),
                             StmtInstruction::Expr(ExprInstruction::Number("4".to_string())),
                         ],
+                        else_instruction_count: 0,
+                        else_body: vec![],
                     },
                     StmtInstruction::Expr(ExprInstruction::Pow(
                         Box::new(ExprInstruction::Number("1".to_string())),
                         Box::new(ExprInstruction::Number("2.2".to_string())),
+                        Span::default(),
                     )),
                 ],
                 source_locations: vec![1, 2, 3, 4, 5, 7],
@@ -1657,6 +2543,64 @@ mod tests {
         assert_eq!(err.call_stack[0].line, 7);
     }
 
+    #[test]
+    fn test_error_inside_else_reports_correct_line() {
+        let mut interpreter = Interpreter::default();
+        // ```
+        // if (0) {
+        //   1
+        //   2
+        // } else {
+        //   3
+        //   1 ^ 2.2
+        // }
+        // ```
+        let err = interpreter
+            .exec(Program {
+                instructions: vec![StmtInstruction::If {
+                    condition: ConditionInstruction::Expr(ExprInstruction::Number("0".to_string())),
+                    instruction_count: 2,
+                    body: vec![
+                        StmtInstruction::Expr(ExprInstruction::Number("1".to_string())),
+                        StmtInstruction::Expr(ExprInstruction::Number("2".to_string())),
+                    ],
+                    else_instruction_count: 2,
+                    else_body: vec![
+                        StmtInstruction::Expr(ExprInstruction::Number("3".to_string())),
+                        StmtInstruction::Expr(ExprInstruction::Pow(
+                            Box::new(ExprInstruction::Number("1".to_string())),
+                            Box::new(ExprInstruction::Number("2.2".to_string())),
+                            Span::default(),
+                        )),
+                    ],
+                }],
+                source_locations: vec![1, 2, 3, 5, 6],
+                file: "".into(),
+            })
+            .expect_err("expected error");
+        assert_eq!(err.call_stack[0].line, 6);
+    }
+
+    #[test]
+    fn test_error_after_if_else_reports_correct_line() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program("if (1) {\n 1\n} else {\n 2\n 3\n}\n1 ^ 2.2\n", None).unwrap();
+        let err = interpreter.exec(program).expect_err("expected error");
+        assert_eq!(err.call_stack[0].line, 7);
+    }
+
+    #[test]
+    fn test_error_after_loop_with_nested_if_reports_correct_line() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program(
+            "while (i < 1) {\n if (1) {\n 1\n } else 2\n i = 1\n}\n1 ^ 2.2\n",
+            None,
+        )
+        .unwrap();
+        let err = interpreter.exec(program).expect_err("expected error");
+        assert_eq!(err.call_stack[0].line, 7);
+    }
+
     #[test]
     fn test_function_call_errors_report_correct_lines() {
         let mut interpreter = Interpreter::default();
@@ -1670,21 +2614,23 @@ mod tests {
             .exec(Program {
                 instructions: vec![
                     StmtInstruction::DefineFunction {
-                        name: 'f',
+                        name: "f".into(),
                         function: Function {
-                            name: 'f',
+                            name: "f".into(),
                             source_locations: [2].into(),
                             body: [StmtInstruction::Expr(ExprInstruction::Pow(
                                 Box::new(ExprInstruction::Number("1".to_string())),
                                 Box::new(ExprInstruction::Number("2.2".to_string())),
+                                Span::default(),
                             ))]
                             .into(),
                             ..Default::default()
                         },
                     },
                     StmtInstruction::Expr(ExprInstruction::Call {
-                        name: 'f',
+                        name: "f".into(),
                         args: vec![],
+                        span: Span::default(),
                     }),
                 ],
                 source_locations: vec![4],
@@ -1694,4 +2640,897 @@ mod tests {
         assert_eq!(err.call_stack[0].line, 2);
         assert_eq!(err.call_stack[1].line, 4);
     }
+
+    #[test]
+    fn test_math_library_is_loaded() {
+        let mut interpreter = Interpreter::with_math_library();
+        let program = parse_program("scale\ns(0)\n4 * a(1)\n", None).unwrap();
+        let output = interpreter.exec(program).unwrap();
+        assert!(output.starts_with("20\n0\n3.141592653589793238"));
+    }
+
+    fn exec_with_math_library(text: &str) -> ExecutionResult<String> {
+        let mut interpreter = Interpreter::with_math_library();
+        interpreter.exec(parse_program(text, None).unwrap())
+    }
+
+    #[test]
+    fn test_math_library_functions_are_native() {
+        let output = exec_with_math_library("scale = 40\ns(1)\nc(1)\na(1)\nl(2)\ne(1)\n").unwrap();
+        assert_eq!(
+            output,
+            "0.8414709848078965066525023216302989996225\n\
+             0.5403023058681397174009366074429766037323\n\
+             0.7853981633974483096156608458198757210492\n\
+             0.6931471805599453094172321214581765680755\n\
+             2.7182818284590452353602874713526624977572\n"
+        );
+    }
+
+    #[test]
+    fn test_math_library_functions_can_be_redefined() {
+        let output = exec_with_math_library("define s(x) { return (x * 2); }\ns(3)\n").unwrap();
+        assert_eq!(output, "6\n");
+    }
+
+    #[test]
+    fn test_native_ln_of_non_positive_number() {
+        // the same result as the bc definition, and GNU bc
+        let output = exec_with_math_library("scale = 5\nl(0)\nl(-1)\n").unwrap();
+        assert_eq!(output, "-99999\n-99999\n");
+    }
+
+    #[test]
+    fn test_native_function_with_wrong_arguments_is_error() {
+        let err = exec_with_math_library("s(1, 2)\n").expect_err("expected error");
+        assert_eq!(
+            *err.error,
+            BcError::ArgumentMismatch {
+                function: "s".into()
+            }
+        );
+    }
+
+    #[test]
+    fn test_native_exp_checks_digits_before_computing() {
+        let mut interpreter = Interpreter::with_math_library();
+        interpreter.set_limits(ExecutionLimits {
+            max_digits: Some(1000),
+            ..Default::default()
+        });
+        let err = interpreter
+            .exec(parse_program("e(100000000)\n", None).unwrap())
+            .expect_err("expected error");
+        assert!(err.is_aborted());
+    }
+
+    #[test]
+    fn test_native_functions_of_huge_numbers() {
+        let output = exec_with_math_library("s(10^20)\nc(-10^20)\n").unwrap();
+        assert_eq!(output, "-0.64525128526578084420\n0.76397040444172830040\n");
+    }
+
+    #[test]
+    fn test_native_functions_check_the_limits_while_computing() {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut interpreter = Interpreter::with_math_library();
+        interpreter.set_limits(ExecutionLimits {
+            cancel: Some(cancel.clone()),
+            ..Default::default()
+        });
+        // cancels in the middle of the statement that calls `a`
+        interpreter.register_function("stop", move |_| {
+            cancel.store(true, Ordering::Relaxed);
+            Ok(Number::zero())
+        });
+        let err = interpreter
+            .exec(parse_program("scale = 10000; x = stop() + a(0.3)\n", None).unwrap())
+            .expect_err("expected error");
+        assert!(err.is_aborted());
+        assert_eq!(*err.error(), BcError::Cancelled);
+    }
+
+    fn exec_with_limits(text: &str, limits: ExecutionLimits) -> ExecutionResult<String> {
+        let mut interpreter = Interpreter::default();
+        interpreter.set_limits(limits);
+        interpreter.exec(parse_program(text, None).unwrap())
+    }
+
+    #[test]
+    fn test_statement_limit_aborts_infinite_loop() {
+        let limits = ExecutionLimits {
+            max_statements: Some(1000),
+            ..Default::default()
+        };
+        let err = exec_with_limits("while(1){}\n", limits.clone()).expect_err("expected error");
+        assert!(err.is_aborted());
+        let output = exec_with_limits("for(i = 0; i < 10; ++i) i\n", limits).unwrap();
+        assert_eq!(output, "0\n1\n2\n3\n4\n5\n6\n7\n8\n9\n");
+    }
+
+    #[test]
+    fn test_call_depth_limit_aborts_infinite_recursion() {
+        let limits = ExecutionLimits {
+            max_call_depth: Some(50),
+            ..Default::default()
+        };
+        let err = exec_with_limits("define f(x) {\nreturn (f(x))\n}\nf(1)\n", limits)
+            .expect_err("expected error");
+        assert!(err.is_aborted());
+    }
+
+    #[test]
+    fn test_digit_limit_aborts_large_numbers() {
+        let limits = ExecutionLimits {
+            max_digits: Some(1000),
+            ..Default::default()
+        };
+        let err = exec_with_limits("2^99999999\n", limits.clone()).expect_err("expected error");
+        assert!(err.is_aborted());
+        let err = exec_with_limits("scale = 5000\n", limits.clone()).expect_err("expected error");
+        assert!(err.is_aborted());
+        let err =
+            exec_with_limits("a = 10^900\na * a\n", limits.clone()).expect_err("expected error");
+        assert!(err.is_aborted());
+        let output = exec_with_limits("2^100\n", limits).unwrap();
+        assert_eq!(output, "1267650600228229401496703205376\n");
+    }
+
+    #[test]
+    fn test_digit_limit_allows_small_powers_with_huge_exponents() {
+        let limits = ExecutionLimits {
+            max_digits: Some(1000),
+            timeout: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let output = exec_with_limits("2^-99999999\n", limits.clone()).unwrap();
+        assert_eq!(output, "0\n");
+        let output = exec_with_limits("0.5^99999999\n", limits.clone()).unwrap();
+        assert_eq!(output, "0\n");
+        let output = exec_with_limits("scale = 20\n1.0000001^-99999999\n", limits).unwrap();
+        assert_eq!(output, "0.00004539995700244914\n");
+    }
+
+    #[test]
+    fn test_timeout_aborts_execution() {
+        let limits = ExecutionLimits {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let err = exec_with_limits("while(1){}\n", limits).expect_err("expected error");
+        assert!(err.is_aborted());
+    }
+
+    #[test]
+    fn test_cancel_aborts_execution() {
+        let cancel = Arc::new(AtomicBool::new(true));
+        let limits = ExecutionLimits {
+            cancel: Some(cancel),
+            ..Default::default()
+        };
+        let err = exec_with_limits("while(1){}\n", limits).expect_err("expected error");
+        assert!(err.is_aborted());
+    }
+
+    #[test]
+    fn test_runtime_errors_are_not_aborted() {
+        let err =
+            exec_with_limits("1 / 0\n", ExecutionLimits::default()).expect_err("expected error");
+        assert!(!err.is_aborted());
+    }
+
+    #[test]
+    fn test_print_statement() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program(
+            "t = 12.5; obase = 16\nprint \"total:\\t\", t, \"\\n\", \"\\\"q\\\"\\\\\"\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "total:\tC.8\n\"q\"\\");
+    }
+
+    #[test]
+    fn test_last_is_the_last_printed_value() {
+        let mut interpreter = Interpreter::default();
+        let program =
+            parse_program("last\n2 + 3\nx = 4\nlast * 2\n. + 1\n\"text\"\n.\n", None).unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "0\n5\n10\n11\ntext11\n");
+        let program = parse_program("last\n", None).unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "11\n");
+    }
+
+    #[test]
+    fn test_last_is_assignable() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program("last = 7\n++.\nlast += 2; last\n", None).unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "8\n10\n");
+        interpreter.set_last(Number::from(255));
+        let program = parse_program(". + 1\n", None).unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "256\n");
+    }
+
+    #[test]
+    fn test_continue_in_loops() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program(
+            concat!(
+                "for (i = 0; i < 5; i++) {\n",
+                "  if (i == 2) continue\n",
+                "  i\n",
+                "}\n",
+                "while (j < 4) {\n",
+                "  j += 1\n",
+                "  if (j % 2) continue\n",
+                "  j\n",
+                "}\n",
+            ),
+            None,
+        )
+        .unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "0\n1\n3\n4\n2\n4\n");
+    }
+
+    #[test]
+    fn test_error_after_loop_with_continue_reports_correct_line() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program(
+            "while (i < 3) {\n i += 1\n if (i < 3) {\n continue\n }\n i\n}\n1 ^ 2.2\n",
+            None,
+        )
+        .unwrap();
+        let err = interpreter.exec(program).expect_err("expected error");
+        assert_eq!(err.partial_output(), "3\n");
+        assert_eq!(err.call_stack[0].line, 8);
+    }
+
+    #[test]
+    fn test_error_inside_loop_after_continue_reports_correct_line() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program(
+            "for (i = 0; i < 3; i++) {\n if (i == 0) continue\n 1 / (i - 1)\n}\n",
+            None,
+        )
+        .unwrap();
+        let err = interpreter.exec(program).expect_err("expected error");
+        assert_eq!(err.call_stack[0].line, 3);
+    }
+
+    #[test]
+    fn test_halt_stops_execution() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program(
+            "define f(x) {\n if (x > 2) halt\n return (x)\n}\nf(1)\nf(3)\n5\n",
+            None,
+        )
+        .unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "1\n");
+        assert!(interpreter.has_quit());
+
+        // the interpreter can be used again after a halt
+        assert_eq!(interpreter.eval("f(2) + 1"), Ok(Number::from(3)));
+        assert!(!interpreter.has_quit());
+        assert_eq!(interpreter.eval("halt; 1"), Err(EvalError::Halted));
+        let program = parse_program("f(2)\n", None).unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "2\n");
+    }
+
+    #[test]
+    fn test_unexecuted_halt_does_not_stop_execution() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program("if (0) halt\n5\n", None).unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "5\n");
+        assert!(!interpreter.has_quit());
+    }
+
+    #[test]
+    fn test_limits_statement() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program("limits\n", None).unwrap();
+        let output = interpreter.exec(program).unwrap();
+        assert!(output.starts_with(&format!("BC_BASE_MAX     = {BC_BASE_MAX}\n")));
+        assert!(output.contains(&format!("BC_SCALE_MAX    = {BC_SCALE_MAX}\n")));
+        assert!(!output.contains("MAX_DIGITS"));
+
+        interpreter.set_limits(ExecutionLimits {
+            max_digits: Some(100),
+            max_call_depth: Some(10),
+            ..Default::default()
+        });
+        let program = parse_program("limits\n", None).unwrap();
+        let output = interpreter.exec(program).unwrap();
+        assert!(output.contains("BC_SCALE_MAX    = 100\n"));
+        assert!(output.contains("MAX_CALL_DEPTH  = 10\n"));
+        assert!(output.ends_with("MAX_DIGITS      = 100\n"));
+    }
+
+    #[test]
+    fn test_long_names() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program(
+            concat!(
+                "define tax(amount, rates[]) {\n",
+                "auto total\n",
+                "total = amount * rates[0]\n",
+                "return (total)\n",
+                "}\n",
+                "rates[0] = 3; total = 1; a = 2\n",
+                "tax(5, rates[])\n",
+                "total + a\n",
+            ),
+            None,
+        )
+        .unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "15\n3\n");
+    }
+
+    #[test]
+    fn test_state_is_kept_between_programs() {
+        let mut interpreter = Interpreter::default();
+        let program = "a = 5\nb[2] = 3\nscale = 2\ndefine f(x) { return (x * 2) }\n";
+        interpreter
+            .exec(parse_program(program, None).unwrap())
+            .unwrap();
+        let output = interpreter
+            .exec(parse_program("a * b[2]\nf(a)\nscale\n", None).unwrap())
+            .unwrap();
+        assert_eq!(output, "15\n10\n2\n");
+    }
+
+    #[test]
+    fn test_dump_state_restores_globals_and_functions() {
+        let mut interpreter = Interpreter::default();
+        let program = concat!(
+            "a = -5.50\nb[3] = 2\nscale = 4\n",
+            "define f(x, y[]) { auto z; z = x * y[3]; return (z) }\n",
+            "obase = 16\nibase = 16\n",
+        );
+        interpreter
+            .exec(parse_program(program, None).unwrap())
+            .unwrap();
+        let state = interpreter.dump_state();
+
+        let mut restored = Interpreter::default();
+        restored.exec(parse_program(&state, None).unwrap()).unwrap();
+        assert_eq!(restored.dump_state(), state);
+        let output = restored
+            .exec(parse_program("f(a, b[])\nscale\n", None).unwrap())
+            .unwrap();
+        assert_eq!(output, "-B.00\n4\n");
+        assert_eq!(restored.global_variable_names(), ["a"]);
+    }
+
+    #[test]
+    fn test_dump_state_skips_math_library() {
+        let interpreter = Interpreter::with_math_library();
+        assert_eq!(
+            interpreter.dump_state(),
+            "ibase = A\nscale = 20\nobase = 10\nibase = 10\n"
+        );
+    }
+
+    #[test]
+    fn test_load_file() {
+        let path = std::env::temp_dir().join("bc_test_load_file.bc");
+        std::fs::write(&path, "define d(x) { return (2 * x) }\nd(21)").unwrap();
+        let mut interpreter = Interpreter::default();
+        let output = interpreter
+            .load_file(&path, &ParseOptions::default())
+            .unwrap();
+        assert_eq!(output, "42\n");
+
+        std::fs::write(&path, "1 + 1\n2 +* 2\n").unwrap();
+        let err = interpreter
+            .load_file(&path, &ParseOptions::default())
+            .expect_err("expected error");
+        std::fs::remove_file(&path).unwrap();
+        match err {
+            LoadError::Parse(e) => assert!(e.to_string().contains("2:")),
+            _ => panic!("expected parse error"),
+        }
+    }
+
+    #[test]
+    fn test_saved_state_with_extensions_is_loaded_with_the_same_options() {
+        let options = ParseOptions {
+            bitwise_operators: true,
+            exponent_literals: true,
+        };
+        let mut interpreter = Interpreter::default();
+        let program = "define f(x) { return (x & 1e1) }\n";
+        interpreter
+            .exec(parse_program_with_options(program, None, &options).unwrap())
+            .unwrap();
+        let path = std::env::temp_dir().join("bc_test_saved_extensions.bc");
+        interpreter.save_state(&path).unwrap();
+
+        let mut restored = Interpreter::default();
+        let loaded = restored.load_file(&path, &options);
+        let without_options = Interpreter::default().load_file(&path, &ParseOptions::default());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), "");
+        assert!(matches!(without_options, Err(LoadError::Parse(_))));
+        let output = restored
+            .exec(parse_program("f(3); f(15)\n", None).unwrap())
+            .unwrap();
+        assert_eq!(output, "2\n10\n");
+    }
+
+    fn error_span(text: &str) -> Option<Span> {
+        let mut interpreter = Interpreter::default();
+        interpreter
+            .exec(parse_program(text, None).unwrap())
+            .expect_err("expected error")
+            .span()
+    }
+
+    #[test]
+    fn test_errors_of_operators_have_the_span_of_the_operator() {
+        assert_eq!(error_span("x = 1\nx + 2 / 0\n"), Some(Span::new(12, 13)));
+        assert_eq!(error_span("5 % (1 - 1)\n"), Some(Span::new(2, 3)));
+        assert_eq!(error_span("2 ^ 0.5\n"), Some(Span::new(2, 3)));
+        assert_eq!(error_span("x = 1; x /= 0\n"), Some(Span::new(9, 11)));
+    }
+
+    #[test]
+    fn test_errors_of_calls_have_the_span_of_the_call() {
+        assert_eq!(error_span("1 + foo(2)\n"), Some(Span::new(4, 7)));
+        // errors inside of the function are reported at the call
+        assert_eq!(
+            error_span("define f(x) { return (1 / x) }\n2 * f(0)\n"),
+            Some(Span::new(35, 36))
+        );
+        // errors in the arguments are reported where they occur
+        assert_eq!(
+            error_span("define f(x) { return (x) }\nf(1 / 0)\n"),
+            Some(Span::new(31, 32))
+        );
+    }
+
+    #[test]
+    fn test_error_diagnostic() {
+        let text = "a = 2\nb = a ^ 1.5\n";
+        let mut interpreter = Interpreter::default();
+        let err = interpreter
+            .exec(parse_program(text, None).unwrap())
+            .expect_err("expected error");
+        let diagnostic = err.diagnostic(text).unwrap();
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 7));
+        assert_eq!(
+            diagnostic.render(text),
+            "exponent has to be an integer\nb = a ^ 1.5\n      ^"
+        );
+        assert_eq!(error_span("ibase = 20\n"), None);
+    }
+
+    fn exec_error(text: &str) -> BcError {
+        let mut interpreter = Interpreter::default();
+        interpreter
+            .exec(parse_program(text, None).unwrap())
+            .expect_err("expected error")
+            .error()
+            .clone()
+    }
+
+    #[test]
+    fn test_errors_keep_the_offending_values() {
+        assert_eq!(exec_error("1 / 0\n"), BcError::DivisionByZero);
+        assert_eq!(
+            exec_error("foo(1)\n"),
+            BcError::UndefinedFunction("foo".into())
+        );
+        assert_eq!(
+            exec_error("2 ^ 1.5\n"),
+            BcError::NonIntegerExponent(Number::parse("1.5", 10).unwrap())
+        );
+        assert_eq!(
+            exec_error("a[-1] = 2\n"),
+            BcError::ArrayIndexTooLarge(Number::from(1).negate())
+        );
+        assert_eq!(
+            exec_error("define f(x[]) { }\nf(1)\n"),
+            BcError::ArgumentMismatch {
+                function: "f".into()
+            }
+        );
+        assert_eq!(
+            exec_error("ibase = 2; 12\n"),
+            BcError::InvalidDigit {
+                number: "12".to_string(),
+                ibase: 2
+            }
+        );
+        assert_eq!(
+            exec_error("ibase = 17\n"),
+            BcError::InvalidIBase(Number::from(17))
+        );
+    }
+
+    fn interpreter_with_host_functions() -> Interpreter {
+        let mut interpreter = Interpreter::default();
+        interpreter.register_function("vat", |args| match args {
+            [x] => Ok(x.clone().mul(&Number::parse("1.2", 10).unwrap(), 2)),
+            _ => Err(BcError::Custom("vat takes one argument".to_string())),
+        });
+        interpreter.register_function("answer", |_| Ok(Number::from(42)));
+        interpreter
+    }
+
+    #[test]
+    fn test_call_host_functions() {
+        let mut interpreter = interpreter_with_host_functions();
+        let program = parse_program("vat(10)\nanswer() + 1\nx = vat(answer())\nx\n", None);
+        let output = interpreter.exec(program.unwrap()).unwrap();
+        assert_eq!(output, "12.0\n43\n50.4\n");
+    }
+
+    #[test]
+    fn test_the_latest_of_definition_and_host_function_is_called() {
+        let mut interpreter = interpreter_with_host_functions();
+        let program = parse_program(
+            "define answer() { return (1) }\ndefine f() { return (2) }\nanswer()\nf()\n",
+            None,
+        );
+        let output = interpreter.exec(program.unwrap()).unwrap();
+        assert_eq!(output, "1\n2\n");
+
+        interpreter.register_function("answer", |_| Ok(Number::from(42)));
+        let output = interpreter
+            .exec(parse_program("answer()\n", None).unwrap())
+            .unwrap();
+        assert_eq!(output, "42\n");
+
+        assert!(interpreter.unregister_function("answer"));
+        let output = interpreter
+            .exec(parse_program("answer()\n", None).unwrap())
+            .unwrap();
+        assert_eq!(output, "1\n");
+    }
+
+    #[test]
+    fn test_host_function_errors_are_runtime_errors() {
+        let mut interpreter = interpreter_with_host_functions();
+        let err = interpreter
+            .exec(parse_program("1\n2 * vat(1, 2)\n", None).unwrap())
+            .expect_err("expected error");
+        assert_eq!(
+            *err.error(),
+            BcError::Custom("vat takes one argument".to_string())
+        );
+        assert_eq!(err.span(), Some(Span::new(6, 9)));
+        assert_eq!(
+            err.to_string(),
+            "runtime error (line 2): vat takes one argument"
+        );
+        assert_eq!(err.partial_output(), "1\n");
+
+        let err = interpreter
+            .exec(parse_program("vat(a[])\n", None).unwrap())
+            .expect_err("expected error");
+        assert_eq!(
+            *err.error(),
+            BcError::ArgumentMismatch {
+                function: "vat".into()
+            }
+        );
+    }
+
+    #[test]
+    fn test_random_numbers_are_deterministic_when_seeded() {
+        let text = "x = seed(42)\na = rand(); b = irand(1000); s = seed()\nx = seed(42)\n\
+                    rand() == a; irand(1000) == b; seed() == s\n";
+        let output = Interpreter::default()
+            .exec(parse_program(text, None).unwrap())
+            .unwrap();
+        assert_eq!(output, "1\n1\n1\n");
+
+        let first = Interpreter::default()
+            .exec(parse_program("seed(7); rand(); irand(100)\n", None).unwrap())
+            .unwrap();
+        let second = Interpreter::default()
+            .exec(parse_program("seed(7); rand(); irand(100)\n", None).unwrap())
+            .unwrap();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_random_numbers_are_in_range() {
+        let text = "for (i = 0; i < 200; ++i) {\n\
+                    x = irand(6); if (x < 0 || x > 5 || scale(x) != 0) \"out of range\"\n\
+                    y = rand(); if (y < 0 || y > 4294967295) \"out of range\"\n}\nirand(0)\n";
+        let output = Interpreter::default()
+            .exec(parse_program(text, None).unwrap())
+            .unwrap();
+        assert_eq!(output, "0\n");
+    }
+
+    #[test]
+    fn test_invalid_random_arguments_are_errors() {
+        assert_eq!(
+            exec_error("irand(-1)\n"),
+            BcError::InvalidRandomBound(Number::from(1).negate())
+        );
+        assert_eq!(
+            exec_error("seed(-2)\n"),
+            BcError::InvalidSeed(Number::from(2).negate())
+        );
+        assert_eq!(
+            exec_error("irand()\n"),
+            BcError::ArgumentMismatch {
+                function: "irand".into()
+            }
+        );
+    }
+
+    #[test]
+    fn test_random_functions_give_way_to_definitions_and_variables() {
+        let text = "define rand() { return (4) }\nseed = 3; irand = 2\n\
+                    rand(); seed; irand\nseed(5) == seed(5)\n";
+        let output = Interpreter::default()
+            .exec(parse_program(text, None).unwrap())
+            .unwrap();
+        assert_eq!(output, "4\n3\n2\n1\n");
+    }
+
+    #[test]
+    fn test_deep_recursion_does_not_overflow_the_stack() {
+        let text = "define r(n) {\nif (n == 0) return (0)\nreturn (r(n - 1) + 1)\n}\nr(50000)\n";
+        let output = Interpreter::default()
+            .exec(parse_program(text, None).unwrap())
+            .unwrap();
+        assert_eq!(output, "50000\n");
+    }
+
+    #[test]
+    fn test_arguments_are_evaluated_only_for_parameters() {
+        let text = "define f(x) { return (x) }\ndefine g(x, y) { return (y) }\n\
+                    y = 7\nf(1, ++a); a\ng(1); a\n";
+        let output = Interpreter::default()
+            .exec(parse_program(text, None).unwrap())
+            .unwrap();
+        // a parameter without an argument is the global variable
+        assert_eq!(output, "1\n0\n7\n0\n");
+
+        let mut interpreter = Interpreter::default();
+        let err = interpreter
+            .exec(parse_program("foo(++a)\n", None).unwrap())
+            .expect_err("expected error");
+        assert_eq!(*err.error(), BcError::UndefinedFunction("foo".into()));
+        let output = interpreter
+            .exec(parse_program("a\n", None).unwrap())
+            .unwrap();
+        assert_eq!(output, "0\n");
+    }
+
+    #[test]
+    fn test_errors_in_nested_calls_are_reported_at_each_call() {
+        let text = "define f(x) {\nreturn (1 / x)\n}\ndefine g(x) {\nauto y\ny = f(x)\nreturn (y)\n}\n1\ng(0)\n";
+        let err = Interpreter::default()
+            .exec(parse_program(text, None).unwrap())
+            .expect_err("expected error");
+        let lines: Vec<_> = err.call_stack.iter().map(|call| call.line).collect();
+        assert_eq!(lines, [2, 6, 10]);
+        assert_eq!(err.call_stack[1].function_name.as_ref(), "g");
+        assert_eq!(err.span(), Some(Span::new(76, 77)));
+        assert_eq!(err.partial_output(), "1\n");
+    }
+
+    fn exec_with_bitwise_operators(text: &str) -> ExecutionResult<String> {
+        let options = ParseOptions {
+            bitwise_operators: true,
+            ..Default::default()
+        };
+        Interpreter::default().exec(parse_program_with_options(text, None, &options).unwrap())
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let output = exec_with_bitwise_operators(
+            "x = 172\nx & 15; x | 3; x ^^ 255; ~x; x << 4; x >> 2; -1 & 255; -5 >> 1\n",
+        )
+        .unwrap();
+        assert_eq!(output, "12\n175\n83\n-173\n2752\n43\n255\n-3\n");
+    }
+
+    #[test]
+    fn test_bitwise_operator_errors_have_the_span_of_the_operator() {
+        let err = exec_with_bitwise_operators("x = 1.5\n2 | x\n").unwrap_err();
+        assert_eq!(
+            *err.error(),
+            BcError::NonIntegerOperand(Number::parse("1.5", 10).unwrap())
+        );
+        assert_eq!(err.span(), Some(Span::new(10, 11)));
+
+        let err = exec_with_bitwise_operators("1 >> -1\n").unwrap_err();
+        assert_eq!(
+            *err.error(),
+            BcError::InvalidShift(Number::from(1).negate())
+        );
+        assert_eq!(err.span(), Some(Span::new(2, 4)));
+    }
+
+    #[test]
+    fn test_shift_is_checked_against_digit_limit() {
+        let options = ParseOptions {
+            bitwise_operators: true,
+            ..Default::default()
+        };
+        let mut interpreter = Interpreter::default();
+        interpreter.set_limits(ExecutionLimits {
+            max_digits: Some(100),
+            ..Default::default()
+        });
+        let program = parse_program_with_options("1 << 4000000000\n", None, &options).unwrap();
+        let err = interpreter.exec(program).unwrap_err();
+        assert_eq!(*err.error(), BcError::TooManyDigits);
+    }
+
+    fn exec_with_exponent_literals(interpreter: &mut Interpreter, text: &str) -> String {
+        let options = ParseOptions {
+            exponent_literals: true,
+            ..Default::default()
+        };
+        let program = parse_program_with_options(text, None, &options).unwrap();
+        interpreter.exec(program).unwrap()
+    }
+
+    #[test]
+    fn test_exponent_literals() {
+        let mut interpreter = Interpreter::default();
+        let output = exec_with_exponent_literals(&mut interpreter, "2.5e3 + 1\n1e-2\n");
+        assert_eq!(output, "2501\n0.01\n");
+
+        interpreter.set_limits(ExecutionLimits {
+            max_digits: Some(100),
+            ..Default::default()
+        });
+        let options = ParseOptions {
+            exponent_literals: true,
+            ..Default::default()
+        };
+        let program = parse_program_with_options("1e4000000000\n", None, &options).unwrap();
+        let err = interpreter.exec(program).unwrap_err();
+        assert_eq!(*err.error(), BcError::TooManyDigits);
+    }
+
+    #[test]
+    fn test_scientific_output() {
+        let mut interpreter = Interpreter::default();
+        interpreter.set_scientific_output(Some(ScientificFormat {
+            notation: Notation::Scientific,
+            significant_digits: 6,
+        }));
+        let output = exec_with_exponent_literals(
+            &mut interpreter,
+            "6.02e23\n123456\n1234567\nscale = 10; 1 / 30000; .0005\nprint 2^64, \"\\n\"\n",
+        );
+        assert_eq!(
+            output,
+            "6.02e23\n123456\n1.23457e6\n3.33333e-5\n0.0005\n1.84467e19\n"
+        );
+
+        interpreter.set_scientific_output(Some(ScientificFormat {
+            notation: Notation::Engineering,
+            significant_digits: 6,
+        }));
+        let output = exec_with_exponent_literals(&mut interpreter, "-6.02e23\nobase = 16; 2^64\n");
+        assert_eq!(output, "-602e21\n10000000000000000\n");
+    }
+
+    #[test]
+    fn test_eval_returns_the_value_of_the_last_expression() {
+        let mut interpreter = Interpreter::default();
+        let value = interpreter.eval("scale = 2; x = 10\nx / 4").unwrap();
+        assert_eq!(value, "2.50".parse().unwrap());
+        // the state is kept, and the value is not printed
+        assert_eq!(interpreter.eval("x + 1"), Ok(Number::from(11)));
+        assert_eq!(
+            interpreter
+                .exec(parse_program(".\n", None).unwrap())
+                .unwrap(),
+            "0\n"
+        );
+
+        assert_eq!(crate::eval_expr("x = 3; x ^ 2"), Ok(Number::from(9)));
+        assert_eq!(
+            crate::eval_expr("1 / 0"),
+            Err(EvalError::Execution(BcError::DivisionByZero))
+        );
+        assert_eq!(
+            crate::eval_expr("x = 3; print x"),
+            Err(EvalError::NotAnExpression)
+        );
+        assert_eq!(crate::eval_expr("quit; 1"), Err(EvalError::Halted));
+        assert_eq!(
+            crate::eval_expr("define f() { halt; }\n1 + f()"),
+            Err(EvalError::Halted)
+        );
+        assert_eq!(
+            crate::eval_expr("1 +* 2"),
+            Err(EvalError::Syntax(
+                "expected '!' or expression\n1 +* 2\n   ^".to_string()
+            ))
+        );
+
+        // exponent literals are an extension of the language
+        assert!(matches!(crate::eval_expr("1e5"), Err(EvalError::Syntax(_))));
+        let options = ParseOptions {
+            exponent_literals: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Interpreter::default().eval_with_options("1e5", &options),
+            "1e5".parse()
+        );
+    }
+
+    #[test]
+    fn test_output_sink_receives_output_as_it_is_printed() {
+        let written = Rc::new(std::cell::RefCell::new(Vec::<String>::new()));
+        let sink_written = written.clone();
+        let mut interpreter = Interpreter::default();
+        interpreter.set_output_sink(Some(Box::new(move |text: &str| {
+            sink_written.borrow_mut().push(text.to_string());
+            Ok(())
+        })));
+        let program = parse_program("1 + 1\nprint \"a\", 3\n\"b\"\nlimits\n", None).unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "");
+        assert_eq!(written.borrow()[..4], ["2\n", "a", "3", "b"]);
+        assert!(written.borrow()[4].starts_with("BC_BASE_MAX"));
+
+        interpreter.set_output_sink(None);
+        let program = parse_program("5\n", None).unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), "5\n");
+    }
+
+    #[test]
+    fn test_last_value_output() {
+        let mut interpreter = Interpreter::default();
+        interpreter.set_line_length(4);
+        let output = interpreter
+            .exec(parse_program("print \"a\"\n2^20\n\"b\"\n", None).unwrap())
+            .unwrap();
+        let range = interpreter.last_value_output().unwrap();
+        assert_eq!(&output[range], "104\\\n857\\\n6\n");
+        assert_eq!(interpreter.format_number(interpreter.last()), "1048576");
+
+        interpreter
+            .exec(parse_program("x = 1\n", None).unwrap())
+            .unwrap();
+        assert_eq!(interpreter.last_value_output(), None);
+    }
+
+    #[test]
+    fn test_output_sink_errors_stop_execution() {
+        let mut lines = 0;
+        let mut interpreter = Interpreter::default();
+        interpreter.set_output_sink(Some(Box::new(move |_: &str| {
+            lines += 1;
+            if lines > 3 {
+                Err(BcError::Custom("too many lines".to_string()))
+            } else {
+                Ok(())
+            }
+        })));
+        let program = parse_program("for (i = 0; i < 10; ++i) i\nx = 1\n", None).unwrap();
+        let err = interpreter.exec(program).unwrap_err();
+        assert!(err.is_aborted());
+        assert_eq!(*err.error(), BcError::Custom("too many lines".to_string()));
+        assert_eq!(interpreter.eval("i"), Ok(Number::from(3)));
+        assert_eq!(interpreter.eval("x"), Ok(Number::zero()));
+    }
+
+    #[test]
+    fn test_long_numbers_are_wrapped() {
+        let mut interpreter = Interpreter::default();
+        let program = parse_program("x = 2^300; x\nprint x, \"\\n\"\n", None).unwrap();
+        let output = interpreter.exec(program).unwrap();
+        let line = "203703597633448608626844568840937816105146839366593625063614044935438\\\n\
+                    1299763336706183397376\n";
+        assert_eq!(output, line.repeat(2));
+
+        interpreter.set_line_length(0);
+        let program = parse_program("x\n", None).unwrap();
+        assert_eq!(interpreter.exec(program).unwrap(), line.replace("\\\n", ""));
+    }
 }
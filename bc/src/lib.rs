@@ -7,7 +7,26 @@
 // SPDX-License-Identifier: MIT
 //
 
+mod bytecode;
+pub mod diagnostic;
+pub mod error;
 pub mod instructions;
 pub mod interpreter;
 pub mod number;
+pub mod output;
 pub mod parser;
+pub mod posix;
+mod random;
+
+use error::EvalError;
+use interpreter::Interpreter;
+use number::Number;
+
+/// Evaluates `text` with a new interpreter and returns the value of its
+/// last statement, e.g. `eval_expr("x = 3; x ^ 2")` is 9.
+/// The language extensions are not enabled, so `eval_expr("1e5")` is a
+/// syntax error although `"1e5".parse::<Number>()` is not.
+/// See `Interpreter::eval` and `Interpreter::eval_with_options`.
+pub fn eval_expr(text: &str) -> Result<Number, EvalError> {
+    Interpreter::default().eval(text)
+}
@@ -0,0 +1,171 @@
+//! bc - arbitrary-precision arithmetic language
+//!
+//! usage: bc [-lqs] [file ...]
+//!
+//! The files are executed in order, then the standard input is read
+//! until the end of the input or until a `quit` statement.
+//!
+//! Numbers longer than `BC_LINE_LENGTH` characters, 70 by default, are
+//! split with a backslash at the end of each line. 0 disables the split.
+
+use std::io::{self, BufRead, IsTerminal};
+use std::process::ExitCode;
+
+use bc::instructions::Program;
+use bc::interpreter::Interpreter;
+use bc::output::WriteSink;
+use bc::parser::parse_program;
+use bc::posix::find_extensions;
+
+const USAGE: &str = "usage: bc [-lqs] [file ...]";
+
+#[derive(Default)]
+struct Args {
+    /// -l: load the math library and set scale to 20
+    math_library: bool,
+    /// -q: don't print the banner in interactive mode
+    quiet: bool,
+    /// -s: reject the extensions to POSIX bc
+    standard: bool,
+    files: Vec<String>,
+}
+
+fn parse_args(args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut result = Args::default();
+    let mut options_ended = false;
+    for arg in args {
+        if options_ended || !arg.starts_with('-') {
+            result.files.push(arg);
+            continue;
+        }
+        if arg == "--" {
+            options_ended = true;
+            continue;
+        }
+        for option in arg.chars().skip(1) {
+            match option {
+                'l' => result.math_library = true,
+                'q' => result.quiet = true,
+                's' => result.standard = true,
+                _ => return Err(format!("bc: invalid option -- '{option}'")),
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Executes `program`, printing its output and errors.
+/// # Returns
+/// true if the program has quit
+fn exec_program(interpreter: &mut Interpreter, program: Program, standard: bool) -> bool {
+    if standard {
+        let extensions = find_extensions(&program);
+        if !extensions.is_empty() {
+            for extension in extensions {
+                eprintln!("{extension}");
+            }
+            return false;
+        }
+    }
+    // the output is printed by the output sink as soon as it is produced,
+    // so the error is printed after the output that preceded it
+    if let Err(e) = interpreter.exec(program) {
+        eprintln!("{e}");
+    }
+    interpreter.has_quit()
+}
+
+/// Parses and executes `text`, see `exec_program`.
+fn exec_text(
+    interpreter: &mut Interpreter,
+    text: &str,
+    file: Option<&str>,
+    standard: bool,
+) -> bool {
+    match parse_program(text, file) {
+        Ok(program) => exec_program(interpreter, program, standard),
+        Err(e) => {
+            eprint!("{e}");
+            false
+        }
+    }
+}
+
+/// Reads the standard input line by line, executing each line as soon as
+/// it is a complete program. Incomplete programs, like a function
+/// definition that has not been closed yet, continue on the next line.
+fn run_stdin(interpreter: &mut Interpreter, standard: bool) -> io::Result<()> {
+    let mut buffer = String::new();
+    for line in io::stdin().lock().lines() {
+        buffer.push_str(&line?);
+        buffer.push('\n');
+        let has_quit = match parse_program(&buffer, None) {
+            Ok(program) => exec_program(interpreter, program, standard),
+            Err(e) if e.is_incomplete => continue,
+            Err(e) => {
+                eprint!("{e}");
+                false
+            }
+        };
+        buffer.clear();
+        if has_quit {
+            return Ok(());
+        }
+    }
+    if !buffer.is_empty() {
+        // the input ended in the middle of a statement
+        exec_text(interpreter, &buffer, None, standard);
+    }
+    Ok(())
+}
+
+/// The value of `BC_LINE_LENGTH`, `None` if it is not set or not a number.
+/// As in GNU bc, lengths below 3 other than 0 are ignored.
+fn line_length_from_env() -> Option<usize> {
+    let line_length: usize = std::env::var("BC_LINE_LENGTH").ok()?.trim().parse().ok()?;
+    (line_length == 0 || line_length >= 3).then_some(line_length)
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{message}");
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut interpreter = if args.math_library {
+        Interpreter::with_math_library()
+    } else {
+        Interpreter::default()
+    };
+    interpreter.set_output_sink(Some(Box::new(WriteSink(io::stdout()))));
+    if let Some(line_length) = line_length_from_env() {
+        interpreter.set_line_length(line_length);
+    }
+
+    for file in &args.files {
+        let text = match std::fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("bc: {file}: {e}");
+                return ExitCode::FAILURE;
+            }
+        };
+        if exec_text(&mut interpreter, &text, Some(file), args.standard) {
+            return ExitCode::SUCCESS;
+        }
+    }
+
+    if io::stdin().is_terminal() && !args.quiet {
+        println!("bc {}", env!("CARGO_PKG_VERSION"));
+        println!("type `quit` to exit");
+    }
+    if let Err(e) = run_stdin(&mut interpreter, args.standard) {
+        eprintln!("bc: {e}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
@@ -1,5 +1,11 @@
+use std::str::FromStr;
+
 use bigdecimal::{num_bigint::BigInt, BigDecimal, Num, One, Signed, ToPrimitive, Zero};
 
+use crate::error::{BcError, EvalError};
+
+mod math;
+
 /// Converts a character to a number
 /// # Panics
 /// panics if the character is not a valid hexadecimal digit
@@ -12,13 +18,13 @@ fn to_digit(c: u8) -> Option<u8> {
     }
 }
 
-/// Convert a number to a character
+/// Convert a number to a character, the digits above 9 are the letters `A` to `Z`
 /// # Panics
-/// panics if the number is bigger than 15
+/// panics if the number is bigger than 35
 fn to_char(val: u8) -> char {
     match val {
         0..=9 => (val + b'0') as char,
-        10..=15 => (val - 10 + b'A') as char,
+        10..=35 => (val - 10 + b'A') as char,
         _ => panic!("number bigger than biggest representable base {}", val),
     }
 }
@@ -29,7 +35,126 @@ fn pad_digit(d: u64, base_ilog10: u32) -> String {
     format!("{:0width$}", d, width = width as usize)
 }
 
-pub type NumericResult = Result<Number, &'static str>;
+pub type NumericResult = Result<Number, BcError>;
+
+/// Splits `text` into lines of at most `line_length` characters, ending
+/// every line but the last with a backslash, as bc prints long numbers.
+/// A line length below 2, usually 0, means no wrapping.
+pub fn wrap_lines(text: &str, line_length: usize) -> String {
+    if line_length < 2 || text.chars().count() < line_length {
+        return text.to_string();
+    }
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .chunks(line_length - 1)
+        .map(String::from_iter)
+        .collect::<Vec<_>>()
+        .join("\\\n")
+}
+
+/// How to write an integer in two's complement, see `NumberFormat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TwosComplement {
+    /// the number modulo 2^bits, between -2^(bits - 1) and 2^(bits - 1) - 1
+    Signed(u32),
+    /// the number modulo 2^bits, between 0 and 2^bits - 1
+    Unsigned(u32),
+}
+
+impl TwosComplement {
+    pub fn bits(self) -> u32 {
+        match self {
+            TwosComplement::Signed(bits) | TwosComplement::Unsigned(bits) => bits,
+        }
+    }
+}
+
+/// The options of `Number::format`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumberFormat {
+    /// between 2 and 36, the digits above 9 are written as letters
+    pub base: u64,
+    /// written after the sign, e.g. `0x`
+    pub prefix: String,
+    /// wraps the number to a number of bits, like a cast to an integer type
+    pub twos_complement: Option<TwosComplement>,
+    /// the number of integer digits between separators, 0 for no grouping
+    pub group_size: usize,
+    pub group_separator: char,
+    /// see `wrap_lines`, 0 for no wrapping
+    pub line_length: usize,
+}
+
+/// How `Number::to_scientific_string` places the decimal point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// one digit before the point, as in `6.02e23`
+    Scientific,
+    /// one to three digits before the point and an exponent
+    /// that is a multiple of 3, as in `602e21`
+    Engineering,
+}
+
+/// The options of `Number::to_scientific_string`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScientificFormat {
+    pub notation: Notation,
+    /// the number is rounded to this many digits, at least 1
+    pub significant_digits: u64,
+}
+
+impl NumberFormat {
+    pub fn new(base: u64) -> Self {
+        assert!(
+            (2..=36).contains(&base),
+            "base {base} is not between 2 and 36"
+        );
+        Self {
+            base,
+            prefix: String::new(),
+            twos_complement: None,
+            group_size: 0,
+            group_separator: ' ',
+            line_length: 0,
+        }
+    }
+}
+
+/// multiplies `a` and `b`, keeping at most `scale` fractional digits
+fn mul_truncated(a: &BigDecimal, b: &BigDecimal, scale: i64) -> BigDecimal {
+    let result = a * b;
+    if result.fractional_digit_count() > scale {
+        result.with_scale(scale)
+    } else {
+        result
+    }
+}
+
+/// computes `base ^ exponent` by repeated squaring, truncating every
+/// intermediate result to `scale` fractional digits
+fn pow_truncated(base: &BigDecimal, mut exponent: u64, scale: i64) -> BigDecimal {
+    let mut result = BigDecimal::one();
+    let mut power = base.clone();
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = mul_truncated(&result, &power, scale);
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            power = mul_truncated(&power, &power, scale);
+        }
+    }
+    result
+}
+
+/// scale used for intermediate results, `guard` digits more than `scale`
+fn working_scale(scale: u64, guard: u64) -> i64 {
+    i64::try_from(scale.saturating_add(guard)).unwrap_or(i64::MAX)
+}
+
+fn decimal_digits(n: u64) -> u64 {
+    n.checked_ilog10().map_or(1, |digits| digits as u64 + 1)
+}
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Number(BigDecimal);
@@ -47,7 +172,9 @@ impl Number {
         self.0.to_u64()
     }
 
-    /// Parse a number from a string in the given base.
+    /// Parse a number from a string in the given base. In base 10, where
+    /// `e` is not a digit, the number can have an exponent, as in `6.02e23`
+    /// or `1.5E-9`.
     /// # Returns
     /// `None` if the string contains invalid characters for the given base.
     /// # Panics
@@ -61,6 +188,12 @@ impl Number {
         assert!(!s.is_empty(), "parsed number has no digits");
         assert!((2..=16).contains(&base), "base must be in the range 2..=16");
 
+        if base == 10 {
+            if let Some((mantissa, exponent)) = s.split_once(['e', 'E']) {
+                return Self::parse_scientific(mantissa, exponent);
+            }
+        }
+
         for c in s.bytes() {
             if c != b'.' {
                 let digit = to_digit(c);
@@ -103,50 +236,37 @@ impl Number {
         ))
     }
 
-    /// Convert the number to a string in the given base.
-    pub fn to_string(&self, base: u64) -> String {
-        let mut number = self.0.clone();
-        if number.is_zero() {
-            return "0".to_string();
-        }
-
-        let mut result = String::new();
-        if self.0.is_negative() {
-            result.push('-');
-            number = -number;
+    fn parse_scientific(mantissa: &str, exponent: &str) -> Option<Number> {
+        if mantissa.is_empty() || mantissa == "." {
+            return None;
         }
+        let exponent: i64 = exponent.parse().ok()?;
+        let (digits, scale) = Self::parse(mantissa, 10)?.0.into_bigint_and_exponent();
+        // the scale is negative for large exponents, which keeps
+        // the number small until it is checked against the limits
+        Some(Self(BigDecimal::new(digits, scale.checked_sub(exponent)?)))
+    }
 
-        let base_ilog10 = base.ilog10();
+    /// The digits of the absolute value of the number in `base`, most
+    /// significant first, split into the integer and fractional parts.
+    fn digits(&self, base: u64) -> (Vec<u64>, Vec<u64>) {
+        let number = self.0.abs();
         let integer_part = number.with_scale(0);
         let mut fractional_part = number - &integer_part;
 
-        if integer_part.is_zero() {
-            result.push('0');
-        } else {
-            let (mut integer_part, _) = integer_part.into_bigint_and_exponent();
-
-            let mut stack = Vec::new();
-            while !integer_part.is_zero() {
-                let remainder = &integer_part % base;
-                integer_part /= base;
-                stack.push(remainder.to_u64().unwrap());
-            }
-
-            for digit in stack.iter().rev() {
-                if base <= 16 {
-                    result.push(to_char(*digit as u8));
-                } else {
-                    result.push(' ');
-                    result.push_str(&pad_digit(*digit, base_ilog10))
-                }
-            }
+        let mut integer_digits = Vec::new();
+        let (mut integer_part, _) = integer_part.into_bigint_and_exponent();
+        while !integer_part.is_zero() {
+            let remainder = &integer_part % base;
+            integer_part /= base;
+            integer_digits.push(remainder.to_u64().unwrap());
         }
+        integer_digits.reverse();
 
+        let mut fractional_digits = Vec::new();
         if fractional_part.fractional_digit_count() == 0 {
-            return result;
+            return (integer_digits, fractional_digits);
         }
-
-        result.push('.');
         let mut temp = BigDecimal::one();
         let scale = self.scale();
         // The standard doesn't specify how many fractional digits to print.
@@ -156,15 +276,50 @@ impl Number {
         while temp.digits() <= scale {
             fractional_part *= base;
             let integer_part = fractional_part.with_scale(0);
-            let digit = integer_part.to_u64().unwrap();
+            fractional_digits.push(integer_part.to_u64().unwrap());
+            fractional_part -= &integer_part;
+            temp *= base
+        }
+        (integer_digits, fractional_digits)
+    }
+
+    /// Convert the number to a string in the given base, as bc prints it:
+    /// bases above 16 write each digit as a decimal number preceded by a space.
+    pub fn to_string_radix(&self, base: u64) -> String {
+        if self.is_zero() {
+            return "0".to_string();
+        }
+
+        let mut result = String::new();
+        if self.0.is_negative() {
+            result.push('-');
+        }
+
+        let base_ilog10 = base.ilog10();
+        let (integer_digits, fractional_digits) = self.digits(base);
+        if integer_digits.is_empty() {
+            result.push('0');
+        }
+        for digit in integer_digits {
+            if base <= 16 {
+                result.push(to_char(digit as u8));
+            } else {
+                result.push(' ');
+                result.push_str(&pad_digit(digit, base_ilog10))
+            }
+        }
+
+        if fractional_digits.is_empty() {
+            return result;
+        }
+        result.push('.');
+        for digit in fractional_digits {
             if base <= 16 {
                 result.push(to_char(digit as u8));
             } else {
                 result.push_str(&pad_digit(digit, base_ilog10));
                 result.push(' ');
             }
-            fractional_part -= &integer_part;
-            temp *= base
         }
         // remove trailing space
         if base > 16 {
@@ -173,25 +328,160 @@ impl Number {
         result
     }
 
+    /// Converts the number to a string as described by `format`.
+    /// Fails if the number has to be written in two's complement
+    /// but has a fractional part.
+    pub fn format(&self, format: &NumberFormat) -> Result<String, BcError> {
+        let value = match format.twos_complement {
+            Some(twos_complement) => {
+                let modulus = BigInt::one() << twos_complement.bits();
+                // the remainder has the sign of the dividend, so it is
+                // made positive to get the value modulo 2^bits
+                let mut value = self.to_bigint()? % &modulus;
+                if value.is_negative() {
+                    value += &modulus;
+                }
+                if let TwosComplement::Signed(bits) = twos_complement {
+                    if bits > 0 && value.bit(u64::from(bits - 1)) {
+                        value -= modulus;
+                    }
+                }
+                Self(value.into())
+            }
+            None => self.clone(),
+        };
+
+        let mut result = String::new();
+        if value.0.is_negative() {
+            result.push('-');
+        }
+        result.push_str(&format.prefix);
+
+        let (integer_digits, fractional_digits) = value.digits(format.base);
+        if integer_digits.is_empty() {
+            result.push('0');
+        }
+        let count = integer_digits.len();
+        for (i, digit) in integer_digits.into_iter().enumerate() {
+            if i > 0 && format.group_size > 0 && (count - i) % format.group_size == 0 {
+                result.push(format.group_separator);
+            }
+            result.push(to_char(digit as u8));
+        }
+        if !fractional_digits.is_empty() {
+            result.push('.');
+            result.extend(
+                fractional_digits
+                    .into_iter()
+                    .map(|digit| to_char(digit as u8)),
+            );
+        }
+        Ok(wrap_lines(&result, format.line_length))
+    }
+
+    /// The exponent of the most significant decimal digit,
+    /// e.g. 2 for 123.4 and -3 for 0.001. `None` for zero.
+    pub fn decimal_exponent(&self) -> Option<i64> {
+        if self.is_zero() {
+            return None;
+        }
+        Some(self.0.digits() as i64 - 1 - self.0.fractional_digit_count())
+    }
+
+    /// Writes the number in scientific or engineering notation, rounded
+    /// half away from zero. Trailing zeros after the point are removed.
+    pub fn to_scientific_string(&self, format: &ScientificFormat) -> String {
+        let Some(mut exponent) = self.decimal_exponent() else {
+            return "0".to_string();
+        };
+        let significant_digits = format.significant_digits.max(1);
+        let (mut digits, _) = self.0.abs().into_bigint_and_exponent();
+        let digit_count = self.0.digits();
+        if digit_count > significant_digits {
+            let divisor = BigInt::from(10).pow((digit_count - significant_digits) as u32);
+            let remainder = &digits % &divisor;
+            digits /= &divisor;
+            if remainder * 2 >= divisor {
+                digits += 1;
+            }
+        }
+        let mut mantissa = digits.to_string();
+        if mantissa.len() as u64 > significant_digits {
+            // rounding carried into a new digit, as in 9.99 -> 10.0
+            mantissa.pop();
+            exponent += 1;
+        }
+
+        let integer_digits = match format.notation {
+            Notation::Scientific => 1,
+            Notation::Engineering => exponent.rem_euclid(3) as usize + 1,
+        };
+        exponent -= integer_digits as i64 - 1;
+        let mut mantissa = mantissa.trim_end_matches('0').to_string();
+        while mantissa.len() < integer_digits {
+            mantissa.push('0');
+        }
+
+        let mut result = String::new();
+        if self.is_negative() {
+            result.push('-');
+        }
+        result.push_str(&mantissa[..integer_digits]);
+        if mantissa.len() > integer_digits {
+            result.push('.');
+            result.push_str(&mantissa[integer_digits..]);
+        }
+        result.push_str(&format!("e{exponent}"));
+        result
+    }
+
     /// The number of decimal digits in the number.
     pub fn scale(&self) -> u64 {
         self.0.fractional_digit_count().max(0) as u64
     }
 
     pub fn length(&self) -> u64 {
-        self.0.digits()
+        // the scale is negative if the number was written with an exponent
+        let trailing_zeros = (-self.0.fractional_digit_count()).max(0) as u64;
+        self.0.digits() + trailing_zeros
     }
 
-    pub fn negate(self) -> Self {
-        Self(-self.0)
+    /// The number of decimal digits needed to write the number,
+    /// counting both the integer and the fractional part.
+    pub fn digit_count(&self) -> u64 {
+        self.length().max(self.scale())
     }
 
-    pub fn add(self, other: &Number) -> Number {
-        Self(self.0 + &other.0)
+    /// An approximation of `log10(|self|)`, which is also
+    /// usable for numbers that don't fit in a `f64`.
+    /// Returns negative infinity for zero.
+    fn log10_abs(&self) -> f64 {
+        match self.0.abs().to_f64() {
+            Some(value) if value.is_normal() => value.log10(),
+            _ if self.is_zero() => f64::NEG_INFINITY,
+            _ => self.length() as f64 - self.scale() as f64,
+        }
     }
 
-    pub fn sub(self, other: &Number) -> Number {
-        Self(self.0 - &other.0)
+    /// An estimate of the number of integer digits in `self ^ exponent`.
+    /// This is cheap to compute, and can be used to reject powers that
+    /// would take too long to evaluate.
+    /// Powers without integer digits (negative exponents, or bases
+    /// between -1 and 1) are estimated as 0: `pow` computes them with
+    /// O(log |exponent|) multiplications at the precision of the scale,
+    /// which is bounded separately.
+    pub fn pow_digits_estimate(&self, exponent: &Number) -> u64 {
+        let exponent = exponent.0.to_f64().unwrap_or(f64::INFINITY);
+        if exponent == 0.0 || self.is_zero() {
+            return 0;
+        }
+        // `as` saturates, so an infinite estimate becomes u64::MAX
+        // and a negative one becomes 0
+        (exponent * self.log10_abs()).ceil() as u64
+    }
+
+    pub fn negate(self) -> Self {
+        Self(-self.0)
     }
 
     pub fn mul(self, other: &Number, scale: u64) -> Number {
@@ -204,7 +494,7 @@ impl Number {
 
     pub fn div(self, other: &Number, scale: u64) -> NumericResult {
         if other.is_zero() {
-            return Err("division by zero");
+            return Err(BcError::DivisionByZero);
         }
         let result = self.0 / &other.0;
         Ok(Self(result).rescale(scale))
@@ -212,34 +502,51 @@ impl Number {
 
     pub fn pow(self, other: &Number, scale: u64) -> NumericResult {
         if !other.0.is_integer() {
-            return Err("exponent has to be an integer");
+            return Err(BcError::NonIntegerExponent(other.clone()));
         }
 
         let a = self.scale();
         let b = other
             .0
             .to_i64()
-            .ok_or("exponent is too large")?
+            .ok_or_else(|| BcError::ExponentTooLarge(other.clone()))?
             .unsigned_abs();
-        let scale = if other.0.is_positive() {
-            u64::min(a * b, u64::max(scale, a))
-        } else {
-            scale
-        };
-        let mut result = BigDecimal::one();
-        for _ in 0..b {
-            result *= &self.0;
-        }
+        // log10 of the absolute value of the result
+        let magnitude = b as f64 * self.log10_abs();
+
         if other.0.is_negative() {
-            result = BigDecimal::one() / result;
+            if self.is_zero() {
+                return Err(BcError::DivisionByZero);
+            }
+            if magnitude > scale as f64 + 1.0 {
+                // the result is smaller than the last digit of `scale`
+                return Ok(Self::zero().rescale(scale));
+            }
+            // the error of the truncated power is multiplied
+            // by up to `10 ^ (2 * |magnitude|)` by the division
+            let guard = (magnitude.abs().ceil() as u64)
+                .saturating_mul(2)
+                .saturating_add(decimal_digits(b) + 2);
+            // keep at least the digits of the base, like positive powers do
+            let power_scale = working_scale(scale.max(a), guard);
+            let power = pow_truncated(&self.0, b, power_scale);
+            return Self::from(1).div(&Self(power), scale);
         }
+
+        // POSIX: the scale of the result is min(a * b, max(scale, a)).
+        // Intermediate results keep enough digits for the truncation errors,
+        // which are multiplied by the integer part of the result, not to
+        // reach the digits that are kept
+        let scale = u64::min(a.saturating_mul(b), u64::max(scale, a));
+        let guard = (magnitude.max(0.0).ceil() as u64).saturating_add(decimal_digits(b) + 2);
+        let result = pow_truncated(&self.0, b, working_scale(scale, guard));
         Ok(Self(result).rescale(scale))
     }
 
     pub fn modulus(self, other: &Number, scale: u64) -> NumericResult {
         let a_over_b = self.clone().div(other, scale)?;
         let scale = u64::max(scale + other.scale(), self.scale());
-        let result = self.sub(&a_over_b.mul(other, scale));
+        let result = self - &a_over_b.mul(other, scale);
         Ok(result)
     }
 
@@ -248,7 +555,59 @@ impl Number {
         if let Some(result) = self.0.sqrt() {
             return Ok(Self(result).rescale(scale));
         }
-        Err("square root of negative number")
+        Err(BcError::NegativeSquareRoot(self))
+    }
+
+    /// the value of an integer operand of a bitwise operator
+    fn to_bigint(&self) -> Result<BigInt, BcError> {
+        if !self.0.is_integer() {
+            return Err(BcError::NonIntegerOperand(self.clone()));
+        }
+        Ok(self.0.with_scale(0).into_bigint_and_exponent().0)
+    }
+
+    fn shift_amount(bits: &Number) -> Result<usize, BcError> {
+        bits.to_bigint()?
+            .to_u32()
+            .map(|bits| bits as usize)
+            .ok_or_else(|| BcError::InvalidShift(bits.clone()))
+    }
+
+    // The bitwise operators work on integers only. Negative numbers
+    // behave as in two's complement, with infinitely many sign bits.
+
+    pub fn bit_and(self, other: &Number) -> NumericResult {
+        Ok(Self((self.to_bigint()? & other.to_bigint()?).into()))
+    }
+
+    pub fn bit_or(self, other: &Number) -> NumericResult {
+        Ok(Self((self.to_bigint()? | other.to_bigint()?).into()))
+    }
+
+    pub fn bit_xor(self, other: &Number) -> NumericResult {
+        Ok(Self((self.to_bigint()? ^ other.to_bigint()?).into()))
+    }
+
+    pub fn bit_not(self) -> NumericResult {
+        Ok(Self((!self.to_bigint()?).into()))
+    }
+
+    pub fn shift_left(self, bits: &Number) -> NumericResult {
+        let bits = Self::shift_amount(bits)?;
+        Ok(Self((self.to_bigint()? << bits).into()))
+    }
+
+    /// shifts to the right, rounding towards negative infinity
+    pub fn shift_right(self, bits: &Number) -> NumericResult {
+        let bits = Self::shift_amount(bits)?;
+        Ok(Self((self.to_bigint()? >> bits).into()))
+    }
+
+    /// An estimate of the number of integer digits in `self << bits`,
+    /// see `pow_digits_estimate`.
+    pub fn shift_left_digits_estimate(&self, bits: &Number) -> u64 {
+        let bits = bits.0.to_f64().unwrap_or(f64::INFINITY).max(0.0);
+        (self.length() as f64 + bits * std::f64::consts::LOG10_2).ceil() as u64
     }
 
     pub fn inc(&mut self) {
@@ -262,6 +621,31 @@ impl Number {
     pub fn is_zero(&self) -> bool {
         self.0.is_zero()
     }
+
+    pub fn is_negative(&self) -> bool {
+        self.0.is_negative()
+    }
+
+    /// true if the fractional part is zero, even if the scale is not
+    pub fn is_integer(&self) -> bool {
+        self.0.is_integer()
+    }
+}
+
+impl std::ops::Add<&Number> for Number {
+    type Output = Number;
+
+    fn add(self, other: &Number) -> Number {
+        Self(self.0 + &other.0)
+    }
+}
+
+impl std::ops::Sub<&Number> for Number {
+    type Output = Number;
+
+    fn sub(self, other: &Number) -> Number {
+        Self(self.0 - &other.0)
+    }
 }
 
 impl From<u64> for Number {
@@ -270,133 +654,275 @@ impl From<u64> for Number {
     }
 }
 
+impl From<i32> for Number {
+    fn from(n: i32) -> Self {
+        Self(BigDecimal::from(n))
+    }
+}
+
+impl From<i64> for Number {
+    fn from(n: i64) -> Self {
+        Self(BigDecimal::from(n))
+    }
+}
+
+impl From<i128> for Number {
+    fn from(n: i128) -> Self {
+        Self(BigDecimal::from(n))
+    }
+}
+
+impl TryFrom<f64> for Number {
+    type Error = EvalError;
+
+    /// Converts the shortest decimal number that rounds to `n`,
+    /// so that 0.1 is 0.1 and not 0.1000000000000000055511151231257827.
+    fn try_from(n: f64) -> Result<Self, EvalError> {
+        if !n.is_finite() {
+            return Err(EvalError::InvalidNumber(n.to_string()));
+        }
+        // `Display` for `f64` never uses an exponent
+        n.to_string().parse()
+    }
+}
+
+impl From<&Number> for f64 {
+    /// The closest `f64`, infinite if the number is too large.
+    fn from(n: &Number) -> f64 {
+        n.0.to_f64().unwrap_or(if n.is_negative() {
+            f64::NEG_INFINITY
+        } else {
+            f64::INFINITY
+        })
+    }
+}
+
+macro_rules! impl_try_from_number {
+    ($($integer:ty => $convert:ident),*) => {
+        $(
+            impl TryFrom<&Number> for $integer {
+                type Error = EvalError;
+
+                /// Fails if the number has a fractional part or is out of range.
+                fn try_from(n: &Number) -> Result<Self, EvalError> {
+                    if !n.is_integer() {
+                        return Err(EvalError::OutOfRange(n.clone()));
+                    }
+                    n.0.with_scale(0)
+                        .$convert()
+                        .ok_or_else(|| EvalError::OutOfRange(n.clone()))
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_number!(i64 => to_i64, u64 => to_u64, i128 => to_i128);
+
+impl FromStr for Number {
+    type Err = EvalError;
+
+    /// Parses a decimal number with an optional sign and exponent,
+    /// such as `-12.5` or `6.02e23`. The exponent is always accepted, while
+    /// programs need `ParseOptions::exponent_literals` for it.
+    fn from_str(s: &str) -> Result<Self, EvalError> {
+        let invalid = || EvalError::InvalidNumber(s.to_string());
+        let (negative, unsigned) = match s.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        // `parse` expects text that was checked by the parser
+        let mantissa = unsigned.split(['e', 'E']).next().unwrap();
+        let is_valid = mantissa.bytes().any(|c| c.is_ascii_digit())
+            && mantissa.bytes().all(|c| c.is_ascii_digit() || c == b'.')
+            && mantissa.matches('.').count() <= 1;
+        if !is_valid {
+            return Err(invalid());
+        }
+        let n = Number::parse(unsigned, 10).ok_or_else(invalid)?;
+        Ok(if negative { n.negate() } else { n })
+    }
+}
+
+impl TryFrom<&str> for Number {
+    type Error = EvalError;
+
+    fn try_from(s: &str) -> Result<Self, EvalError> {
+        s.parse()
+    }
+}
+
+impl std::fmt::Display for Number {
+    /// Writes the number in base 10, as bc prints it with `obase=10`.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.to_string_radix(10))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::ops::{Add, Sub};
 
     #[test]
     fn test_parse_base_10() {
-        assert_eq!(&Number::from(1).to_string(10), "1");
-        assert_eq!(&Number::parse("123", 10).unwrap().to_string(10), "123");
+        assert_eq!(&Number::from(1).to_string_radix(10), "1");
         assert_eq!(
-            &Number::parse("123.456", 10).unwrap().to_string(10),
+            &Number::parse("123", 10).unwrap().to_string_radix(10),
+            "123"
+        );
+        assert_eq!(
+            &Number::parse("123.456", 10).unwrap().to_string_radix(10),
             "123.456"
         );
-        assert_eq!(&Number::parse(".1234", 10).unwrap().to_string(10), "0.1234");
+        assert_eq!(
+            &Number::parse(".1234", 10).unwrap().to_string_radix(10),
+            "0.1234"
+        );
     }
 
     #[test]
     fn test_parse_base_2() {
-        assert_eq!(&Number::parse("1", 2).unwrap().to_string(10), "1");
-        assert_eq!(&Number::parse("1101", 2).unwrap().to_string(10), "13");
+        assert_eq!(&Number::parse("1", 2).unwrap().to_string_radix(10), "1");
+        assert_eq!(&Number::parse("1101", 2).unwrap().to_string_radix(10), "13");
         assert_eq!(
-            &Number::parse("1101.101", 2).unwrap().to_string(10),
+            &Number::parse("1101.101", 2).unwrap().to_string_radix(10),
             "13.625"
         );
-        assert_eq!(&Number::parse(".1101", 2).unwrap().to_string(10), "0.8125");
+        assert_eq!(
+            &Number::parse(".1101", 2).unwrap().to_string_radix(10),
+            "0.8125"
+        );
     }
 
     #[test]
     fn test_parse_base_12() {
-        assert_eq!(&Number::parse("1", 12).unwrap().to_string(10), "1");
-        assert_eq!(&Number::parse("123", 12).unwrap().to_string(10), "171");
+        assert_eq!(&Number::parse("1", 12).unwrap().to_string_radix(10), "1");
         assert_eq!(
-            &Number::parse("1B3.BA6", 12).unwrap().to_string(10),
+            &Number::parse("123", 12).unwrap().to_string_radix(10),
+            "171"
+        );
+        assert_eq!(
+            &Number::parse("1B3.BA6", 12).unwrap().to_string_radix(10),
             "279.989"
         );
-        assert_eq!(&Number::parse(".1B3A", 12).unwrap().to_string(10), "0.1619");
+        assert_eq!(
+            &Number::parse(".1B3A", 12).unwrap().to_string_radix(10),
+            "0.1619"
+        );
     }
 
     #[test]
     fn test_output_base_10() {
-        assert_eq!(Number::from(1).to_string(10), "1");
-        assert_eq!(Number::from(123).to_string(10), "123");
-        assert_eq!(Number::from(123).negate().to_string(10), "-123");
+        assert_eq!(Number::from(1).to_string_radix(10), "1");
+        assert_eq!(Number::from(123).to_string_radix(10), "123");
+        assert_eq!(Number::from(123).negate().to_string_radix(10), "-123");
         assert_eq!(
-            Number::parse("123.456", 10).unwrap().to_string(10),
+            Number::parse("123.456", 10).unwrap().to_string_radix(10),
             "123.456"
         );
         assert_eq!(
-            Number::parse("123.456", 10).unwrap().negate().to_string(10),
+            Number::parse("123.456", 10)
+                .unwrap()
+                .negate()
+                .to_string_radix(10),
             "-123.456"
         );
-        assert_eq!(Number::parse(".1234", 10).unwrap().to_string(10), "0.1234");
         assert_eq!(
-            Number::parse(".1234", 10).unwrap().negate().to_string(10),
+            Number::parse(".1234", 10).unwrap().to_string_radix(10),
+            "0.1234"
+        );
+        assert_eq!(
+            Number::parse(".1234", 10)
+                .unwrap()
+                .negate()
+                .to_string_radix(10),
             "-0.1234"
         );
         assert_eq!(
-            Number::parse("2.000000", 10).unwrap().to_string(10),
+            Number::parse("2.000000", 10).unwrap().to_string_radix(10),
             "2.000000"
         );
         assert_eq!(
             Number::parse("0.000000000000000000000000001", 10)
                 .unwrap()
-                .to_string(10),
+                .to_string_radix(10),
             "0.000000000000000000000000001"
         );
         assert_eq!(
             Number::parse("100000000000000000000000000000000000000000000000", 10)
                 .unwrap()
-                .to_string(10),
+                .to_string_radix(10),
             "100000000000000000000000000000000000000000000000",
         );
     }
 
     #[test]
     fn test_output_base_2() {
-        assert_eq!(Number::from(1).to_string(2), "1");
-        assert_eq!(Number::from(13).to_string(2), "1101");
-        assert_eq!(Number::from(13).negate().to_string(2), "-1101");
+        assert_eq!(Number::from(1).to_string_radix(2), "1");
+        assert_eq!(Number::from(13).to_string_radix(2), "1101");
+        assert_eq!(Number::from(13).negate().to_string_radix(2), "-1101");
         assert_eq!(
-            Number::parse("13.625", 10).unwrap().to_string(2),
+            Number::parse("13.625", 10).unwrap().to_string_radix(2),
             "1101.1010000000"
         );
         assert_eq!(
-            Number::parse("13.625", 10).unwrap().negate().to_string(2),
+            Number::parse("13.625", 10)
+                .unwrap()
+                .negate()
+                .to_string_radix(2),
             "-1101.1010000000"
         );
         assert_eq!(
-            Number::parse("0.8125", 10).unwrap().to_string(2),
+            Number::parse("0.8125", 10).unwrap().to_string_radix(2),
             "0.11010000000000"
         );
     }
 
     #[test]
     fn test_output_base_12() {
-        assert_eq!(Number::from(1).to_string(12), "1");
-        assert_eq!(Number::from(123).negate().to_string(12), "-A3");
+        assert_eq!(Number::from(1).to_string_radix(12), "1");
+        assert_eq!(Number::from(123).negate().to_string_radix(12), "-A3");
         assert_eq!(
-            Number::parse("123.321", 10).unwrap().to_string(12),
+            Number::parse("123.321", 10).unwrap().to_string_radix(12),
             "A3.3A2"
         );
-        assert_eq!(Number::parse("0.0891", 10).unwrap().to_string(12), "0.109B");
+        assert_eq!(
+            Number::parse("0.0891", 10).unwrap().to_string_radix(12),
+            "0.109B"
+        );
     }
 
     #[test]
     fn test_output_base_150() {
-        assert_eq!(Number::from(1).to_string(150), " 001");
-        assert_eq!(Number::from(1040).negate().to_string(150), "- 006 140");
+        assert_eq!(Number::from(1).to_string_radix(150), " 001");
         assert_eq!(
-            Number::parse("230.461", 10).unwrap().to_string(150),
+            Number::from(1040).negate().to_string_radix(150),
+            "- 006 140"
+        );
+        assert_eq!(
+            Number::parse("230.461", 10).unwrap().to_string_radix(150),
             " 001 080.069 022"
         );
         assert_eq!(
-            Number::parse("0.673", 10).unwrap().to_string(150),
+            Number::parse("0.673", 10).unwrap().to_string_radix(150),
             "0.100 142"
         );
     }
 
     #[test]
     fn test_output_base_1029() {
-        assert_eq!(Number::from(1).to_string(1029), " 0001");
-        assert_eq!(Number::from(1040).negate().to_string(1029), "- 0001 0011");
+        assert_eq!(Number::from(1).to_string_radix(1029), " 0001");
+        assert_eq!(
+            Number::from(1040).negate().to_string_radix(1029),
+            "- 0001 0011"
+        );
         assert_eq!(
-            Number::parse("193.286", 10).unwrap().to_string(1029),
+            Number::parse("193.286", 10).unwrap().to_string_radix(1029),
             " 0193.0294"
         );
         assert_eq!(
-            Number::parse("0.2964", 10).unwrap().to_string(1029),
+            Number::parse("0.2964", 10).unwrap().to_string_radix(1029),
             "0.0304 1024"
         );
     }
@@ -417,7 +943,7 @@ mod tests {
             .unwrap()
             .add(&Number::parse("20.750", 10).unwrap());
         assert_eq!(n.scale(), 3);
-        assert_eq!(n.to_string(10), "31.000");
+        assert_eq!(n.to_string_radix(10), "31.000");
     }
 
     #[test]
@@ -426,7 +952,7 @@ mod tests {
             .unwrap()
             .sub(&Number::parse("20.750", 10).unwrap());
         assert_eq!(n.scale(), 3);
-        assert_eq!(n.to_string(10), "-10.500");
+        assert_eq!(n.to_string_radix(10), "-10.500");
     }
 
     #[test]
@@ -435,14 +961,14 @@ mod tests {
             .unwrap()
             .mul(&Number::parse("5", 10).unwrap(), 10);
         assert_eq!(n.scale(), 0);
-        assert_eq!(n.to_string(10), "10");
+        assert_eq!(n.to_string_radix(10), "10");
     }
 
     #[test]
     fn test_mul() {
         let n = Number::parse("2.25", 10).unwrap().mul(&Number::from(4), 10);
         assert_eq!(n.scale(), 2);
-        assert_eq!(n.to_string(10), "9.00");
+        assert_eq!(n.to_string_radix(10), "9.00");
     }
 
     #[test]
@@ -452,7 +978,7 @@ mod tests {
             .expect("error dividing two positive integers");
 
         assert_eq!(n.scale(), 0);
-        assert_eq!(n.to_string(10), "2");
+        assert_eq!(n.to_string_radix(10), "2");
     }
 
     #[test]
@@ -462,7 +988,7 @@ mod tests {
             .expect("error dividing two positive integers");
 
         assert_eq!(n.scale(), 0);
-        assert_eq!(n.to_string(10), "1");
+        assert_eq!(n.to_string_radix(10), "1");
     }
 
     #[test]
@@ -473,14 +999,14 @@ mod tests {
             .expect("error dividing two positive integers");
 
         assert_eq!(n.scale(), 2);
-        assert_eq!(n.to_string(10), "2.25");
+        assert_eq!(n.to_string_radix(10), "2.25");
     }
 
     #[test]
     fn test_div_by_zero_is_error() {
         let n = Number::parse("4.5", 10).unwrap();
         let result = n.div(&Number::zero(), 2);
-        assert_eq!(result, Err("division by zero"));
+        assert_eq!(result, Err(BcError::DivisionByZero));
     }
 
     #[test]
@@ -490,7 +1016,7 @@ mod tests {
             .pow(&Number::from(3), 10)
             .expect("error raising 2 to the power of 3");
         assert_eq!(n.scale(), 0);
-        assert_eq!(n.to_string(10), "8");
+        assert_eq!(n.to_string_radix(10), "8");
     }
 
     #[test]
@@ -501,7 +1027,7 @@ mod tests {
             .expect("error raising 2 to the power of -3");
 
         assert_eq!(n.scale(), 2);
-        assert_eq!(n.to_string(10), "0.12");
+        assert_eq!(n.to_string_radix(10), "0.12");
     }
 
     #[test]
@@ -511,7 +1037,7 @@ mod tests {
             .expect("error raising 2 to the power of 0");
 
         assert_eq!(n.scale(), 0);
-        assert_eq!(n.to_string(10), "1");
+        assert_eq!(n.to_string_radix(10), "1");
     }
 
     #[test]
@@ -531,25 +1057,81 @@ mod tests {
     #[test]
     fn test_raise_to_non_integer_is_error() {
         let n = Number::from(2);
-        let result = n.pow(&Number::parse("3.5", 10).unwrap(), 2);
-        assert_eq!(result, Err("exponent has to be an integer"));
+        let exponent = Number::parse("3.5", 10).unwrap();
+        let result = n.pow(&exponent, 2);
+        assert_eq!(result, Err(BcError::NonIntegerExponent(exponent)));
     }
 
     #[test]
     fn test_raise_too_large_integer_is_error() {
         let n = Number::from(2);
-        let result = n.pow(
-            &Number::parse("10000000000000000000000000000", 10).unwrap(),
-            2,
-        );
-        assert_eq!(result, Err("exponent is too large"));
+        let exponent = Number::parse("10000000000000000000000000000", 10).unwrap();
+        let result = n.pow(&exponent, 2);
+        assert_eq!(result, Err(BcError::ExponentTooLarge(exponent)));
+    }
+
+    #[test]
+    fn test_raise_to_large_power() {
+        let n = Number::parse("1.0001", 10)
+            .unwrap()
+            .pow(&Number::from(100_000_000), 20)
+            .expect("error raising 1.0001 to the power of 100000000");
+        assert_eq!(n.scale(), 20);
+        assert!(n.to_string_radix(10).starts_with("5341783311503723012354"));
+    }
+
+    #[test]
+    fn test_raise_to_fractional_power() {
+        let n = Number::parse("1.0001", 10)
+            .unwrap()
+            .pow(&Number::from(10000), 20)
+            .unwrap();
+        assert_eq!(n.to_string_radix(10), "2.71814592682522486403");
+    }
+
+    #[test]
+    fn test_raise_to_negative_power() {
+        let n = Number::parse("1.5", 10)
+            .unwrap()
+            .pow(&Number::from(20).negate(), 30)
+            .unwrap();
+        assert_eq!(n.to_string_radix(10), "0.000300728659821717494255819919");
+        let n = Number::parse("1.000001", 10)
+            .unwrap()
+            .pow(&Number::from(6).negate(), 0)
+            .unwrap();
+        assert!(n.is_zero());
+    }
+
+    #[test]
+    fn test_raise_to_large_negative_power_is_zero() {
+        let n = Number::from(2)
+            .pow(&Number::from(100_000_000).negate(), 20)
+            .unwrap();
+        assert!(n.is_zero());
+        assert_eq!(n.scale(), 20);
+    }
+
+    #[test]
+    fn test_raise_zero_to_negative_power_is_error() {
+        let result = Number::zero().pow(&Number::from(1).negate(), 2);
+        assert_eq!(result, Err(BcError::DivisionByZero));
+    }
+
+    #[test]
+    fn test_pow_digits_estimate() {
+        let n = Number::from(10);
+        assert_eq!(n.pow_digits_estimate(&Number::from(100)), 100);
+        assert_eq!(n.pow_digits_estimate(&Number::from(100).negate()), 0);
+        let n = Number::parse("0.1", 10).unwrap();
+        assert_eq!(n.pow_digits_estimate(&Number::from(100).negate()), 100);
     }
 
     #[test]
     fn test_mod_zero_is_error() {
         let n = Number::parse("4.5", 10).unwrap();
         let result = n.modulus(&Number::zero(), 2);
-        assert_eq!(result, Err("division by zero"));
+        assert_eq!(result, Err(BcError::DivisionByZero));
     }
 
     #[test]
@@ -559,13 +1141,288 @@ mod tests {
             .unwrap();
 
         assert_eq!(n.scale(), 1);
-        assert_eq!(n.to_string(10), "1.0");
+        assert_eq!(n.to_string_radix(10), "1.0");
     }
 
     #[test]
     fn test_to_string() {
         let n = Number::parse("4.5", 10).unwrap().negate();
-        assert_eq!(n.to_string(10), "-4.5");
-        assert_eq!(n.to_string(10), "-4.5");
+        assert_eq!(n.to_string_radix(10), "-4.5");
+        assert_eq!(n.to_string_radix(10), "-4.5");
+    }
+
+    #[test]
+    fn test_to_string_above_base_16_writes_decimal_digits() {
+        assert_eq!(Number::from(1295).to_string_radix(36), " 35 35");
+    }
+
+    #[test]
+    fn test_format_with_letter_digits() {
+        let n = Number::from(1295);
+        assert_eq!(n.format(&NumberFormat::new(36)).unwrap(), "ZZ");
+        assert_eq!(n.format(&NumberFormat::new(16)).unwrap(), "50F");
+        let n = Number::parse("10.5", 10).unwrap().negate();
+        assert_eq!(n.format(&NumberFormat::new(20)).unwrap(), "-A.A");
+        assert_eq!(Number::zero().format(&NumberFormat::new(2)).unwrap(), "0");
+    }
+
+    #[test]
+    fn test_format_with_prefix_and_groups() {
+        let format = NumberFormat {
+            prefix: "0b".to_string(),
+            group_size: 4,
+            ..NumberFormat::new(2)
+        };
+        assert_eq!(
+            Number::from(0b1101_0110).format(&format).unwrap(),
+            "0b1101 0110"
+        );
+        assert_eq!(
+            Number::from(0b10_0110).format(&format).unwrap(),
+            "0b10 0110"
+        );
+        assert_eq!(Number::from(5).negate().format(&format).unwrap(), "-0b101");
+        let format = NumberFormat {
+            group_size: 3,
+            group_separator: ',',
+            ..NumberFormat::new(10)
+        };
+        let n = Number::parse("1234567.125", 10).unwrap();
+        assert_eq!(n.format(&format).unwrap(), "1,234,567.125");
+    }
+
+    #[test]
+    fn test_format_in_twos_complement() {
+        let unsigned = NumberFormat {
+            twos_complement: Some(TwosComplement::Unsigned(8)),
+            ..NumberFormat::new(16)
+        };
+        let signed = NumberFormat {
+            twos_complement: Some(TwosComplement::Signed(8)),
+            ..NumberFormat::new(10)
+        };
+        let minus_one = Number::from(1).negate();
+        assert_eq!(minus_one.format(&unsigned).unwrap(), "FF");
+        assert_eq!(Number::from(0x1F0).format(&unsigned).unwrap(), "F0");
+        assert_eq!(Number::from(0xFF).format(&signed).unwrap(), "-1");
+        assert_eq!(Number::from(0x7F).format(&signed).unwrap(), "127");
+        assert_eq!(Number::from(128).negate().format(&signed).unwrap(), "-128");
+        let half = Number::parse("0.5", 10).unwrap();
+        assert_eq!(
+            half.format(&signed),
+            Err(BcError::NonIntegerOperand(half.clone()))
+        );
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let a = Number::from(12);
+        let b = Number::from(10);
+        assert_eq!(a.clone().bit_and(&b).unwrap().to_string_radix(10), "8");
+        assert_eq!(a.clone().bit_or(&b).unwrap().to_string_radix(10), "14");
+        assert_eq!(a.clone().bit_xor(&b).unwrap().to_string_radix(10), "6");
+        assert_eq!(a.clone().bit_not().unwrap().to_string_radix(10), "-13");
+        assert_eq!(
+            a.clone()
+                .shift_left(&Number::from(3))
+                .unwrap()
+                .to_string_radix(10),
+            "96"
+        );
+        assert_eq!(
+            a.shift_right(&Number::from(2)).unwrap().to_string_radix(10),
+            "3"
+        );
+    }
+
+    #[test]
+    fn test_bitwise_operators_use_twos_complement() {
+        let minus_one = Number::from(1).negate();
+        assert_eq!(
+            minus_one
+                .clone()
+                .bit_and(&Number::from(255))
+                .unwrap()
+                .to_string_radix(10),
+            "255"
+        );
+        let minus_five = Number::from(5).negate();
+        assert_eq!(
+            minus_five
+                .shift_right(&Number::from(1))
+                .unwrap()
+                .to_string_radix(10),
+            "-3"
+        );
+    }
+
+    #[test]
+    fn test_bitwise_operators_need_integers() {
+        let half = Number::parse("0.5", 10).unwrap();
+        assert_eq!(
+            Number::from(1).bit_or(&half),
+            Err(BcError::NonIntegerOperand(half.clone()))
+        );
+        assert_eq!(
+            half.clone().bit_not(),
+            Err(BcError::NonIntegerOperand(half.clone()))
+        );
+        // a zero fractional part is accepted
+        let two = Number::parse("2.00", 10).unwrap();
+        assert_eq!(
+            two.bit_xor(&Number::from(3)).unwrap().to_string_radix(10),
+            "1"
+        );
+        assert_eq!(
+            Number::from(1).shift_left(&half),
+            Err(BcError::NonIntegerOperand(half.clone()))
+        );
+        let minus_one = Number::from(1).negate();
+        assert_eq!(
+            Number::from(1).shift_right(&minus_one),
+            Err(BcError::InvalidShift(minus_one.clone()))
+        );
+    }
+
+    #[test]
+    fn test_parse_with_exponent() {
+        let n = Number::parse("6.02e23", 10).unwrap();
+        assert_eq!(n.to_string_radix(10), "602000000000000000000000");
+        assert_eq!(n.scale(), 0);
+        assert_eq!(n.length(), 24);
+        let n = Number::parse("1.5E-9", 10).unwrap();
+        assert_eq!(n.to_string_radix(10), "0.0000000015");
+        assert_eq!(n.scale(), 10);
+        assert_eq!(
+            Number::parse("2e+2", 10).unwrap().to_string_radix(10),
+            "200"
+        );
+        assert_eq!(Number::parse("1e", 10), None);
+        assert_eq!(Number::parse("1e2.5", 10), None);
+        assert_eq!(Number::parse("e2", 10), None);
+        // `e` is a digit in base 16
+        assert_eq!(Number::parse("1e5", 16).unwrap().to_string_radix(10), "485");
+    }
+
+    #[test]
+    fn test_to_scientific_string() {
+        let scientific = |n: &str, significant_digits| {
+            Number::parse(n, 10)
+                .unwrap()
+                .to_scientific_string(&ScientificFormat {
+                    notation: Notation::Scientific,
+                    significant_digits,
+                })
+        };
+        assert_eq!(scientific("602000000000000000000000", 10), "6.02e23");
+        assert_eq!(scientific(".0000000015", 10), "1.5e-9");
+        assert_eq!(scientific("123456", 3), "1.23e5");
+        assert_eq!(scientific("123556", 3), "1.24e5");
+        assert_eq!(scientific("99.96", 3), "1e2");
+        assert_eq!(scientific("0", 3), "0");
+        let n = Number::parse("123456", 10).unwrap().negate();
+        let format = ScientificFormat {
+            notation: Notation::Scientific,
+            significant_digits: 2,
+        };
+        assert_eq!(n.to_scientific_string(&format), "-1.2e5");
+    }
+
+    #[test]
+    fn test_to_engineering_string() {
+        let engineering = |n: &str| {
+            Number::parse(n, 10)
+                .unwrap()
+                .to_scientific_string(&ScientificFormat {
+                    notation: Notation::Engineering,
+                    significant_digits: 4,
+                })
+        };
+        assert_eq!(engineering("602000000000000000000000"), "602e21");
+        assert_eq!(engineering("1500"), "1.5e3");
+        assert_eq!(engineering("10000"), "10e3");
+        assert_eq!(engineering(".0000000015"), "1.5e-9");
+        assert_eq!(engineering(".00012345"), "123.5e-6");
+    }
+
+    #[test]
+    fn test_integer_conversions() {
+        assert_eq!(Number::from(-5i64).to_string_radix(10), "-5");
+        assert_eq!(
+            Number::from(i128::MAX).to_string_radix(10),
+            i128::MAX.to_string()
+        );
+        assert_eq!(i64::try_from(&Number::from(i64::MIN)), Ok(i64::MIN));
+        assert_eq!(u64::try_from(&Number::from(u64::MAX)), Ok(u64::MAX));
+        // a zero fractional part is accepted
+        let n = Number::parse("42.000", 10).unwrap();
+        assert_eq!(i128::try_from(&n), Ok(42));
+
+        let too_large = Number::from(u64::MAX);
+        assert_eq!(
+            i64::try_from(&too_large),
+            Err(EvalError::OutOfRange(too_large.clone()))
+        );
+        let negative = Number::from(-1);
+        assert_eq!(
+            u64::try_from(&negative),
+            Err(EvalError::OutOfRange(negative.clone()))
+        );
+        let fraction = Number::parse("1.5", 10).unwrap();
+        assert_eq!(
+            i64::try_from(&fraction),
+            Err(EvalError::OutOfRange(fraction.clone()))
+        );
+    }
+
+    #[test]
+    fn test_f64_conversions() {
+        assert_eq!(Number::try_from(0.1).unwrap().to_string_radix(10), "0.1");
+        assert_eq!(
+            Number::try_from(-2.5e-3).unwrap().to_string_radix(10),
+            "-0.0025"
+        );
+        assert_eq!(Number::try_from(1e21).unwrap().length(), 22);
+        assert_eq!(
+            Number::try_from(f64::NAN),
+            Err(EvalError::InvalidNumber("NaN".to_string()))
+        );
+        assert_eq!(f64::from(&Number::parse("0.1", 10).unwrap()), 0.1);
+        assert_eq!(f64::from(&Number::from(-3)), -3.0);
+        let huge = Number::parse("1e400", 10).unwrap();
+        assert_eq!(f64::from(&huge), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_from_str_and_display() {
+        let n: Number = "-12.50".parse().unwrap();
+        assert_eq!(format!("{n}"), "-12.50");
+        assert_eq!(n.scale(), 2);
+        assert_eq!(
+            format!("{}", Number::try_from("+6.02e23").unwrap()),
+            "602000000000000000000000"
+        );
+        assert_eq!(format!("{}", Number::try_from(".5").unwrap()), "0.5");
+        for invalid in ["", "-", ".", "1.2.3", "12a", "0x10", "1e", "--1", " 1"] {
+            assert_eq!(
+                invalid.parse::<Number>(),
+                Err(EvalError::InvalidNumber(invalid.to_string())),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_wrap_lines() {
+        assert_eq!(wrap_lines("123456", 4), "123\\\n456");
+        assert_eq!(wrap_lines("1234567", 4), "123\\\n456\\\n7");
+        assert_eq!(wrap_lines("123", 4), "123");
+        assert_eq!(wrap_lines("1234", 4), "123\\\n4");
+        assert_eq!(wrap_lines("1234", 0), "1234");
+        let format = NumberFormat {
+            line_length: 5,
+            ..NumberFormat::new(2)
+        };
+        assert_eq!(Number::from(255).format(&format).unwrap(), "1111\\\n1111");
     }
 }
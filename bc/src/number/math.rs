@@ -0,0 +1,636 @@
+//! Transcendental functions on [`Number`].
+//!
+//! Every function computes an approximation together with a bound on its
+//! error, using binary fixed point arithmetic with more digits than the
+//! requested scale. If the result truncated to the requested scale is not
+//! the same for both ends of the error interval, the computation is
+//! repeated with more digits. This way the results are the exact values
+//! truncated to `scale` digits, as for the other arithmetic operations.
+//!
+//! Every attempt calls an `interrupt` function, which can stop a
+//! computation that takes too long by returning an error.
+
+use super::{Number, NumericResult};
+use crate::error::BcError;
+use bigdecimal::{num_bigint::BigInt, BigDecimal, One, Signed, ToPrimitive, Zero};
+
+/// digits computed in addition to the requested scale in the first attempt
+const GUARD_DIGITS: u64 = 10;
+
+/// attempts of a computation, every attempt doubles the guard digits
+const MAX_ATTEMPTS: u32 = 8;
+
+/// bits computed in addition to the precision, for the rounding errors
+/// of the argument reductions
+const GUARD_BITS: u64 = 16;
+
+/// divides and rounds towards positive infinity, for non-negative values
+fn div_ceil(n: &BigInt, d: &BigInt) -> BigInt {
+    (n + d - 1u32) / d
+}
+
+/// The number of times the argument of a series is reduced. Every
+/// reduction costs about as much as a few terms of the series, and
+/// saves a number of terms proportional to the precision.
+fn reductions(bits: u64) -> u64 {
+    (bits as f64).sqrt() as u64 / 2
+}
+
+/// A fixed point approximation with `bits` fractional bits.
+/// The exact value is within `error` units of the last bit from `value`.
+#[derive(Debug, Clone)]
+struct Approx {
+    value: BigInt,
+    error: BigInt,
+    bits: u64,
+}
+
+impl Approx {
+    fn new(value: BigInt, error: BigInt, bits: u64) -> Self {
+        Self { value, error, bits }
+    }
+
+    fn from_integer(n: i64, bits: u64) -> Self {
+        Self::new(BigInt::from(n) << bits, BigInt::zero(), bits)
+    }
+
+    fn from_decimal(x: &BigDecimal, bits: u64) -> Self {
+        let (digits, scale) = x.as_bigint_and_exponent();
+        let shifted = digits << bits;
+        let (value, error) = if scale <= 0 {
+            (
+                shifted * BigInt::from(10).pow(scale.unsigned_abs() as u32),
+                0,
+            )
+        } else {
+            let denominator = BigInt::from(10).pow(scale as u32);
+            let exact = (&shifted % &denominator).is_zero();
+            (shifted / denominator, if exact { 0 } else { 1 })
+        };
+        Self::new(value, BigInt::from(error), bits)
+    }
+
+    fn is_negative(&self) -> bool {
+        self.value.is_negative()
+    }
+
+    fn add(&self, other: &Approx) -> Approx {
+        Self::new(
+            &self.value + &other.value,
+            &self.error + &other.error,
+            self.bits,
+        )
+    }
+
+    fn sub(&self, other: &Approx) -> Approx {
+        Self::new(
+            &self.value - &other.value,
+            &self.error + &other.error,
+            self.bits,
+        )
+    }
+
+    fn negate(self) -> Approx {
+        Self::new(-self.value, self.error, self.bits)
+    }
+
+    fn mul(&self, other: &Approx) -> Approx {
+        let value = (&self.value * &other.value) >> self.bits;
+        let propagated = self.value.abs() * &other.error
+            + other.value.abs() * &self.error
+            + &self.error * &other.error;
+        let error = (propagated >> self.bits) + 2u32;
+        Self::new(value, error, self.bits)
+    }
+
+    fn mul_integer(&self, n: impl Into<BigInt>) -> Approx {
+        let n = n.into();
+        let error = &self.error * n.abs();
+        Self::new(&self.value * n, error, self.bits)
+    }
+
+    fn div_integer(&self, n: u64) -> Approx {
+        let n = BigInt::from(n);
+        let error = div_ceil(&self.error, &n) + 1u32;
+        Self::new(&self.value / n, error, self.bits)
+    }
+
+    /// divides by `2 ^ n`
+    fn shr(&self, n: u64) -> Approx {
+        Self::new(&self.value >> n, (&self.error >> n) + 2u32, self.bits)
+    }
+
+    /// multiplies by `2 ^ n`
+    fn shl(&self, n: u64) -> Approx {
+        Self::new(&self.value << n, &self.error << n, self.bits)
+    }
+
+    /// # Returns
+    /// `None` if the divisor could be zero
+    fn div(&self, other: &Approx) -> Option<Approx> {
+        let divisor = other.value.abs();
+        if divisor <= other.error {
+            return None;
+        }
+        let value = (&self.value << self.bits) / &other.value;
+        // |a/b - (a+da)/(b+db)| <= (|a| * |db| + |b| * |da|) / (|b| * (|b| - |db|))
+        let propagated = (self.value.abs() * &other.error + &divisor * &self.error) << self.bits;
+        let error = div_ceil(&propagated, &(&divisor * (&divisor - &other.error))) + 1u32;
+        Some(Self::new(value, error, self.bits))
+    }
+
+    /// # Returns
+    /// `None` if the value could be negative
+    fn sqrt(&self) -> Option<Approx> {
+        let low = &self.value - &self.error;
+        if low.is_negative() {
+            return None;
+        }
+        let value = (&self.value << self.bits).sqrt();
+        // sqrt(v + e) - sqrt(v) <= e / (2 * sqrt(v - e)), and sqrt(0 + e) = sqrt(e)
+        let low_root = (low << self.bits).sqrt();
+        let error = if low_root.is_zero() {
+            (&self.error << self.bits).sqrt() + 1u32
+        } else {
+            div_ceil(&(&self.error << self.bits), &(low_root * 2u32)) + 1u32
+        };
+        Some(Self::new(value, error, self.bits))
+    }
+
+    /// Truncates the approximation to `scale` fractional digits.
+    /// # Returns
+    /// `None` if the exact value could be truncated to a different result
+    fn truncate(&self, scale: u64) -> Option<BigDecimal> {
+        let low = self.truncate_value(&self.value - &self.error, scale);
+        let high = self.truncate_value(&self.value + &self.error, scale);
+        (low == high).then_some(low)
+    }
+
+    /// truncates `value`, a fixed point number with the bits of the
+    /// approximation, to `scale` fractional digits
+    fn truncate_value(&self, value: BigInt, scale: u64) -> BigDecimal {
+        let power_of_ten = BigInt::from(10).pow(scale as u32);
+        let digits = (value.abs() * &power_of_ten) >> self.bits;
+        let digits = if value.is_negative() { -digits } else { digits };
+        BigDecimal::new(digits, scale as i64)
+    }
+}
+
+/// Adds the terms of a series until they are smaller than the precision.
+/// `next_term` computes a term from the previous one and its index,
+/// starting from `first`. The terms have to decrease by at least half
+/// every step, so the sum of the skipped terms is bounded by the last one.
+fn sum_series(first: Approx, mut next_term: impl FnMut(&Approx, u64) -> Approx) -> Approx {
+    let mut sum = first.clone();
+    let mut term = first;
+    for i in 1.. {
+        term = next_term(&term, i);
+        sum = sum.add(&term);
+        if term.value.is_zero() {
+            sum.error += &term.error;
+            break;
+        }
+    }
+    sum
+}
+
+/// atan(1 / n) or atanh(1 / n) for an integer n > 1
+fn atan_inverse(n: u64, hyperbolic: bool, bits: u64) -> Approx {
+    let x = Approx::from_integer(1, bits).div_integer(n);
+    let n_squared = n * n;
+    let mut power = x.clone();
+    sum_series(x, |_, i| {
+        power = power.div_integer(n_squared);
+        let term = power.div_integer(2 * i + 1);
+        if i % 2 == 1 && !hyperbolic {
+            term.negate()
+        } else {
+            term
+        }
+    })
+}
+
+/// pi = 16 * atan(1/5) - 4 * atan(1/239)
+fn pi(bits: u64) -> Approx {
+    let a = atan_inverse(5, false, bits).mul_integer(16);
+    let b = atan_inverse(239, false, bits).mul_integer(4);
+    a.sub(&b)
+}
+
+/// ln(2) = 2 * atanh(1/3)
+fn ln_2(bits: u64) -> Approx {
+    atan_inverse(3, true, bits).mul_integer(2)
+}
+
+/// e^x = 1 + x + x^2/2! + x^3/3! + ..., for |x| <= 1/2
+fn exp_series(x: &Approx) -> Approx {
+    let one = Approx::from_integer(1, x.bits);
+    sum_series(one, |term, i| term.mul(x).div_integer(i))
+}
+
+/// sin(x) = x - x^3/3! + x^5/5! - ..., for |x| <= 1
+fn sin_series(x: &Approx) -> Approx {
+    let x_squared = x.mul(x).negate();
+    sum_series(x.clone(), |term, i| {
+        term.mul(&x_squared).div_integer(2 * i * (2 * i + 1))
+    })
+}
+
+/// atan(x) = x - x^3/3 + x^5/5 - ..., for |x| <= 1/2
+fn atan_series(x: &Approx) -> Approx {
+    let x_squared = x.mul(x).negate();
+    let mut power = x.clone();
+    sum_series(x.clone(), |_, i| {
+        power = power.mul(&x_squared);
+        power.div_integer(2 * i + 1)
+    })
+}
+
+/// atanh(x) = x + x^3/3 + x^5/5 + ..., for |x| <= 1/2
+fn atanh_series(x: &Approx) -> Approx {
+    let x_squared = x.mul(x);
+    let mut power = x.clone();
+    sum_series(x.clone(), |_, i| {
+        power = power.mul(&x_squared);
+        power.div_integer(2 * i + 1)
+    })
+}
+
+/// sin(x) for |x| <= 1, using sin(3x) = 3 * sin(x) - 4 * sin(x)^3
+/// to reduce the argument. Every step multiplies the error by at most 3
+fn sin_reduced(x: &Approx) -> Approx {
+    let steps = reductions(x.bits) / 2;
+    let mut y = x.clone();
+    for _ in 0..steps {
+        y = y.div_integer(3);
+    }
+    let mut result = sin_series(&y);
+    for _ in 0..steps {
+        let cube = result.mul(&result).mul(&result);
+        result = result.mul_integer(3).sub(&cube.mul_integer(4));
+    }
+    result
+}
+
+/// cos(x) = 1 - 2 * sin(x / 2)^2, for |x| <= 1
+fn cos_reduced(x: &Approx) -> Approx {
+    let sin = sin_reduced(&x.shr(1));
+    Approx::from_integer(1, x.bits).sub(&sin.mul(&sin).shl(1))
+}
+
+/// Computes `f` with increasing precision until the result
+/// truncated to `scale` digits is known. `f` gets the number
+/// of fractional bits it has to compute. After `MAX_ATTEMPTS`, the
+/// exact value is too close to a multiple of the last digit to tell
+/// on which side it is, and the approximation is truncated instead.
+/// The long loops of `f` also call `interrupt`, and return `None` when
+/// it fails, so that its error is returned by the next call.
+fn truncated(
+    scale: u64,
+    guard: u64,
+    interrupt: &dyn Fn() -> Result<(), BcError>,
+    f: impl Fn(u64) -> Option<Approx>,
+) -> NumericResult {
+    let mut guard = guard.saturating_add(GUARD_DIGITS);
+    for attempt in 1..=MAX_ATTEMPTS {
+        interrupt()?;
+        let digits = scale.saturating_add(guard);
+        let bits = (digits as f64 * std::f64::consts::LOG2_10).ceil() as u64 + GUARD_BITS;
+        if let Some(approx) = f(bits) {
+            if let Some(value) = approx.truncate(scale) {
+                return Ok(Number(value));
+            }
+            if attempt == MAX_ATTEMPTS {
+                return Ok(Number(approx.truncate_value(approx.value.clone(), scale)));
+            }
+        }
+        guard = guard.saturating_mul(2);
+    }
+    interrupt()?;
+    // `f` needs more digits to bound its intermediate results
+    Err(BcError::TooManyDigits)
+}
+
+impl Number {
+    /// An estimate of the number of integer digits in `e ^ self`.
+    /// This is cheap to compute, and can be used to reject arguments
+    /// that would take too long to evaluate.
+    pub fn exp_digits_estimate(&self) -> u64 {
+        let x = self.0.to_f64().unwrap_or(f64::INFINITY);
+        // `as` saturates, so an infinite estimate becomes u64::MAX
+        // and a negative one becomes 0
+        (x * std::f64::consts::LOG10_E).ceil() as u64
+    }
+
+    /// e raised to the power of `self`. `interrupt` is called before
+    /// every attempt of the computation, its error is returned.
+    pub fn exp(&self, scale: u64, interrupt: &dyn Fn() -> Result<(), BcError>) -> NumericResult {
+        if self.is_zero() {
+            return Ok(Self::from(1).rescale(scale));
+        }
+        let negative = self.0.is_negative();
+        let value = self.0.to_f64().unwrap_or(if negative {
+            f64::NEG_INFINITY
+        } else {
+            f64::INFINITY
+        });
+        if value * std::f64::consts::LOG10_E < -(scale as f64 + 2.0) {
+            // the result is smaller than the last digit of `scale`
+            return Ok(Self::zero().rescale(scale));
+        }
+        let x_abs = self.0.abs();
+        let integer_bits = value.abs().log2().ceil().max(0.0) as u64;
+        truncated(scale, self.exp_digits_estimate(), interrupt, |bits| {
+            // e^x = (e^(x / 2^k))^(2^k), every squaring doubles the error
+            let halvings = integer_bits + reductions(bits);
+            let bits = bits + halvings;
+            let x = Approx::from_decimal(&x_abs, bits).shr(halvings);
+            let mut result = exp_series(&x);
+            for _ in 0..halvings {
+                interrupt().ok()?;
+                result = result.mul(&result);
+            }
+            if negative {
+                Approx::from_integer(1, bits).div(&result)
+            } else {
+                Some(result)
+            }
+        })
+    }
+
+    /// natural logarithm of `self`, see `exp` for `interrupt`
+    pub fn ln(&self, scale: u64, interrupt: &dyn Fn() -> Result<(), BcError>) -> NumericResult {
+        if !self.0.is_positive() {
+            return Err(BcError::NonPositiveLogarithm(self.clone()));
+        }
+        if self.0.is_one() {
+            return Ok(Self::zero().rescale(scale));
+        }
+        // ln(x) = k * ln(2) + ln(m), where m = x / 2^k is close to 1.
+        // Dividing by a power of 2 is exact in decimal
+        let k = (self.log10_abs() / std::f64::consts::LOG10_2).round() as i64;
+        let m = if k >= 0 {
+            let power_of_five = BigInt::from(5).pow(k as u32);
+            &self.0 * BigDecimal::new(power_of_five, k)
+        } else {
+            &self.0 * BigDecimal::from(BigInt::from(2).pow(k.unsigned_abs() as u32))
+        };
+        let guard = super::decimal_digits(k.unsigned_abs());
+        truncated(scale, guard, interrupt, |bits| {
+            // ln(m) = 2^j * ln(m^(1 / 2^j)), the result multiplies the error by 2^j
+            let roots = reductions(bits) / 2;
+            let bits = bits + roots;
+            let mut m = Approx::from_decimal(&m, bits);
+            for _ in 0..roots {
+                interrupt().ok()?;
+                m = m.sqrt()?;
+            }
+            // ln(m) = 2 * atanh((m - 1) / (m + 1))
+            let one = Approx::from_integer(1, bits);
+            let y = m.sub(&one).div(&m.add(&one))?;
+            let ln_m = atanh_series(&y).shl(roots + 1);
+            Some(ln_2(bits).mul_integer(k).add(&ln_m))
+        })
+    }
+
+    /// sine of `self`, in radians, see `exp` for `interrupt`
+    pub fn sin(&self, scale: u64, interrupt: &dyn Fn() -> Result<(), BcError>) -> NumericResult {
+        self.sin_cos(scale, false, interrupt)
+    }
+
+    /// cosine of `self`, in radians, see `exp` for `interrupt`
+    pub fn cos(&self, scale: u64, interrupt: &dyn Fn() -> Result<(), BcError>) -> NumericResult {
+        self.sin_cos(scale, true, interrupt)
+    }
+
+    fn sin_cos(
+        &self,
+        scale: u64,
+        cos: bool,
+        interrupt: &dyn Fn() -> Result<(), BcError>,
+    ) -> NumericResult {
+        if self.is_zero() {
+            let result = if cos { Self::from(1) } else { Self::zero() };
+            return Ok(result.rescale(scale));
+        }
+        // the reduction by pi / 2 loses the integer digits of `self`
+        let guard = self.length().saturating_sub(self.scale());
+        truncated(scale, guard, interrupt, |bits| {
+            // the reduction of sin_reduced multiplies the error by 3 every step
+            let bits = bits + reductions(bits);
+            let x = Approx::from_decimal(&self.0, bits);
+            let half_pi = pi(bits).shr(1);
+            // x = r + quadrant * pi / 2, where |r| <= pi / 4.
+            // The quotient is rounded away from zero
+            let half = if x.is_negative() {
+                -&half_pi.value
+            } else {
+                half_pi.value.clone()
+            };
+            let quadrant = (&x.value * 2u32 + half) / (&half_pi.value * 2u32);
+            let r = x.sub(&half_pi.mul_integer(quadrant.clone()));
+            // cos(x) = sin(x + pi / 2)
+            let quadrant = (quadrant % 4u32 + 4u32) % 4u32 + if cos { 1u32 } else { 0 };
+            Some(
+                match quadrant.to_u32().expect("the quadrant is below 5") % 4 {
+                    0 => sin_reduced(&r),
+                    1 => cos_reduced(&r),
+                    2 => sin_reduced(&r).negate(),
+                    _ => cos_reduced(&r).negate(),
+                },
+            )
+        })
+    }
+
+    /// arctangent of `self`, in radians, see `exp` for `interrupt`
+    pub fn atan(&self, scale: u64, interrupt: &dyn Fn() -> Result<(), BcError>) -> NumericResult {
+        if self.is_zero() {
+            return Ok(Self::zero().rescale(scale));
+        }
+        truncated(scale, 0, interrupt, |bits| {
+            // every halving of the argument doubles the error of the result
+            let halvings = (reductions(bits) / 2).max(1);
+            let bits = bits + halvings;
+            let x = Approx::from_decimal(&self.0.abs(), bits);
+            let one = Approx::from_integer(1, bits);
+            // atan(x) = pi / 2 - atan(1 / x), for x > 1
+            let inverted = x.value > one.value;
+            let mut x = if inverted { one.div(&x)? } else { x };
+            // atan(x) = 2 * atan(x / (1 + sqrt(1 + x^2))), until x <= 1 / 2^halvings
+            let mut doublings = 0;
+            while (&x.value << halvings) > one.value {
+                interrupt().ok()?;
+                let root = one.add(&x.mul(&x)).sqrt()?;
+                x = x.div(&one.add(&root))?;
+                doublings += 1;
+            }
+            let mut result = atan_series(&x).shl(doublings);
+            if inverted {
+                result = pi(bits).shr(1).sub(&result);
+            }
+            Some(if self.0.is_negative() {
+                result.negate()
+            } else {
+                result
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(s: &str) -> Number {
+        Number::parse(s, 10).unwrap()
+    }
+
+    fn never() -> Result<(), BcError> {
+        Ok(())
+    }
+
+    #[test]
+    fn test_exp() {
+        assert_eq!(
+            number("1").exp(30, &never).unwrap().to_string_radix(10),
+            "2.718281828459045235360287471352"
+        );
+        assert_eq!(
+            number("1")
+                .negate()
+                .exp(20, &never)
+                .unwrap()
+                .to_string_radix(10),
+            "0.36787944117144232159"
+        );
+        assert_eq!(
+            number("100").exp(5, &never).unwrap().to_string_radix(10),
+            "26881171418161354484126255515800135873611118.77374"
+        );
+        assert_eq!(
+            number("0").exp(3, &never).unwrap().to_string_radix(10),
+            "1.000"
+        );
+    }
+
+    #[test]
+    fn test_exp_of_large_negative_number_is_zero() {
+        let n = number("1000000").negate().exp(20, &never).unwrap();
+        assert!(n.is_zero());
+        assert_eq!(n.scale(), 20);
+    }
+
+    #[test]
+    fn test_ln() {
+        assert_eq!(
+            number("2").ln(30, &never).unwrap().to_string_radix(10),
+            "0.693147180559945309417232121458"
+        );
+        assert_eq!(
+            number("0.001").ln(20, &never).unwrap().to_string_radix(10),
+            "-6.90775527898213705205"
+        );
+        assert_eq!(number("1").ln(5, &never).unwrap().to_string_radix(10), "0");
+        assert_eq!(
+            number("123456789012345678901234567890")
+                .ln(20, &never)
+                .unwrap()
+                .to_string_radix(10),
+            "66.98568871914297739757"
+        );
+    }
+
+    #[test]
+    fn test_ln_of_non_positive_number_is_error() {
+        assert_eq!(
+            number("0").ln(20, &never),
+            Err(BcError::NonPositiveLogarithm(number("0")))
+        );
+        let negative = number("2").negate();
+        assert_eq!(
+            negative.ln(20, &never),
+            Err(BcError::NonPositiveLogarithm(negative.clone()))
+        );
+    }
+
+    #[test]
+    fn test_sin_cos() {
+        assert_eq!(
+            number("1").sin(30, &never).unwrap().to_string_radix(10),
+            "0.841470984807896506652502321630"
+        );
+        assert_eq!(
+            number("1").cos(30, &never).unwrap().to_string_radix(10),
+            "0.540302305868139717400936607442"
+        );
+        assert_eq!(
+            number("2")
+                .negate()
+                .sin(20, &never)
+                .unwrap()
+                .to_string_radix(10),
+            "-0.90929742682568169539"
+        );
+        assert_eq!(
+            number("1000000")
+                .sin(20, &never)
+                .unwrap()
+                .to_string_radix(10),
+            "-0.34999350217129295211"
+        );
+        assert_eq!(
+            number("0").cos(2, &never).unwrap().to_string_radix(10),
+            "1.00"
+        );
+    }
+
+    #[test]
+    fn test_sin_cos_of_huge_numbers() {
+        // the quadrant of these doesn't fit in 64 bits
+        assert_eq!(
+            number("100000000000000000000")
+                .sin(20, &never)
+                .unwrap()
+                .to_string_radix(10),
+            "-0.64525128526578084420"
+        );
+        assert_eq!(
+            number("100000000000000000000")
+                .negate()
+                .cos(5, &never)
+                .unwrap()
+                .to_string_radix(10),
+            "0.76397"
+        );
+    }
+
+    #[test]
+    fn test_interrupt_stops_the_computation() {
+        let interrupt = || Err(BcError::Cancelled);
+        assert_eq!(
+            number("0.3").atan(10000, &interrupt),
+            Err(BcError::Cancelled)
+        );
+        assert_eq!(number("2").ln(10, &interrupt), Err(BcError::Cancelled));
+    }
+
+    #[test]
+    fn test_atan() {
+        assert_eq!(
+            number("1").atan(30, &never).unwrap().to_string_radix(10),
+            "0.785398163397448309615660845819"
+        );
+        assert_eq!(
+            number("0.5").atan(20, &never).unwrap().to_string_radix(10),
+            "0.46364760900080611621"
+        );
+        assert_eq!(
+            number("1000")
+                .negate()
+                .atan(20, &never)
+                .unwrap()
+                .to_string_radix(10),
+            "-1.56979632712822975256"
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Destinations of the output of programs, see `Interpreter::set_output_sink`.
+
+use std::io::Write;
+
+use crate::error::BcError;
+
+/// Receives the text printed by a program as soon as it is printed.
+pub trait OutputSink {
+    /// Writes `text`. An error stops the execution, and `exec` returns it
+    /// as an aborted `ExecutionError`. Errors are usually `BcError::Custom`.
+    fn write_str(&mut self, text: &str) -> Result<(), BcError>;
+}
+
+/// Writes the output to `W`, flushing it after every write
+/// so that the output is shown progressively.
+pub struct WriteSink<W: Write>(pub W);
+
+impl<W: Write> OutputSink for WriteSink<W> {
+    fn write_str(&mut self, text: &str) -> Result<(), BcError> {
+        self.0
+            .write_all(text.as_bytes())
+            .and_then(|_| self.0.flush())
+            .map_err(|e| BcError::Custom(e.to_string()))
+    }
+}
+
+/// Calls the closure with each piece of output.
+impl<F: FnMut(&str) -> Result<(), BcError>> OutputSink for F {
+    fn write_str(&mut self, text: &str) -> Result<(), BcError> {
+        self(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_sink_writes_everything() {
+        let mut sink = WriteSink(Vec::new());
+        sink.write_str("1\n").unwrap();
+        sink.write_str("two").unwrap();
+        assert_eq!(sink.0, b"1\ntwo");
+    }
+
+    #[test]
+    fn test_write_errors_are_custom_errors() {
+        let mut sink = WriteSink(&mut [0u8; 2][..]);
+        assert_eq!(sink.write_str("ab"), Ok(()));
+        assert!(matches!(sink.write_str("c"), Err(BcError::Custom(_))));
+    }
+}
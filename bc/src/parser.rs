@@ -7,6 +7,7 @@
 // SPDX-License-Identifier: MIT
 //
 
+use super::diagnostic::Diagnostic;
 use super::instructions::*;
 
 use pest::error::InputLocation;
@@ -17,8 +18,26 @@ use std::rc::Rc;
 use std::sync::LazyLock;
 
 static PRATT_PARSER: LazyLock<PrattParser<Rule>> = LazyLock::new(|| {
-    // Precedence is defined lowest to highest
+    // Precedence is defined lowest to highest, `!` is below the
+    // relational operators as in GNU bc, so `!a == b` is `!(a == b)`.
+    // The bitwise operators are between the relational and the additive
+    // operators, in the same order as in Python, so `x & 1 == 0` is
+    // `(x & 1) == 0` and `1 << n - 1` is `1 << (n - 1)`. Xor is `^^`
+    // because `^` is the power, and `~` binds as tightly as unary minus
     PrattParser::new()
+        .op(Op::infix(Rule::or, Assoc::Left))
+        .op(Op::infix(Rule::and, Assoc::Left))
+        .op(Op::prefix(Rule::not))
+        .op(Op::infix(Rule::same, Assoc::Left)
+            | Op::infix(Rule::neq, Assoc::Left)
+            | Op::infix(Rule::lt, Assoc::Left)
+            | Op::infix(Rule::leq, Assoc::Left)
+            | Op::infix(Rule::gt, Assoc::Left)
+            | Op::infix(Rule::geq, Assoc::Left))
+        .op(Op::infix(Rule::bit_or, Assoc::Left))
+        .op(Op::infix(Rule::xor, Assoc::Left))
+        .op(Op::infix(Rule::bit_and, Assoc::Left))
+        .op(Op::infix(Rule::shl, Assoc::Left) | Op::infix(Rule::shr, Assoc::Left))
         .op(Op::infix(Rule::add, Assoc::Left) | Op::infix(Rule::sub, Assoc::Left))
         .op(Op::infix(Rule::mul, Assoc::Left)
             | Op::infix(Rule::div, Assoc::Left)
@@ -27,12 +46,13 @@ static PRATT_PARSER: LazyLock<PrattParser<Rule>> = LazyLock::new(|| {
         .op(Op::prefix(Rule::neg))
 });
 
-fn first_char(s: &str) -> char {
-    s.chars().next().unwrap()
+fn as_name(r: Pair<Rule>) -> Rc<str> {
+    Rc::from(r.as_str())
 }
 
-fn as_letter(r: Pair<Rule>) -> char {
-    first_char(r.as_str())
+fn as_span(r: &Pair<Rule>) -> Span {
+    let span = r.as_span();
+    Span::new(span.start(), span.end())
 }
 
 fn first_child(r: Pair<Rule>) -> Pair<Rule> {
@@ -41,8 +61,8 @@ fn first_child(r: Pair<Rule>) -> Pair<Rule> {
 
 fn as_variable(r: Pair<Rule>) -> Variable {
     match r.as_rule() {
-        Rule::variable_number => Variable::Number(as_letter(r)),
-        Rule::array => Variable::Array(as_letter(r)),
+        Rule::variable_number => Variable::Number(as_name(first_child(r))),
+        Rule::array => Variable::Array(as_name(first_child(r))),
         _ => unreachable!(),
     }
 }
@@ -51,6 +71,40 @@ fn to_bc_str(s: &str) -> String {
     s.trim_matches('\"').to_string()
 }
 
+/// removes the quotes around a string of a print statement
+/// and replaces its escape sequences
+fn unescape(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s[1..s.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('\\') => result.push('\\'),
+            Some('"') => result.push('"'),
+            // unknown escape sequences are kept as they are
+            Some(c) => {
+                result.push('\\');
+                result.push(c);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+fn parse_print_item(item: Pair<Rule>) -> PrintItem {
+    match item.as_rule() {
+        Rule::print_string => PrintItem::String(unescape(item.as_str())),
+        Rule::expression => PrintItem::Expr(parse_expr(item)),
+        _ => unreachable!(),
+    }
+}
+
 fn to_bc_number(s: &str) -> String {
     s.trim().replace("\\\n", "")
 }
@@ -65,7 +119,7 @@ fn as_register(r: Pair<Rule>) -> Register {
 }
 
 fn generate_assignment<F>(
-    op_rule: Rule,
+    op: Pair<Rule>,
     named_expr: ExprInstruction,
     value: ExprInstruction,
     assign: F,
@@ -73,7 +127,8 @@ fn generate_assignment<F>(
 where
     F: FnOnce(Box<ExprInstruction>) -> ExprInstruction,
 {
-    match op_rule {
+    let span = as_span(&op);
+    match op.as_rule() {
         Rule::assign => assign(Box::new(value)),
         Rule::add_assign => assign(Box::new(ExprInstruction::Add(
             Box::new(named_expr),
@@ -90,14 +145,17 @@ where
         Rule::div_assign => assign(Box::new(ExprInstruction::Div(
             Box::new(named_expr),
             Box::new(value),
+            span,
         ))),
         Rule::mod_assign => assign(Box::new(ExprInstruction::Mod(
             Box::new(named_expr),
             Box::new(value),
+            span,
         ))),
         Rule::pow_assign => assign(Box::new(ExprInstruction::Pow(
             Box::new(named_expr),
             Box::new(value),
+            span,
         ))),
         _ => unreachable!(),
     }
@@ -106,11 +164,12 @@ where
 fn parse_named_expr(expr: Pair<Rule>) -> NamedExpr {
     let expr = first_child(expr);
     match expr.as_rule() {
-        Rule::variable_number => NamedExpr::VariableNumber(first_char(expr.as_str())),
+        Rule::variable_number => NamedExpr::VariableNumber(as_name(first_child(expr))),
+        Rule::last => NamedExpr::Last,
         Rule::array_item => {
             // name [ index ]
             let mut inner = expr.into_inner();
-            let name = as_letter(inner.next().unwrap());
+            let name = as_name(inner.next().unwrap());
             let index = Box::new(parse_expr(inner.next().unwrap()));
             NamedExpr::ArrayItem { name, index }
         }
@@ -120,7 +179,7 @@ fn parse_named_expr(expr: Pair<Rule>) -> NamedExpr {
 
 fn parse_function_argument(arg: Pair<Rule>) -> FunctionArgument {
     match arg.as_rule() {
-        Rule::array => FunctionArgument::ArrayVariable(as_letter(arg)),
+        Rule::array => FunctionArgument::ArrayVariable(as_name(first_child(arg))),
         Rule::expression => FunctionArgument::Expr(parse_expr(arg)),
         _ => unreachable!(),
     }
@@ -149,13 +208,15 @@ fn parse_primary(expr: Pair<Rule>) -> ExprInstruction {
         Rule::fn_call => {
             // name ( expr* )
             let mut inner = expr.into_inner();
-            let name = as_letter(inner.next().unwrap());
+            let name = inner.next().unwrap();
+            let span = as_span(&name);
+            let name = as_name(name);
             let args = if let Some(args) = inner.next() {
                 args.into_inner().map(parse_function_argument).collect()
             } else {
                 vec![]
             };
-            ExprInstruction::Call { name, args }
+            ExprInstruction::Call { name, args, span }
         }
         Rule::prefix_increment => {
             ExprInstruction::PreIncrement(parse_named_expr(first_child(expr)))
@@ -170,27 +231,22 @@ fn parse_primary(expr: Pair<Rule>) -> ExprInstruction {
             ExprInstruction::PostDecrement(parse_named_expr(first_child(expr)))
         }
         Rule::negation => ExprInstruction::UnaryMinus(Box::new(parse_primary(first_child(expr)))),
-        Rule::register => {
-            let register = match first_child(expr).as_rule() {
-                Rule::scale => Register::Scale,
-                Rule::ibase => Register::IBase,
-                Rule::obase => Register::OBase,
-                _ => unreachable!(),
-            };
-            ExprInstruction::GetRegister(register)
+        Rule::complement => {
+            // the span of `~`
+            let start = expr.as_span().start();
+            let span = Span::new(start, start + 1);
+            ExprInstruction::BitNot(Box::new(parse_primary(first_child(expr))), span)
         }
+        Rule::register => ExprInstruction::GetRegister(as_register(first_child(expr))),
         Rule::register_assignment => {
             // register assign_op expression
             let mut inner = expr.into_inner();
             let register = as_register(first_child(inner.next().unwrap()));
             let op = inner.next().unwrap();
             let value = parse_expr(inner.next().unwrap());
-            generate_assignment(
-                op.as_rule(),
-                ExprInstruction::GetRegister(register),
-                value,
-                |value| ExprInstruction::SetRegister { register, value },
-            )
+            generate_assignment(op, ExprInstruction::GetRegister(register), value, |value| {
+                ExprInstruction::SetRegister { register, value }
+            })
         }
         Rule::assignment => {
             // name assignment_operator expr
@@ -198,12 +254,9 @@ fn parse_primary(expr: Pair<Rule>) -> ExprInstruction {
             let named = parse_named_expr(inner.next().unwrap());
             let op = inner.next().unwrap();
             let value = parse_expr(inner.next().unwrap());
-            generate_assignment(
-                op.as_rule(),
-                ExprInstruction::Named(named.clone()),
-                value,
-                |value| ExprInstruction::Assignment { named, value },
-            )
+            generate_assignment(op, ExprInstruction::Named(named.clone()), value, |value| {
+                ExprInstruction::Assignment { named, value }
+            })
         }
 
         Rule::named_expression => ExprInstruction::Named(parse_named_expr(expr)),
@@ -216,40 +269,45 @@ fn parse_expr(expr: Pair<Rule>) -> ExprInstruction {
         .map_primary(parse_primary)
         .map_prefix(|op, rhs| match op.as_rule() {
             Rule::neg => ExprInstruction::UnaryMinus(Box::new(rhs)),
+            Rule::not => ExprInstruction::Not(Box::new(rhs)),
             _ => unreachable!(),
         })
         .map_infix(|lhs, op, rhs| match op.as_rule() {
             Rule::add => ExprInstruction::Add(Box::new(lhs), Box::new(rhs)),
             Rule::sub => ExprInstruction::Sub(Box::new(lhs), Box::new(rhs)),
             Rule::mul => ExprInstruction::Mul(Box::new(lhs), Box::new(rhs)),
-            Rule::div => ExprInstruction::Div(Box::new(lhs), Box::new(rhs)),
-            Rule::modulus => ExprInstruction::Mod(Box::new(lhs), Box::new(rhs)),
-            Rule::pow => ExprInstruction::Pow(Box::new(lhs), Box::new(rhs)),
+            Rule::div => ExprInstruction::Div(Box::new(lhs), Box::new(rhs), as_span(&op)),
+            Rule::modulus => ExprInstruction::Mod(Box::new(lhs), Box::new(rhs), as_span(&op)),
+            Rule::pow => ExprInstruction::Pow(Box::new(lhs), Box::new(rhs), as_span(&op)),
+            Rule::same => ExprInstruction::Eq(Box::new(lhs), Box::new(rhs)),
+            Rule::neq => ExprInstruction::Ne(Box::new(lhs), Box::new(rhs)),
+            Rule::lt => ExprInstruction::Lt(Box::new(lhs), Box::new(rhs)),
+            Rule::leq => ExprInstruction::Leq(Box::new(lhs), Box::new(rhs)),
+            Rule::gt => ExprInstruction::Gt(Box::new(lhs), Box::new(rhs)),
+            Rule::geq => ExprInstruction::Geq(Box::new(lhs), Box::new(rhs)),
+            Rule::and => ExprInstruction::And(Box::new(lhs), Box::new(rhs)),
+            Rule::or => ExprInstruction::Or(Box::new(lhs), Box::new(rhs)),
+            Rule::bit_and => ExprInstruction::BitAnd(Box::new(lhs), Box::new(rhs), as_span(&op)),
+            Rule::bit_or => ExprInstruction::BitOr(Box::new(lhs), Box::new(rhs), as_span(&op)),
+            Rule::xor => ExprInstruction::BitXor(Box::new(lhs), Box::new(rhs), as_span(&op)),
+            Rule::shl => ExprInstruction::Shl(Box::new(lhs), Box::new(rhs), as_span(&op)),
+            Rule::shr => ExprInstruction::Shr(Box::new(lhs), Box::new(rhs), as_span(&op)),
             _ => unreachable!(),
         })
         .parse(expr.into_inner())
 }
 
 fn parse_condition(expr: Pair<Rule>) -> ConditionInstruction {
-    let expr = first_child(expr);
-    match expr.as_rule() {
-        Rule::expression => ConditionInstruction::Expr(parse_expr(expr)),
-        Rule::relational_expression => {
-            let mut inner = expr.into_inner();
-            let left = parse_expr(inner.next().unwrap());
-            let op = inner.next().unwrap();
-            let right = parse_expr(inner.next().unwrap());
-            match op.as_rule() {
-                Rule::same => ConditionInstruction::Eq(left, right),
-                Rule::neq => ConditionInstruction::Ne(left, right),
-                Rule::lt => ConditionInstruction::Lt(left, right),
-                Rule::leq => ConditionInstruction::Leq(left, right),
-                Rule::gt => ConditionInstruction::Gt(left, right),
-                Rule::geq => ConditionInstruction::Geq(left, right),
-                _ => unreachable!(),
-            }
-        }
-        _ => unreachable!(),
+    // a relational operator at the top level of the condition
+    // is evaluated directly instead of producing a number
+    match parse_expr(first_child(expr)) {
+        ExprInstruction::Eq(left, right) => ConditionInstruction::Eq(*left, *right),
+        ExprInstruction::Ne(left, right) => ConditionInstruction::Ne(*left, *right),
+        ExprInstruction::Lt(left, right) => ConditionInstruction::Lt(*left, *right),
+        ExprInstruction::Leq(left, right) => ConditionInstruction::Leq(*left, *right),
+        ExprInstruction::Gt(left, right) => ConditionInstruction::Gt(*left, *right),
+        ExprInstruction::Geq(left, right) => ConditionInstruction::Geq(*left, *right),
+        expr => ConditionInstruction::Expr(expr),
     }
 }
 
@@ -263,7 +321,7 @@ fn parse_stmt(
     in_loop: bool,
     statements: &mut Vec<StmtInstruction>,
     source_locations: &mut Vec<usize>,
-) -> Result<usize, PestError> {
+) -> Result<usize, Box<PestError>> {
     let stmt = first_child(stmt);
     let (line, _) = stmt.line_col();
     source_locations.push(line);
@@ -271,27 +329,44 @@ fn parse_stmt(
     match stmt.as_rule() {
         Rule::break_stmt => {
             if !in_loop {
-                return Err(pest::error::Error::new_from_span(
+                return Err(Box::new(pest::error::Error::new_from_span(
                     pest::error::ErrorVariant::CustomError {
                         message: "break outside of loop".to_string(),
                     },
                     stmt.as_span(),
-                ));
+                )));
             }
             statements.push(StmtInstruction::Break);
         }
+        Rule::continue_stmt => {
+            if !in_loop {
+                return Err(Box::new(pest::error::Error::new_from_span(
+                    pest::error::ErrorVariant::CustomError {
+                        message: "continue outside of loop".to_string(),
+                    },
+                    stmt.as_span(),
+                )));
+            }
+            statements.push(StmtInstruction::Continue);
+        }
         Rule::quit => {
             statements.push(StmtInstruction::Quit);
         }
+        Rule::halt => {
+            statements.push(StmtInstruction::Halt);
+        }
+        Rule::limits => {
+            statements.push(StmtInstruction::Limits);
+        }
         Rule::return_stmt => {
             // return ( "(" expr? ")" )?
             if !in_function {
-                return Err(pest::error::Error::new_from_span(
+                return Err(Box::new(pest::error::Error::new_from_span(
                     pest::error::ErrorVariant::CustomError {
                         message: "return outside of function".to_string(),
                     },
                     stmt.as_span(),
-                ));
+                )));
             }
             let mut inner = stmt.into_inner();
             if let Some(expr) = inner.next() {
@@ -301,21 +376,35 @@ fn parse_stmt(
             }
         }
         Rule::if_stmt => {
-            // if (condition) stmt
+            // if (condition) stmt [else stmt]
             let mut inner = stmt.into_inner();
             let condition = parse_condition(inner.next().unwrap());
             let mut body = Vec::new();
-            instruction_count = parse_stmt(
+            let body_instruction_count = parse_stmt(
                 inner.next().unwrap(),
                 in_function,
                 in_loop,
                 &mut body,
                 source_locations,
             )?;
+            let mut else_body = Vec::new();
+            let mut else_instruction_count = 0;
+            if let Some(else_stmt) = inner.next() {
+                else_instruction_count = parse_stmt(
+                    else_stmt,
+                    in_function,
+                    in_loop,
+                    &mut else_body,
+                    source_locations,
+                )?;
+            }
+            instruction_count += body_instruction_count + else_instruction_count;
             statements.push(StmtInstruction::If {
                 condition,
-                instruction_count,
+                instruction_count: body_instruction_count,
                 body,
+                else_instruction_count,
+                else_body,
             });
         }
         Rule::while_stmt => {
@@ -323,16 +412,17 @@ fn parse_stmt(
             let mut inner = stmt.into_inner();
             let condition = parse_condition(inner.next().unwrap());
             let mut body = Vec::new();
-            instruction_count = parse_stmt(
+            let body_instruction_count = parse_stmt(
                 inner.next().unwrap(),
                 in_function,
                 true,
                 &mut body,
                 source_locations,
             )?;
+            instruction_count += body_instruction_count;
             statements.push(StmtInstruction::While {
                 condition,
-                instruction_count,
+                instruction_count: body_instruction_count,
                 body,
             });
         }
@@ -343,18 +433,19 @@ fn parse_stmt(
             let condition = parse_condition(inner.next().unwrap());
             let update = parse_expr(inner.next().unwrap());
             let mut body = Vec::new();
-            instruction_count = parse_stmt(
+            let body_instruction_count = parse_stmt(
                 inner.next().unwrap(),
                 in_function,
                 true,
                 &mut body,
                 source_locations,
             )?;
+            instruction_count += body_instruction_count;
             statements.push(StmtInstruction::For {
                 init,
                 condition,
                 update,
-                instruction_count,
+                instruction_count: body_instruction_count,
                 body,
             });
         }
@@ -373,6 +464,10 @@ fn parse_stmt(
         Rule::string => {
             statements.push(StmtInstruction::String(to_bc_str(stmt.as_str())));
         }
+        Rule::print_stmt => {
+            let items = stmt.into_inner().map(parse_print_item).collect();
+            statements.push(StmtInstruction::Print(items));
+        }
         Rule::expression => {
             statements.push(StmtInstruction::Expr(parse_expr(stmt)));
         }
@@ -381,12 +476,15 @@ fn parse_stmt(
     Ok(instruction_count)
 }
 
-fn parse_function(func: Pair<Rule>, file: Rc<str>) -> Result<Function, PestError> {
-    let mut function = func.into_inner();
+fn parse_function(func: Pair<Rule>, file: Rc<str>) -> Result<Function, Box<PestError>> {
+    let (line, _) = func.line_col();
+    let mut function = func
+        .into_inner()
+        .filter(|pair| pair.as_rule() != Rule::body_start);
 
-    // define letter ( parameter_list ) auto_define_list statement_list end
+    // define name ( parameter_list ) auto_define_list statement_list end
 
-    let name = as_letter(function.next().unwrap());
+    let name = as_name(function.next().unwrap());
 
     let mut parameters = Vec::new();
     let parameter_list = function.next().unwrap();
@@ -420,6 +518,7 @@ fn parse_function(func: Pair<Rule>, file: Rc<str>) -> Result<Function, PestError
         locals: locals.into(),
         body: body.into(),
         source_locations: source_locations.into(),
+        line,
     })
 }
 
@@ -439,15 +538,53 @@ fn location_end(loc: &InputLocation) -> usize {
 fn is_incomplete(text: &str, error: &PestError) -> bool {
     let pos = location_end(&error.location);
     // The program is incomplete if either:
-    // - we expect something after the end of the input
+    // - we expect something after the end of the input, like the body of a
+    //   function, the closing brace of a block, or the rest of an expression
+    //   after an operator followed by a backslash newline
     // - the error occurs at the start of an incomplete comment
     // - the error occurs at the start of an incomplete string
+    let rest = &text[pos..];
     pos == text.len()
-        || text.as_bytes()[pos..text.len().min(pos + 2)] == [b'/', b'*']
-        || text.as_bytes()[pos] == b'"'
+        || (rest.starts_with("/*") && !rest[2..].contains("*/"))
+        || (rest.starts_with('"') && !rest[1..].contains('"'))
 }
 
-fn improve_pest_error(err: PestError, text: &str, file_path: Option<&str>) -> PestError {
+fn is_bitwise_rule(rule: Rule) -> bool {
+    matches!(
+        rule,
+        Rule::bit_and | Rule::bit_or | Rule::xor | Rule::shl | Rule::shr | Rule::complement
+    )
+}
+
+fn improve_pest_error(
+    err: PestError,
+    text: &str,
+    file_path: Option<&str>,
+    options: &ParseOptions,
+) -> PestError {
+    // the grammar always has the bitwise operators, don't suggest
+    // them when they are not enabled
+    let err = match err.variant {
+        pest::error::ErrorVariant::ParsingError {
+            mut positives,
+            negatives,
+        } if !options.bitwise_operators => {
+            positives.retain(|rule| !is_bitwise_rule(*rule));
+            let variant = pest::error::ErrorVariant::ParsingError {
+                positives,
+                negatives,
+            };
+            match err.location {
+                InputLocation::Pos(pos) => {
+                    PestError::new_from_pos(variant, Position::new(text, pos).unwrap())
+                }
+                InputLocation::Span((start, end)) => {
+                    PestError::new_from_span(variant, pest::Span::new(text, start, end).unwrap())
+                }
+            }
+        }
+        _ => err,
+    };
     let err = if let Some(path) = file_path {
         err.with_path(path)
     } else {
@@ -474,7 +611,16 @@ fn improve_pest_error(err: PestError, text: &str, file_path: Option<&str>) -> Pe
             Rule::div => "'/'",
             Rule::modulus => "'%'",
             Rule::pow => "'^'",
+            Rule::xor => "'^^'",
+            Rule::bit_and => "'&'",
+            Rule::bit_or => "'|'",
+            Rule::shl => "'<<'",
+            Rule::shr => "'>>'",
+            Rule::complement => "'~'",
+            Rule::and => "'&&'",
+            Rule::or => "'||'",
             Rule::neg => "'-'",
+            Rule::not => "'!'",
             Rule::assign => "'='",
             Rule::add_assign => "'+='",
             Rule::sub_assign => "'-='",
@@ -490,6 +636,7 @@ fn improve_pest_error(err: PestError, text: &str, file_path: Option<&str>) -> Pe
             Rule::geq => "'>='",
             Rule::primary => "expression",
             Rule::auto_define_list => "auto definitions",
+            Rule::body_start => "'{'",
             _ => return format!("{:?}", rule),
         }
         .to_string()
@@ -503,6 +650,22 @@ pub struct ParseError {
     pub is_incomplete: bool,
 }
 
+impl ParseError {
+    /// Returns the diagnostics of the errors, `text` is the parsed text.
+    pub fn diagnostics(&self, text: &str) -> Vec<Diagnostic> {
+        self.errors
+            .iter()
+            .map(|err| {
+                let span = match err.location {
+                    InputLocation::Pos(pos) => Span::new(pos, pos),
+                    InputLocation::Span((start, end)) => Span::new(start, end),
+                };
+                Diagnostic::new(err.variant.message(), span, text)
+            })
+            .collect()
+    }
+}
+
 impl std::fmt::Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         for err in &self.errors {
@@ -523,7 +686,12 @@ fn next_checkpoint(text: &str) -> usize {
     index
 }
 
-fn gather_errors(text: &str, file_path: Option<&str>, first_error: PestError) -> ParseError {
+fn gather_errors(
+    text: &str,
+    file_path: Option<&str>,
+    options: &ParseOptions,
+    first_error: PestError,
+) -> ParseError {
     let last_location_end = location_end(&first_error.location);
 
     // if the text is not from a file and the error is consistent
@@ -535,7 +703,7 @@ fn gather_errors(text: &str, file_path: Option<&str>, first_error: PestError) ->
         };
     }
 
-    let mut errors = vec![improve_pest_error(first_error, text, file_path)];
+    let mut errors = vec![improve_pest_error(first_error, text, file_path, options)];
     let mut byte_index = last_location_end + next_checkpoint(&text[last_location_end..]);
 
     while byte_index < text.len() {
@@ -550,7 +718,12 @@ fn gather_errors(text: &str, file_path: Option<&str>, first_error: PestError) ->
                 byte_index += location_end(&err.location);
                 let err_correct_position =
                     PestError::new_from_pos(err.variant, Position::new(text, byte_index).unwrap());
-                errors.push(improve_pest_error(err_correct_position, text, file_path));
+                errors.push(improve_pest_error(
+                    err_correct_position,
+                    text,
+                    file_path,
+                    options,
+                ));
             }
         }
         byte_index += next_checkpoint(&text[byte_index..]);
@@ -561,11 +734,60 @@ fn gather_errors(text: &str, file_path: Option<&str>, first_error: PestError) ->
     }
 }
 
+/// Extensions of the language that are disabled by default.
+#[derive(Clone, Debug, Default)]
+pub struct ParseOptions {
+    /// the integer bitwise operators `&`, `|`, `^^` (xor), `~`, `<<` and `>>`
+    pub bitwise_operators: bool,
+    /// numbers with an exponent, as in `6.02e23`. The `e` is lowercase
+    /// because `E` is a digit, and they are only valid when `ibase` is 10
+    pub exponent_literals: bool,
+}
+
+/// Returns an error for every use of an extension that is not enabled in `options`.
+fn check_extensions(program: &Pair<Rule>, options: &ParseOptions) -> Vec<PestError> {
+    let mut errors = Vec::new();
+    for pair in program.clone().into_inner().flatten() {
+        let is_bitwise = is_bitwise_rule(pair.as_rule());
+        let has_exponent = pair.as_rule() == Rule::number && pair.as_str().contains('e');
+        let message = if is_bitwise && !options.bitwise_operators {
+            "bitwise operators are not enabled"
+        } else if has_exponent && !options.exponent_literals {
+            "exponent literals are not enabled"
+        } else {
+            continue;
+        };
+        errors.push(pest::error::Error::new_from_span(
+            pest::error::ErrorVariant::CustomError {
+                message: message.to_string(),
+            },
+            pair.as_span(),
+        ));
+    }
+    errors
+}
+
 pub fn parse_program(text: &str, file_path: Option<&str>) -> Result<Program, ParseError> {
+    parse_program_with_options(text, file_path, &ParseOptions::default())
+}
+
+/// Parses `text` like `parse_program`, accepting the extensions enabled in `options`.
+pub fn parse_program_with_options(
+    text: &str,
+    file_path: Option<&str>,
+    options: &ParseOptions,
+) -> Result<Program, ParseError> {
     let program = match BcParser::parse(Rule::program, text) {
         Ok(mut program) => program.next().unwrap(),
-        Err(err) => return Err(gather_errors(text, file_path, err)),
+        Err(err) => return Err(gather_errors(text, file_path, options, err)),
     };
+    let errors = check_extensions(&program, options);
+    if !errors.is_empty() {
+        return Err(ParseError {
+            errors,
+            is_incomplete: false,
+        });
+    }
     let file = Rc::<str>::from(file_path.unwrap_or(""));
     let mut instructions = Vec::new();
     let mut source_locations = Vec::new();
@@ -578,16 +800,16 @@ pub fn parse_program(text: &str, file_path: Option<&str>) -> Result<Program, Par
                     if let Err(e) =
                         parse_stmt(stmt, false, false, &mut instructions, &mut source_locations)
                     {
-                        errors.push(e);
+                        errors.push(*e);
                     }
                 }
             }
             Rule::function => match parse_function(item, file.clone()) {
                 Ok(f) => instructions.push(StmtInstruction::DefineFunction {
-                    name: f.name,
+                    name: f.name.clone(),
                     function: f,
                 }),
-                Err(e) => errors.push(e),
+                Err(e) => errors.push(*e),
             },
             Rule::EOI => {}
             _ => unreachable!(),
@@ -670,17 +892,95 @@ mod test {
     #[test]
     fn test_parse_named() {
         let expr = parse_expr("a\n");
-        assert_eq!(expr, ExprInstruction::Named(NamedExpr::VariableNumber('a')));
+        assert_eq!(
+            expr,
+            ExprInstruction::Named(NamedExpr::VariableNumber("a".into()))
+        );
         let expr = parse_expr("a[1]\n");
         assert_eq!(
             expr,
             (ExprInstruction::Named(NamedExpr::ArrayItem {
-                name: 'a',
+                name: "a".into(),
                 index: Box::new(ExprInstruction::Number("1".to_string()))
             }))
         );
     }
 
+    #[test]
+    fn test_parse_last() {
+        assert_eq!(
+            parse_expr("last * .\n"),
+            ExprInstruction::Mul(
+                Box::new(ExprInstruction::Named(NamedExpr::Last)),
+                Box::new(ExprInstruction::Named(NamedExpr::Last))
+            )
+        );
+        assert_eq!(
+            parse_expr(". = .5\n"),
+            ExprInstruction::Assignment {
+                named: NamedExpr::Last,
+                value: Box::new(ExprInstruction::Number(".5".to_string()))
+            }
+        );
+        assert_eq!(
+            parse_expr("lastly\n"),
+            ExprInstruction::Named(NamedExpr::VariableNumber("lastly".into()))
+        );
+    }
+
+    #[test]
+    fn test_parse_long_names() {
+        let expr = parse_expr("total_2 = rate * tax(x1, rates[])\n");
+        assert_eq!(
+            expr,
+            ExprInstruction::Assignment {
+                named: NamedExpr::VariableNumber("total_2".into()),
+                value: Box::new(ExprInstruction::Mul(
+                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "rate".into()
+                    ))),
+                    Box::new(ExprInstruction::Call {
+                        name: "tax".into(),
+                        args: vec![
+                            FunctionArgument::Expr(ExprInstruction::Named(
+                                NamedExpr::VariableNumber("x1".into())
+                            )),
+                            FunctionArgument::ArrayVariable("rates".into())
+                        ],
+                        span: Span::new(17, 20),
+                    })
+                ))
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_names_starting_with_keywords() {
+        assert_eq!(
+            parse_expr("scales\n"),
+            ExprInstruction::Named(NamedExpr::VariableNumber("scales".into()))
+        );
+        assert_eq!(
+            parse_expr("returned = 1\n"),
+            ExprInstruction::Assignment {
+                named: NamedExpr::VariableNumber("returned".into()),
+                value: Box::new(ExprInstruction::Number("1".to_string()))
+            }
+        );
+        assert_eq!(parse_stmt("quit\n"), StmtInstruction::Quit);
+        assert_eq!(
+            parse_expr("scale\n"),
+            ExprInstruction::GetRegister(Register::Scale)
+        );
+    }
+
+    #[test]
+    fn test_keywords_are_not_names() {
+        assert!(parse_program("while = 1\n", None).is_err());
+        assert!(parse_program("define if(x) {\n}\n", None).is_err());
+        assert!(parse_program("else[1] = 2\n", None).is_err());
+    }
+
     #[test]
     fn test_parse_register_get() {
         let expr = parse_expr("scale\n");
@@ -724,7 +1024,7 @@ mod test {
         let expr = parse_expr("++d\n");
         assert_eq!(
             expr,
-            ExprInstruction::PreIncrement(NamedExpr::VariableNumber('d'))
+            ExprInstruction::PreIncrement(NamedExpr::VariableNumber("d".into()))
         );
     }
 
@@ -733,7 +1033,7 @@ mod test {
         let expr = parse_expr("--g\n");
         assert_eq!(
             expr,
-            ExprInstruction::PreDecrement(NamedExpr::VariableNumber('g'))
+            ExprInstruction::PreDecrement(NamedExpr::VariableNumber("g".into()))
         );
     }
 
@@ -742,7 +1042,7 @@ mod test {
         let expr = parse_expr("e++\n");
         assert_eq!(
             expr,
-            ExprInstruction::PostIncrement(NamedExpr::VariableNumber('e'))
+            ExprInstruction::PostIncrement(NamedExpr::VariableNumber("e".into()))
         );
     }
 
@@ -751,7 +1051,7 @@ mod test {
         let expr = parse_expr("f--\n");
         assert_eq!(
             expr,
-            ExprInstruction::PostDecrement(NamedExpr::VariableNumber('f'))
+            ExprInstruction::PostDecrement(NamedExpr::VariableNumber("f".into()))
         );
     }
 
@@ -761,8 +1061,9 @@ mod test {
         assert_eq!(
             expr,
             ExprInstruction::Call {
-                name: 'a',
-                args: vec![]
+                name: "a".into(),
+                args: vec![],
+                span: Span::new(0, 1),
             }
         );
     }
@@ -773,10 +1074,11 @@ mod test {
         assert_eq!(
             expr,
             ExprInstruction::Call {
-                name: 'a',
+                name: "a".into(),
                 args: vec![FunctionArgument::Expr(ExprInstruction::Number(
                     "1".to_string()
-                )),]
+                )),],
+                span: Span::new(0, 1),
             }
         );
     }
@@ -787,12 +1089,15 @@ mod test {
         assert_eq!(
             expr,
             ExprInstruction::Call {
-                name: 'a',
+                name: "a".into(),
                 args: vec![
                     FunctionArgument::Expr(ExprInstruction::Number("1".to_string())),
-                    FunctionArgument::Expr(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
-                    FunctionArgument::ArrayVariable('b')
-                ]
+                    FunctionArgument::Expr(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into()
+                    ))),
+                    FunctionArgument::ArrayVariable("b".into())
+                ],
+                span: Span::new(0, 1),
             }
         );
     }
@@ -868,7 +1173,7 @@ mod test {
         assert_eq!(
             expr,
             (ExprInstruction::Assignment {
-                named: NamedExpr::VariableNumber('a'),
+                named: NamedExpr::VariableNumber("a".into()),
                 value: Box::new(ExprInstruction::Number("1".to_string()))
             })
         );
@@ -881,7 +1186,7 @@ mod test {
             expr,
             (ExprInstruction::Assignment {
                 named: NamedExpr::ArrayItem {
-                    name: 'a',
+                    name: "a".into(),
                     index: Box::new(ExprInstruction::Number("20".to_string())),
                 },
                 value: Box::new(ExprInstruction::Number("1".to_string()))
@@ -895,9 +1200,11 @@ mod test {
         assert_eq!(
             expr,
             (ExprInstruction::Assignment {
-                named: NamedExpr::VariableNumber('a'),
+                named: NamedExpr::VariableNumber("a".into()),
                 value: Box::new(ExprInstruction::Add(
-                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
+                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into()
+                    ))),
                     Box::new(ExprInstruction::Number("1".to_string()))
                 ))
             })
@@ -906,9 +1213,11 @@ mod test {
         assert_eq!(
             expr,
             (ExprInstruction::Assignment {
-                named: NamedExpr::VariableNumber('a'),
+                named: NamedExpr::VariableNumber("a".into()),
                 value: Box::new(ExprInstruction::Sub(
-                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
+                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into()
+                    ))),
                     Box::new(ExprInstruction::Number("1".to_string()))
                 ))
             })
@@ -917,9 +1226,11 @@ mod test {
         assert_eq!(
             expr,
             (ExprInstruction::Assignment {
-                named: NamedExpr::VariableNumber('a'),
+                named: NamedExpr::VariableNumber("a".into()),
                 value: Box::new(ExprInstruction::Mul(
-                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
+                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into()
+                    ))),
                     Box::new(ExprInstruction::Number("1".to_string()))
                 ))
             })
@@ -928,10 +1239,13 @@ mod test {
         assert_eq!(
             expr,
             (ExprInstruction::Assignment {
-                named: NamedExpr::VariableNumber('a'),
+                named: NamedExpr::VariableNumber("a".into()),
                 value: Box::new(ExprInstruction::Div(
-                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
-                    Box::new(ExprInstruction::Number("1".to_string()))
+                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into()
+                    ))),
+                    Box::new(ExprInstruction::Number("1".to_string())),
+                    Span::new(2, 4),
                 ))
             })
         );
@@ -939,10 +1253,13 @@ mod test {
         assert_eq!(
             expr,
             (ExprInstruction::Assignment {
-                named: NamedExpr::VariableNumber('a'),
+                named: NamedExpr::VariableNumber("a".into()),
                 value: Box::new(ExprInstruction::Mod(
-                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
-                    Box::new(ExprInstruction::Number("1".to_string()))
+                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into()
+                    ))),
+                    Box::new(ExprInstruction::Number("1".to_string())),
+                    Span::new(2, 4),
                 ))
             })
         );
@@ -950,10 +1267,13 @@ mod test {
         assert_eq!(
             expr,
             (ExprInstruction::Assignment {
-                named: NamedExpr::VariableNumber('a'),
+                named: NamedExpr::VariableNumber("a".into()),
                 value: Box::new(ExprInstruction::Pow(
-                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
-                    Box::new(ExprInstruction::Number("1".to_string()))
+                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into()
+                    ))),
+                    Box::new(ExprInstruction::Number("1".to_string())),
+                    Span::new(2, 4),
                 ))
             })
         );
@@ -983,7 +1303,8 @@ mod test {
             expr,
             (ExprInstruction::Pow(
                 Box::new(ExprInstruction::Number("1".to_string())),
-                Box::new(ExprInstruction::Number("2".to_string()))
+                Box::new(ExprInstruction::Number("2".to_string())),
+                Span::new(2, 3),
             ))
         );
     }
@@ -1019,7 +1340,8 @@ mod test {
                 Box::new(ExprInstruction::Number("1".to_string())),
                 Box::new(ExprInstruction::Pow(
                     Box::new(ExprInstruction::Number("2".to_string())),
-                    Box::new(ExprInstruction::Number("3".to_string()))
+                    Box::new(ExprInstruction::Number("3".to_string())),
+                    Span::new(6, 7),
                 ))
             )
         );
@@ -1030,7 +1352,8 @@ mod test {
                 Box::new(ExprInstruction::UnaryMinus(Box::new(
                     ExprInstruction::Number("1".to_string())
                 ))),
-                Box::new(ExprInstruction::Number("2".to_string()))
+                Box::new(ExprInstruction::Number("2".to_string())),
+                Span::new(3, 4),
             )
         );
     }
@@ -1044,8 +1367,10 @@ mod test {
                 Box::new(ExprInstruction::Number("1".to_string())),
                 Box::new(ExprInstruction::Pow(
                     Box::new(ExprInstruction::Number("2".to_string())),
-                    Box::new(ExprInstruction::Number("3".to_string()))
-                ))
+                    Box::new(ExprInstruction::Number("3".to_string())),
+                    Span::new(6, 7),
+                )),
+                Span::new(2, 3),
             )
         );
     }
@@ -1075,11 +1400,12 @@ mod test {
         assert_eq!(
             stmt,
             StmtInstruction::DefineFunction {
-                name: 'f',
+                name: "f".into(),
                 function: Function {
-                    name: 'f',
+                    name: "f".into(),
                     body: [StmtInstruction::Return].into(),
                     source_locations: [2].into(),
+                    line: 1,
                     ..Default::default()
                 }
             }
@@ -1092,14 +1418,15 @@ mod test {
         assert_eq!(
             stmt,
             StmtInstruction::DefineFunction {
-                name: 'f',
+                name: "f".into(),
                 function: Function {
-                    name: 'f',
+                    name: "f".into(),
                     source_locations: [2].into(),
                     body: [StmtInstruction::ReturnExpr(ExprInstruction::Number(
                         "1".to_string()
                     ))]
                     .into(),
+                    line: 1,
                     ..Default::default()
                 }
             }
@@ -1113,11 +1440,126 @@ mod test {
             stmt,
             StmtInstruction::If {
                 condition: ConditionInstruction::Leq(
-                    ExprInstruction::Named(NamedExpr::VariableNumber('x')),
-                    ExprInstruction::Named(NamedExpr::VariableNumber('z'))
+                    ExprInstruction::Named(NamedExpr::VariableNumber("x".into())),
+                    ExprInstruction::Named(NamedExpr::VariableNumber("z".into()))
                 ),
                 instruction_count: 0,
-                body: vec![]
+                body: vec![],
+                else_instruction_count: 0,
+                else_body: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_if_else() {
+        let stmt = parse_stmt("if (x) a = 2 else {\n b = 3\n c = 4\n}\n");
+        assert_eq!(
+            stmt,
+            StmtInstruction::If {
+                condition: ConditionInstruction::Expr(ExprInstruction::Named(
+                    NamedExpr::VariableNumber("x".into())
+                )),
+                instruction_count: 1,
+                body: vec![StmtInstruction::Expr(ExprInstruction::Assignment {
+                    named: NamedExpr::VariableNumber("a".into()),
+                    value: Box::new(ExprInstruction::Number("2".to_string()))
+                })],
+                else_instruction_count: 2,
+                else_body: vec![
+                    StmtInstruction::Expr(ExprInstruction::Assignment {
+                        named: NamedExpr::VariableNumber("b".into()),
+                        value: Box::new(ExprInstruction::Number("3".to_string()))
+                    }),
+                    StmtInstruction::Expr(ExprInstruction::Assignment {
+                        named: NamedExpr::VariableNumber("c".into()),
+                        value: Box::new(ExprInstruction::Number("4".to_string()))
+                    })
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_if_else_source_locations() {
+        let program = parse_program("if (x) {\n 1\n} else\n{\n 2\n}\n3\n", None).unwrap();
+        assert_eq!(program.source_locations, vec![1, 2, 5, 7]);
+    }
+
+    #[test]
+    fn test_parse_relational_expression_as_value() {
+        let expr = parse_expr("(a < b) + 1\n");
+        assert_eq!(
+            expr,
+            ExprInstruction::Add(
+                Box::new(ExprInstruction::Lt(
+                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "a".into()
+                    ))),
+                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "b".into()
+                    )))
+                )),
+                Box::new(ExprInstruction::Number("1".to_string()))
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_boolean_operators_precedence() {
+        let expr = parse_expr("a || b && !c < d + 1\n");
+        assert_eq!(
+            expr,
+            ExprInstruction::Or(
+                Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                    "a".into()
+                ))),
+                Box::new(ExprInstruction::And(
+                    Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "b".into()
+                    ))),
+                    Box::new(ExprInstruction::Not(Box::new(ExprInstruction::Lt(
+                        Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                            "c".into()
+                        ))),
+                        Box::new(ExprInstruction::Add(
+                            Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                                "d".into()
+                            ))),
+                            Box::new(ExprInstruction::Number("1".to_string()))
+                        ))
+                    ))))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_boolean_condition() {
+        let stmt = parse_stmt("if (a > 0 && b != 0) 1\n");
+        assert_eq!(
+            stmt,
+            StmtInstruction::If {
+                condition: ConditionInstruction::Expr(ExprInstruction::And(
+                    Box::new(ExprInstruction::Gt(
+                        Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                            "a".into()
+                        ))),
+                        Box::new(ExprInstruction::Number("0".to_string()))
+                    )),
+                    Box::new(ExprInstruction::Ne(
+                        Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                            "b".into()
+                        ))),
+                        Box::new(ExprInstruction::Number("0".to_string()))
+                    ))
+                )),
+                instruction_count: 1,
+                body: vec![StmtInstruction::Expr(ExprInstruction::Number(
+                    "1".to_string()
+                ))],
+                else_instruction_count: 0,
+                else_body: vec![],
             }
         );
     }
@@ -1129,24 +1571,26 @@ mod test {
             stmt,
             StmtInstruction::If {
                 condition: ConditionInstruction::Leq(
-                    ExprInstruction::Named(NamedExpr::VariableNumber('x')),
-                    ExprInstruction::Named(NamedExpr::VariableNumber('z'))
+                    ExprInstruction::Named(NamedExpr::VariableNumber("x".into())),
+                    ExprInstruction::Named(NamedExpr::VariableNumber("z".into()))
                 ),
                 instruction_count: 3,
                 body: vec![
                     StmtInstruction::Expr(ExprInstruction::Assignment {
-                        named: NamedExpr::VariableNumber('a'),
+                        named: NamedExpr::VariableNumber("a".into()),
                         value: Box::new(ExprInstruction::Number("2".to_string()))
                     }),
                     StmtInstruction::Expr(ExprInstruction::Assignment {
-                        named: NamedExpr::VariableNumber('b'),
+                        named: NamedExpr::VariableNumber("b".into()),
                         value: Box::new(ExprInstruction::Number("3".to_string()))
                     }),
                     StmtInstruction::Expr(ExprInstruction::Assignment {
-                        named: NamedExpr::VariableNumber('c'),
+                        named: NamedExpr::VariableNumber("c".into()),
                         value: Box::new(ExprInstruction::Number("3".to_string()))
                     })
-                ]
+                ],
+                else_instruction_count: 0,
+                else_body: vec![],
             }
         );
     }
@@ -1159,12 +1603,16 @@ mod test {
             StmtInstruction::While {
                 condition: ConditionInstruction::Ne(
                     ExprInstruction::Add(
-                        Box::new(ExprInstruction::Named(NamedExpr::VariableNumber('x'))),
+                        Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                            "x".into()
+                        ))),
                         Box::new(ExprInstruction::Number("2".to_string()))
                     ),
                     ExprInstruction::Add(
                         Box::new(ExprInstruction::Number("2".to_string())),
-                        Box::new(ExprInstruction::Named(NamedExpr::VariableNumber('y')))
+                        Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                            "y".into()
+                        )))
                     )
                 ),
                 instruction_count: 0,
@@ -1180,8 +1628,8 @@ mod test {
             stmt,
             StmtInstruction::While {
                 condition: ConditionInstruction::Leq(
-                    ExprInstruction::Named(NamedExpr::VariableNumber('x')),
-                    ExprInstruction::Named(NamedExpr::VariableNumber('z'))
+                    ExprInstruction::Named(NamedExpr::VariableNumber("x".into())),
+                    ExprInstruction::Named(NamedExpr::VariableNumber("z".into()))
                 ),
                 instruction_count: 3,
                 body: vec![
@@ -1190,16 +1638,23 @@ mod test {
                         Box::new(ExprInstruction::Number("2".to_string()))
                     )),
                     StmtInstruction::Expr(ExprInstruction::Assignment {
-                        named: NamedExpr::VariableNumber('x'),
+                        named: NamedExpr::VariableNumber("x".into()),
                         value: Box::new(ExprInstruction::Add(
-                            Box::new(ExprInstruction::Named(NamedExpr::VariableNumber('x'))),
+                            Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                                "x".into()
+                            ))),
                             Box::new(ExprInstruction::Div(
-                                Box::new(ExprInstruction::Named(NamedExpr::VariableNumber('y'))),
-                                Box::new(ExprInstruction::Number("2".to_string()))
+                                Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                                    "y".into()
+                                ))),
+                                Box::new(ExprInstruction::Number("2".to_string())),
+                                Span::new(32, 33),
                             ))
                         ))
                     }),
-                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber('u')))
+                    StmtInstruction::Expr(ExprInstruction::Named(NamedExpr::VariableNumber(
+                        "u".into()
+                    )))
                 ]
             }
         );
@@ -1212,14 +1667,14 @@ mod test {
             stmt,
             StmtInstruction::For {
                 init: ExprInstruction::Assignment {
-                    named: NamedExpr::VariableNumber('i'),
+                    named: NamedExpr::VariableNumber("i".into()),
                     value: Box::new(ExprInstruction::Number("0".to_string()))
                 },
                 condition: ConditionInstruction::Lt(
-                    ExprInstruction::Named(NamedExpr::VariableNumber('i')),
+                    ExprInstruction::Named(NamedExpr::VariableNumber("i".into())),
                     ExprInstruction::Number("10".to_string())
                 ),
-                update: ExprInstruction::PostIncrement(NamedExpr::VariableNumber('i')),
+                update: ExprInstruction::PostIncrement(NamedExpr::VariableNumber("i".into())),
                 instruction_count: 0,
                 body: vec![]
             }
@@ -1233,17 +1688,17 @@ mod test {
             stmt,
             StmtInstruction::For {
                 init: ExprInstruction::Assignment {
-                    named: NamedExpr::VariableNumber('i'),
+                    named: NamedExpr::VariableNumber("i".into()),
                     value: Box::new(ExprInstruction::Number("0".to_string()))
                 },
                 condition: ConditionInstruction::Lt(
-                    ExprInstruction::Named(NamedExpr::VariableNumber('i')),
+                    ExprInstruction::Named(NamedExpr::VariableNumber("i".into())),
                     ExprInstruction::Number("10".to_string())
                 ),
-                update: ExprInstruction::PostIncrement(NamedExpr::VariableNumber('i')),
+                update: ExprInstruction::PostIncrement(NamedExpr::VariableNumber("i".into())),
                 instruction_count: 1,
                 body: vec![StmtInstruction::Expr(ExprInstruction::Assignment {
-                    named: NamedExpr::VariableNumber('a'),
+                    named: NamedExpr::VariableNumber("a".into()),
                     value: Box::new(ExprInstruction::Number("2".to_string()))
                 })]
             }
@@ -1303,6 +1758,36 @@ mod test {
         assert_eq!(stmt, StmtInstruction::String("hello\nworld".to_string()));
     }
 
+    #[test]
+    fn test_strings_keep_comments() {
+        let stmt = parse_stmt("\"a /* b */ # c\"\n");
+        assert_eq!(stmt, StmtInstruction::String("a /* b */ # c".to_string()));
+    }
+
+    #[test]
+    fn test_parse_print() {
+        let stmt = parse_stmt("print \"total: \", t, \"\\n\"\n");
+        assert_eq!(
+            stmt,
+            StmtInstruction::Print(vec![
+                PrintItem::String("total: ".to_string()),
+                PrintItem::Expr(ExprInstruction::Named(NamedExpr::VariableNumber(
+                    "t".into()
+                ))),
+                PrintItem::String("\n".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_print_escapes() {
+        let stmt = parse_stmt("print \"a\\tb\\\\c\\\"d\\qe\\n\"\n");
+        assert_eq!(
+            stmt,
+            StmtInstruction::Print(vec![PrintItem::String("a\tb\\c\"d\\qe\n".to_string())])
+        );
+    }
+
     #[test]
     fn test_parse_expression() {
         let stmt = parse_stmt("1 + 2\n");
@@ -1361,7 +1846,9 @@ mod test {
         assert_eq!(
             program.instructions[0],
             StmtInstruction::Expr(ExprInstruction::Add(
-                Box::new(ExprInstruction::Named(NamedExpr::VariableNumber('a'))),
+                Box::new(ExprInstruction::Named(NamedExpr::VariableNumber(
+                    "a".into()
+                ))),
                 Box::new(ExprInstruction::Number("2".to_string()))
             ))
         );
@@ -1373,7 +1860,8 @@ mod test {
         assert_eq!(
             func,
             Function {
-                name: 'f',
+                name: "f".into(),
+                line: 1,
                 ..Default::default()
             }
         );
@@ -1385,14 +1873,15 @@ mod test {
         assert_eq!(
             func,
             Function {
-                name: 'f',
+                name: "f".into(),
                 parameters: [
-                    Variable::Array('a'),
-                    Variable::Number('b'),
-                    Variable::Number('c'),
-                    Variable::Array('d')
+                    Variable::Array("a".into()),
+                    Variable::Number("b".into()),
+                    Variable::Number("c".into()),
+                    Variable::Array("d".into())
                 ]
                 .into(),
+                line: 1,
                 ..Default::default()
             }
         );
@@ -1404,14 +1893,15 @@ mod test {
         assert_eq!(
             func,
             Function {
-                name: 'f',
+                name: "f".into(),
                 locals: [
-                    Variable::Array('a'),
-                    Variable::Number('b'),
-                    Variable::Number('c'),
-                    Variable::Array('d')
+                    Variable::Array("a".into()),
+                    Variable::Number("b".into()),
+                    Variable::Number("c".into()),
+                    Variable::Array("d".into())
                 ]
                 .into(),
+                line: 1,
                 ..Default::default()
             }
         );
@@ -1423,18 +1913,28 @@ mod test {
         assert_eq!(
             func,
             Function {
-                name: 'f',
+                name: "f".into(),
                 source_locations: [2].into(),
                 body: [StmtInstruction::Expr(ExprInstruction::Add(
                     Box::new(ExprInstruction::Number("1".to_string())),
                     Box::new(ExprInstruction::Number("2".to_string()))
                 ))]
                 .into(),
+                line: 1,
                 ..Default::default()
             }
         );
     }
 
+    #[test]
+    fn test_parse_function_on_a_single_line() {
+        let func = parse_function("define f(x) { auto y; y = x; return (y) }\n");
+        assert_eq!(func.name.as_ref(), "f");
+        assert_eq!(func.parameters.as_ref(), [Variable::Number("x".into())]);
+        assert_eq!(func.locals.as_ref(), [Variable::Number("y".into())]);
+        assert_eq!(func.body.len(), 2);
+    }
+
     #[test]
     fn test_ignore_comments() {
         let program = parse_program(
@@ -1459,6 +1959,23 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_ignore_line_comments() {
+        let program = parse_program("# line comment\n1 + 2 # trailing comment\n#\n3\n", None)
+            .expect("error parsing statements with line comments");
+        assert_eq!(
+            program.instructions,
+            vec![
+                StmtInstruction::Expr(ExprInstruction::Add(
+                    Box::new(ExprInstruction::Number("1".to_string())),
+                    Box::new(ExprInstruction::Number("2".to_string()))
+                )),
+                StmtInstruction::Expr(ExprInstruction::Number("3".to_string())),
+            ]
+        );
+        assert_eq!(program.source_locations, vec![2, 4]);
+    }
+
     #[test]
     fn test_ignore_backslash_newline() {
         let stmt = parse_stmt("1 + \\\n2\n");
@@ -1477,6 +1994,30 @@ mod test {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_continue_outside_of_loop_is_an_error() {
+        assert!(parse_program("continue\n", None).is_err());
+        assert!(parse_program("define f() {\ncontinue\n}\n", None).is_err());
+        assert_eq!(
+            parse_stmt("while (1) continue\n"),
+            StmtInstruction::While {
+                condition: ConditionInstruction::Expr(ExprInstruction::Number("1".to_string())),
+                instruction_count: 1,
+                body: vec![StmtInstruction::Continue],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_halt_and_limits() {
+        assert_eq!(parse_stmt("halt\n"), StmtInstruction::Halt);
+        assert_eq!(parse_stmt("limits\n"), StmtInstruction::Limits);
+        assert_eq!(
+            parse_expr("halted\n"),
+            ExprInstruction::Named(NamedExpr::VariableNumber("halted".into()))
+        );
+    }
+
     #[test]
     fn test_return_outside_of_function_is_an_error() {
         let result = parse_program("return\n", None);
@@ -1496,6 +2037,13 @@ mod test {
         assert!(program_err("1 + 2;\"this is the start of a string\n").is_incomplete);
     }
 
+    #[test]
+    fn test_errors_at_closed_strings_and_comments_are_not_incomplete() {
+        assert!(!program_err("1 \"x\"\n").is_incomplete);
+        assert!(!program_err("1 /* c */ \"a\"\n").is_incomplete);
+        assert!(!program_err("1 /* c */ 2\n").is_incomplete);
+    }
+
     #[test]
     fn test_partial_function_requires_is_incomplete() {
         assert!(program_err("define f() {\n").is_incomplete);
@@ -1504,6 +2052,37 @@ mod test {
         assert!(program_err("define f() {\n auto a, b, c[];\n1 + 2;\n").is_incomplete);
     }
 
+    #[test]
+    fn test_function_without_body_is_incomplete() {
+        assert!(program_err("define f(x)\n").is_incomplete);
+        assert!(program_err("define f()").is_incomplete);
+        assert!(program_err("1 + 2\ndefine f(a, b[])\n\n").is_incomplete);
+        assert!(program_err("define f(x)\n{\n").is_incomplete);
+        assert!(!program_err("define f(x) 1\n").is_incomplete);
+    }
+
+    #[test]
+    fn test_function_body_can_start_on_the_next_line() {
+        let program = parse_program("define f(x)\n{\nreturn (x)\n}\n", None).unwrap();
+        assert_eq!(program.instructions.len(), 1);
+    }
+
+    #[test]
+    fn test_trailing_operator_is_incomplete() {
+        assert!(program_err("1 +").is_incomplete);
+        assert!(program_err("x = 2 * \\\n").is_incomplete);
+        assert!(program_err("a[1] += \\\n").is_incomplete);
+        assert!(program_err("1 < 2 && \\\n").is_incomplete);
+        assert!(!program_err("1 +* 2\n").is_incomplete);
+    }
+
+    #[test]
+    fn test_newline_after_operator_is_an_error() {
+        assert!(!program_err("1 +\n").is_incomplete);
+        assert!(parse_program("x = 1 +\n2\n", None).is_err());
+        assert!(parse_program("x =\n1\n", None).is_err());
+    }
+
     #[test]
     fn test_unclosed_braced_statement_list_is_incomplete() {
         assert!(program_err("{\n").is_incomplete);
@@ -1551,4 +2130,191 @@ mod test {
             .expect("error parsing program");
         assert_eq!(program.source_locations, [1, 2, 3, 6]);
     }
+
+    #[test]
+    fn test_printed_program_parses_to_the_same_instructions() {
+        let text = concat!(
+            "a = -(1 + 2) * 3 ^ -b[i++] / (c = 4) % 5 - -1 ^ 2\n",
+            "scale += length(x) - sqrt(.5)\n",
+            "while (x != 0) { --x; if (f(x, y[]) >= 2) break else x }\n",
+            "for (i = 0; i < 3; ++i) { if (x) continue; halt }\n",
+            "limits\n",
+            "for (i = 0; i < 10; ++i) \"loop\"\n",
+            "define f(n, v[]) {\nauto i, w[]\nreturn (n - v[0])\n}\n",
+            "1 - (2 - 3) ^ 2 ^ 3\n",
+            "x = (a < b) + !(c || d) * (e && f == 2)\n",
+            "!!a == b || !(a + 1)\n",
+            "print \"a\\tb\\\\c\\\"d\\n\", 1 + 2, last_value\n",
+        );
+        let program = parse_program(text, None).unwrap();
+        let mut printed = String::new();
+        for stmt in &program.instructions {
+            printed.push_str(&stmt.to_string());
+        }
+        let reparsed = parse_program(&printed, None).unwrap();
+        assert_eq!(program.instructions.len(), reparsed.instructions.len());
+        for (stmt, reparsed) in program
+            .instructions
+            .iter()
+            .zip(reparsed.instructions.iter())
+        {
+            match (stmt, reparsed) {
+                (
+                    StmtInstruction::DefineFunction { function, .. },
+                    StmtInstruction::DefineFunction {
+                        function: reparsed, ..
+                    },
+                ) => {
+                    // source locations and spans are different, as the printed
+                    // function doesn't keep the original formatting
+                    assert_eq!(function.to_string(), reparsed.to_string());
+                    assert_eq!(function.parameters, reparsed.parameters);
+                    assert_eq!(function.locals, reparsed.locals);
+                }
+                // the spans are different, and are not printed
+                _ => assert_eq!(stmt.to_string(), reparsed.to_string()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_printed_math_library_parses_to_the_same_functions() {
+        let text = include_str!("math_functions.bc");
+        let program = parse_program(text, None).unwrap();
+        for stmt in program.instructions {
+            if let StmtInstruction::DefineFunction { function, .. } = stmt {
+                let reparsed = parse_function(&format!("{function}\n"));
+                // the spans are different, so the bodies are compared as printed
+                assert_eq!(function.to_string(), reparsed.to_string());
+                assert_eq!(function.parameters, reparsed.parameters);
+                assert_eq!(function.locals, reparsed.locals);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_error_diagnostics() {
+        let text = "1 + 2\n3 +* 4\n";
+        let diagnostics = program_err(text).diagnostics(text);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (2, 4));
+        assert_eq!(diagnostics[0].span, Span::new(9, 9));
+    }
+
+    #[test]
+    fn test_random_functions_are_ordinary_names() {
+        assert_eq!(
+            parse_expr("irand(6)\n"),
+            ExprInstruction::Call {
+                name: "irand".into(),
+                args: vec![FunctionArgument::Expr(ExprInstruction::Number(
+                    "6".to_string()
+                ))],
+                span: Span::new(0, 5),
+            }
+        );
+        assert_eq!(
+            parse_expr("seed\n"),
+            ExprInstruction::Named(NamedExpr::VariableNumber("seed".into()))
+        );
+        assert!(parse_program("define rand(seed) { auto irand\n return (seed) }\n", None).is_ok());
+    }
+
+    fn parse_with_bitwise_operators(input: &str) -> String {
+        let options = ParseOptions {
+            bitwise_operators: true,
+            ..Default::default()
+        };
+        let program =
+            parse_program_with_options(input, None, &options).expect("error parsing program");
+        program.instructions[0].to_string()
+    }
+
+    #[test]
+    fn test_bitwise_operators_precedence() {
+        let same = |input: &str, parenthesized: &str| {
+            assert_eq!(
+                parse_with_bitwise_operators(input),
+                parse_with_bitwise_operators(parenthesized)
+            )
+        };
+        // bitwise operators bind tighter than comparisons, unlike in C
+        same("x & 1 == 0\n", "(x & 1) == 0\n");
+        same("1 << n - 1\n", "1 << (n - 1)\n");
+        same("a | b ^^ c & d\n", "a | (b ^^ (c & d))\n");
+        same("a && b & c\n", "a && (b & c)\n");
+        // `^` is still the power operator
+        same("2 ^^ 3 ^ 2\n", "2 ^^ (3 ^ 2)\n");
+        assert_eq!(
+            parse_with_bitwise_operators("(a | b) & ~c\n"),
+            "(a | b) & ~c\n"
+        );
+    }
+
+    #[test]
+    fn test_bitwise_operators_are_not_enabled_by_default() {
+        let err = program_err("x = 1 << 2\ny = ~x\n");
+        assert!(!err.is_incomplete);
+        let diagnostics = err.diagnostics("x = 1 << 2\ny = ~x\n");
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| (d.message.as_str(), d.span))
+                .collect::<Vec<_>>(),
+            vec![
+                ("bitwise operators are not enabled", Span::new(6, 8)),
+                ("bitwise operators are not enabled", Span::new(15, 17)),
+            ]
+        );
+        // the logical operators are not affected
+        parse_expr("a && b || !c\n");
+    }
+
+    #[test]
+    fn test_syntax_errors_suggest_bitwise_operators_only_when_enabled() {
+        let message = |options: &ParseOptions| {
+            let err = parse_program_with_options("1 \"x\"\n", None, options).unwrap_err();
+            err.diagnostics("1 \"x\"\n")[0].message.clone()
+        };
+        let plain = message(&ParseOptions::default());
+        assert!(plain.ends_with("'^', '&&', or '||'"), "{plain}");
+        let bitwise = message(&ParseOptions {
+            bitwise_operators: true,
+            ..Default::default()
+        });
+        assert!(
+            bitwise.ends_with("'&', '|', '^^', '<<', or '>>'"),
+            "{bitwise}"
+        );
+    }
+
+    #[test]
+    fn test_exponent_literals() {
+        let options = ParseOptions {
+            exponent_literals: true,
+            ..Default::default()
+        };
+        let program = parse_program_with_options("6.02e23 * 1.5e-9\n", None, &options).unwrap();
+        assert_eq!(
+            program.instructions,
+            vec![StmtInstruction::Expr(ExprInstruction::Mul(
+                Box::new(ExprInstruction::Number("6.02e23".to_string())),
+                Box::new(ExprInstruction::Number("1.5e-9".to_string()))
+            ))]
+        );
+
+        let err = program_err("x = 2e+2\n");
+        let diagnostics = err.diagnostics("x = 2e+2\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "exponent literals are not enabled");
+        assert_eq!(diagnostics[0].span, Span::new(4, 8));
+        // `E` is a digit, so this is a hexadecimal number minus 9
+        assert_eq!(
+            parse_expr("1E-9\n"),
+            ExprInstruction::Sub(
+                Box::new(ExprInstruction::Number("1E".to_string())),
+                Box::new(ExprInstruction::Number("9".to_string()))
+            )
+        );
+    }
 }
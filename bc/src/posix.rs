@@ -0,0 +1,357 @@
+//! Detection of the extensions to the POSIX bc language,
+//! used to reject them in standard mode (`bc -s`).
+
+use std::rc::Rc;
+
+use crate::instructions::{
+    ConditionInstruction, ExprInstruction, FunctionArgument, NamedExpr, PrintItem, Program,
+    StmtInstruction, Variable,
+};
+
+/// A use of a language feature that is not part of POSIX bc.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Extension {
+    pub feature: &'static str,
+    pub line: usize,
+    pub file: Rc<str>,
+}
+
+impl std::fmt::Display for Extension {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let line = self.line;
+        if self.file.is_empty() {
+            write!(
+                f,
+                "error (line {line}): POSIX bc does not allow {}",
+                self.feature
+            )
+        } else {
+            write!(
+                f,
+                "error ({} - line {line}): POSIX bc does not allow {}",
+                self.file, self.feature
+            )
+        }
+    }
+}
+
+struct Checker<'a> {
+    file: &'a Rc<str>,
+    extensions: Vec<Extension>,
+}
+
+impl Checker<'_> {
+    fn report(&mut self, feature: &'static str, line: usize) {
+        let already_reported = self
+            .extensions
+            .iter()
+            .any(|e| e.feature == feature && e.line == line);
+        if !already_reported {
+            self.extensions.push(Extension {
+                feature,
+                line,
+                file: self.file.clone(),
+            });
+        }
+    }
+
+    fn check_name(&mut self, name: &str, line: usize) {
+        if name.len() > 1 {
+            self.report("names longer than one letter", line);
+        }
+    }
+
+    fn check_named(&mut self, named: &NamedExpr, line: usize) {
+        match named {
+            NamedExpr::VariableNumber(name) => self.check_name(name, line),
+            NamedExpr::Last => self.report("last", line),
+            NamedExpr::ArrayItem { name, index } => {
+                self.check_name(name, line);
+                self.check_expr(index, line);
+            }
+        }
+    }
+
+    fn check_expr(&mut self, expr: &ExprInstruction, line: usize) {
+        match expr {
+            ExprInstruction::Number(n) => {
+                if n.contains('e') {
+                    self.report("exponent literals", line);
+                }
+            }
+            ExprInstruction::GetRegister(_) => {}
+            ExprInstruction::Named(named)
+            | ExprInstruction::PreIncrement(named)
+            | ExprInstruction::PreDecrement(named)
+            | ExprInstruction::PostIncrement(named)
+            | ExprInstruction::PostDecrement(named) => self.check_named(named, line),
+            ExprInstruction::Builtin { arg, .. } => self.check_expr(arg, line),
+            ExprInstruction::Call { name, args, .. } => {
+                self.check_name(name, line);
+                for arg in args {
+                    match arg {
+                        FunctionArgument::Expr(expr) => self.check_expr(expr, line),
+                        FunctionArgument::ArrayVariable(name) => self.check_name(name, line),
+                    }
+                }
+            }
+            ExprInstruction::Assignment { named, value } => {
+                self.check_named(named, line);
+                self.check_expr(value, line);
+            }
+            ExprInstruction::SetRegister { value, .. } => self.check_expr(value, line),
+            ExprInstruction::UnaryMinus(expr) => self.check_expr(expr, line),
+            ExprInstruction::Add(lhs, rhs)
+            | ExprInstruction::Sub(lhs, rhs)
+            | ExprInstruction::Mul(lhs, rhs)
+            | ExprInstruction::Div(lhs, rhs, _)
+            | ExprInstruction::Mod(lhs, rhs, _)
+            | ExprInstruction::Pow(lhs, rhs, _) => {
+                self.check_expr(lhs, line);
+                self.check_expr(rhs, line);
+            }
+            ExprInstruction::Eq(lhs, rhs)
+            | ExprInstruction::Ne(lhs, rhs)
+            | ExprInstruction::Lt(lhs, rhs)
+            | ExprInstruction::Leq(lhs, rhs)
+            | ExprInstruction::Gt(lhs, rhs)
+            | ExprInstruction::Geq(lhs, rhs) => {
+                self.report("relational operators outside of conditions", line);
+                self.check_expr(lhs, line);
+                self.check_expr(rhs, line);
+            }
+            ExprInstruction::Not(expr) => {
+                self.report("the ! operator", line);
+                self.check_expr(expr, line);
+            }
+            ExprInstruction::And(lhs, rhs) => {
+                self.report("the && operator", line);
+                self.check_expr(lhs, line);
+                self.check_expr(rhs, line);
+            }
+            ExprInstruction::Or(lhs, rhs) => {
+                self.report("the || operator", line);
+                self.check_expr(lhs, line);
+                self.check_expr(rhs, line);
+            }
+            ExprInstruction::BitAnd(lhs, rhs, _)
+            | ExprInstruction::BitOr(lhs, rhs, _)
+            | ExprInstruction::BitXor(lhs, rhs, _)
+            | ExprInstruction::Shl(lhs, rhs, _)
+            | ExprInstruction::Shr(lhs, rhs, _) => {
+                self.report("bitwise operators", line);
+                self.check_expr(lhs, line);
+                self.check_expr(rhs, line);
+            }
+            ExprInstruction::BitNot(expr, _) => {
+                self.report("bitwise operators", line);
+                self.check_expr(expr, line);
+            }
+        }
+    }
+
+    fn check_condition(&mut self, condition: &ConditionInstruction, line: usize) {
+        match condition {
+            ConditionInstruction::Expr(expr) => self.check_expr(expr, line),
+            ConditionInstruction::Eq(lhs, rhs)
+            | ConditionInstruction::Ne(lhs, rhs)
+            | ConditionInstruction::Lt(lhs, rhs)
+            | ConditionInstruction::Leq(lhs, rhs)
+            | ConditionInstruction::Gt(lhs, rhs)
+            | ConditionInstruction::Geq(lhs, rhs) => {
+                self.check_expr(lhs, line);
+                self.check_expr(rhs, line);
+            }
+        }
+    }
+
+    /// checks the statements in `body`, the first of which has index
+    /// `*index` in `source_locations`, advancing `index` past them
+    fn check_body(
+        &mut self,
+        body: &[StmtInstruction],
+        source_locations: &[usize],
+        index: &mut usize,
+    ) {
+        for stmt in body {
+            if let StmtInstruction::DefineFunction { name, function } = stmt {
+                let line = function.line;
+                self.check_name(name, line);
+                for variable in function.parameters.iter().chain(function.locals.iter()) {
+                    let (Variable::Number(name) | Variable::Array(name)) = variable;
+                    self.check_name(name, line);
+                }
+                self.check_body(&function.body, &function.source_locations, &mut 0);
+                continue;
+            }
+
+            let line = source_locations.get(*index).copied().unwrap_or(0);
+            *index += 1;
+            match stmt {
+                StmtInstruction::Break
+                | StmtInstruction::Quit
+                | StmtInstruction::Return
+                | StmtInstruction::String(_) => {}
+                StmtInstruction::Continue => self.report("the continue statement", line),
+                StmtInstruction::Halt => self.report("the halt statement", line),
+                StmtInstruction::Limits => self.report("the limits statement", line),
+                StmtInstruction::ReturnExpr(expr) | StmtInstruction::Expr(expr) => {
+                    self.check_expr(expr, line)
+                }
+                StmtInstruction::If {
+                    condition,
+                    body,
+                    else_body,
+                    ..
+                } => {
+                    self.check_condition(condition, line);
+                    self.check_body(body, source_locations, index);
+                    if !else_body.is_empty() {
+                        self.report("the else branch", line);
+                        self.check_body(else_body, source_locations, index);
+                    }
+                }
+                StmtInstruction::While {
+                    condition, body, ..
+                } => {
+                    self.check_condition(condition, line);
+                    self.check_body(body, source_locations, index);
+                }
+                StmtInstruction::For {
+                    init,
+                    condition,
+                    update,
+                    body,
+                    ..
+                } => {
+                    self.check_expr(init, line);
+                    self.check_condition(condition, line);
+                    self.check_expr(update, line);
+                    self.check_body(body, source_locations, index);
+                }
+                StmtInstruction::Print(items) => {
+                    self.report("the print statement", line);
+                    for item in items {
+                        if let PrintItem::Expr(expr) = item {
+                            self.check_expr(expr, line);
+                        }
+                    }
+                }
+                StmtInstruction::DefineFunction { .. } => unreachable!(),
+            }
+        }
+    }
+}
+
+/// Returns the extensions to POSIX bc used by `program`, in the order
+/// they appear. `#` comments and the other extensions that don't
+/// change the meaning of the program are not reported.
+pub fn find_extensions(program: &Program) -> Vec<Extension> {
+    let mut checker = Checker {
+        file: &program.file,
+        extensions: Vec::new(),
+    };
+    checker.check_body(&program.instructions, &program.source_locations, &mut 0);
+    checker.extensions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse_program, parse_program_with_options, ParseOptions};
+
+    fn features(text: &str) -> Vec<(&'static str, usize)> {
+        let program = parse_program(text, None).unwrap();
+        find_extensions(&program)
+            .into_iter()
+            .map(|e| (e.feature, e.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_posix_program_has_no_extensions() {
+        let text =
+            "define f(x) {\n  auto a[]\n  if (x < 1) return (1)\n  return (x * f(x - 1))\n}\n\
+                    for (i = 0; i < 3; ++i) { \"a\"; f(i) }\nwhile (i) i = i - 1\n";
+        assert_eq!(features(text), vec![]);
+    }
+
+    #[test]
+    fn test_extensions_are_reported_with_their_line() {
+        let text = "x = 1\nif (x) x else 2\nprint x, \"\\n\"\nlong = !x && last\n";
+        assert_eq!(
+            features(text),
+            vec![
+                ("the else branch", 2),
+                ("the print statement", 3),
+                ("names longer than one letter", 4),
+                ("the && operator", 4),
+                ("the ! operator", 4),
+                ("last", 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extensions_in_functions_are_reported() {
+        let text = "define long(x) {\n  while (1) {\n    if (x) continue\n    halt\n  }\n}\n";
+        assert_eq!(
+            features(text),
+            vec![
+                ("names longer than one letter", 1),
+                ("the continue statement", 3),
+                ("the halt statement", 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extensions_in_function_headers_are_reported_at_the_define_line() {
+        let text = "x = 1\n\ndefine f(long) {\n}\n";
+        assert_eq!(features(text), vec![("names longer than one letter", 3)]);
+    }
+
+    #[test]
+    fn test_relational_operator_in_expression_is_reported() {
+        assert_eq!(
+            features("x = (1 < 2)\nif (1 < 2) x\n"),
+            vec![("relational operators outside of conditions", 1)]
+        );
+    }
+
+    #[test]
+    fn test_random_functions_are_reported_as_long_names() {
+        assert_eq!(
+            features("x = rand() + irand(6)\n"),
+            vec![("names longer than one letter", 1)]
+        );
+    }
+
+    #[test]
+    fn test_bitwise_operators_are_reported() {
+        let options = ParseOptions {
+            bitwise_operators: true,
+            ..Default::default()
+        };
+        let program = parse_program_with_options("x = 1\ny = ~x << 2\n", None, &options).unwrap();
+        let features: Vec<_> = find_extensions(&program)
+            .into_iter()
+            .map(|e| (e.feature, e.line))
+            .collect();
+        assert_eq!(features, vec![("bitwise operators", 2)]);
+    }
+
+    #[test]
+    fn test_exponent_literals_are_reported() {
+        let options = ParseOptions {
+            exponent_literals: true,
+            ..Default::default()
+        };
+        let program = parse_program_with_options("x = 1\ny = 2e3\n", None, &options).unwrap();
+        let features: Vec<_> = find_extensions(&program)
+            .into_iter()
+            .map(|e| (e.feature, e.line))
+            .collect();
+        assert_eq!(features, vec![("exponent literals", 2)]);
+    }
+}
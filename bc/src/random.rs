@@ -0,0 +1,89 @@
+//! The random number generator of `rand()`, `irand(n)` and `seed()`.
+//!
+//! These are not keywords: programs can still use `rand`, `irand` and
+//! `seed` as names of variables, arrays and functions. A call of one of
+//! them uses the generator only when no function of that name has been
+//! defined or registered. `seed()` returns the state of the generator,
+//! and `seed(x)` sets it: after `s = seed()`, calling `seed(s)` repeats
+//! the numbers that followed.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::SystemTime;
+
+/// A SplitMix64 generator. Its state is a counter, so that `seed()`
+/// can return it and passing it back to `seed(x)` repeats the sequence.
+#[derive(Debug, Clone)]
+pub(crate) struct Random {
+    state: u64,
+}
+
+impl Random {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// creates a generator with a seed that is different for every call
+    pub(crate) fn from_os() -> Self {
+        // the keys of `RandomState` come from the randomness of the OS
+        let mut hasher = RandomState::new().build_hasher();
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        hasher.write_u128(now.as_nanos());
+        Self::new(hasher.finish())
+    }
+
+    pub(crate) fn seed(&self) -> u64 {
+        self.state
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// returns a number between 0 and `bound - 1` with the same probability,
+    /// 0 if `bound` is 0
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        if bound == 0 {
+            return 0;
+        }
+        // values below `threshold` are rejected, so that the
+        // remaining range is a multiple of `bound`
+        let threshold = bound.wrapping_neg() % bound;
+        loop {
+            let value = self.next_u64();
+            if value >= threshold {
+                return value % bound;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splitmix64_sequence() {
+        let mut random = Random::new(0);
+        assert_eq!(random.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(random.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+    }
+
+    #[test]
+    fn test_below_is_in_range() {
+        let mut random = Random::new(7);
+        let mut seen = [false; 6];
+        for _ in 0..1000 {
+            seen[random.below(6) as usize] = true;
+        }
+        assert_eq!(seen, [true; 6]);
+        assert_eq!(random.below(0), 0);
+        assert_eq!(random.below(1), 0);
+    }
+}
@@ -0,0 +1,129 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run_bc(args: &[&str], input: &str) -> Output {
+    run_bc_with_env(args, &[], input)
+}
+
+fn run_bc_with_env(args: &[&str], env: &[(&str, &str)], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bc"))
+        .args(args)
+        .env_remove("BC_LINE_LENGTH")
+        .envs(env.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> &str {
+    std::str::from_utf8(&output.stdout).unwrap()
+}
+
+fn stderr(output: &Output) -> &str {
+    std::str::from_utf8(&output.stderr).unwrap()
+}
+
+#[test]
+fn test_reads_stdin() {
+    let output = run_bc(&[], "1 + 2\nx = 3\nx * 2\n");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "3\n6\n");
+}
+
+#[test]
+fn test_continues_incomplete_input_on_next_line() {
+    let input = "define f(x) {\n  return (x * 2)\n}\nf(4)\n\"two\nlines\"\n";
+    let output = run_bc(&[], input);
+    assert_eq!(stdout(&output), "8\ntwo\nlines");
+}
+
+#[test]
+fn test_errors_at_closed_strings_do_not_wait_for_more_input() {
+    let output = run_bc(&[], "1 \"x\"\n2\n3\n");
+    assert_eq!(stdout(&output), "2\n3\n");
+    assert!(stderr(&output).contains("1 \"x\""), "{}", stderr(&output));
+}
+
+#[test]
+fn test_math_library() {
+    let output = run_bc(&["-l"], "scale\ns(0)\n");
+    assert_eq!(stdout(&output), "20\n0\n");
+}
+
+#[test]
+fn test_files_are_executed_before_stdin() {
+    let dir = std::env::temp_dir().join(format!("bc_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let first = dir.join("first.bc");
+    let second = dir.join("second.bc");
+    std::fs::write(&first, "a = 1\na\n").unwrap();
+    std::fs::write(&second, "a += 1\na\n").unwrap();
+
+    let output = run_bc(
+        &["-q", first.to_str().unwrap(), second.to_str().unwrap()],
+        "a * 10\n",
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(stdout(&output), "1\n2\n20\n");
+}
+
+#[test]
+fn test_quit_stops_reading_input() {
+    let output = run_bc(&[], "1\nquit\n2\n");
+    assert!(output.status.success());
+    assert_eq!(stdout(&output), "1\n");
+}
+
+#[test]
+fn test_errors_are_printed_to_stderr() {
+    let output = run_bc(&[], "1 / 0\n2\n");
+    assert_eq!(stdout(&output), "2\n");
+    assert_eq!(
+        stderr(&output),
+        "runtime error (line 1): division by zero\n"
+    );
+}
+
+#[test]
+fn test_standard_mode_rejects_extensions() {
+    let output = run_bc(&["-s"], "print 1\n2\n");
+    assert_eq!(stdout(&output), "2\n");
+    assert_eq!(
+        stderr(&output),
+        "error (line 1): POSIX bc does not allow the print statement\n"
+    );
+}
+
+#[test]
+fn test_invalid_option_is_error() {
+    let output = run_bc(&["-x"], "");
+    assert!(!output.status.success());
+    assert!(stderr(&output).contains("usage: bc"));
+}
+
+#[test]
+fn test_long_numbers_are_split_at_line_length() {
+    // 2^300 has 91 digits
+    let output = run_bc(&[], "2^300\n");
+    let wrapped = stdout(&output);
+    let lines: Vec<_> = wrapped.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0].len(), 70);
+    assert!(lines[0].ends_with('\\'));
+
+    let output = run_bc_with_env(&[], &[("BC_LINE_LENGTH", "0")], "2^300\n");
+    assert_eq!(stdout(&output), wrapped.replace("\\\n", ""));
+    assert_eq!(stdout(&output).len(), 92);
+
+    let output = run_bc_with_env(&[], &[("BC_LINE_LENGTH", "10")], "2^40\n");
+    assert_eq!(stdout(&output), "109951162\\\n7776\n");
+}
@@ -25,6 +25,7 @@
 #define IDC_SYSLINK1                    1007
 #define IDC_CHK_SHOW_CLOCK              1008
 #define IDC_CHK_CALCULATOR              1009
+#define IDC_CHK_CALC_MATH_LIB           1010
 #define IDC_STATIC                      -1
 
 // Next default values for new objects
@@ -34,7 +35,7 @@
 #define _APS_NO_MFC                     1
 #define _APS_NEXT_RESOURCE_VALUE        133
 #define _APS_NEXT_COMMAND_VALUE         32771
-#define _APS_NEXT_CONTROL_VALUE         1009
+#define _APS_NEXT_CONTROL_VALUE         1011
 #define _APS_NEXT_SYMED_VALUE           110
 #endif
 #endif
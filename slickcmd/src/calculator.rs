@@ -1,8 +1,44 @@
-use bc::interpreter::Interpreter;
-use bc::number::Number;
-use bc::parser;
+use bc::error::BcError;
+use bc::interpreter::{ExecutionLimits, Interpreter};
+use bc::number::{
+    wrap_lines, Notation, Number, NumberFormat, NumericResult, ScientificFormat, TwosComplement,
+};
+use bc::parser::{self, ParseOptions};
 use regex::Regex;
+use std::cell::RefCell;
 use std::collections::HashSet;
+use std::mem;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// evaluation runs on the message loop thread, so it must never take long
+const MAX_STATEMENTS: u64 = 1_000_000;
+const MAX_CALL_DEPTH: usize = 256;
+const MAX_DIGITS: u64 = 10_000;
+const TIMEOUT: Duration = Duration::from_millis(1500);
+// about a screen of output, the evaluation stops when it prints more lines
+const MAX_OUTPUT_LINES: usize = 50;
+// significant digits of the results written in scientific notation
+const SIGNIFICANT_DIGITS: u64 = 15;
+// cmd asks for the rest of a line that ends with `^` after this prompt
+const CONTINUATION_PROMPT: &str = "^More? ";
+const PARSE_OPTIONS: ParseOptions = ParseOptions {
+    bitwise_operators: true,
+    exponent_literals: true,
+};
+
+// the regular expressions are used on every key press, they are compiled once
+static OUTPUT_FORMAT_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(.+?)(?:\s+(?:to|in)\s+|\s*:\s*)(hex|oct|bin|dec|sci|eng|x|o|b|d)$").unwrap()
+});
+static DEFINE_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^define\s+[a-z_][a-z0-9_]*\s*\(.*\{").unwrap());
+static EXPONENT_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"([0-9.])E([+-]?[0-9])").unwrap());
+static TRAILING_ZEROS_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^-?\d+\.(\d+)?0$").unwrap());
 
 #[derive(PartialEq)]
 enum PartType {
@@ -28,7 +64,11 @@ fn parse_parts(chars: &[char]) -> Option<Vec<ParsedPart>> {
     for &c in chars {
         let typ = match c {
             ' ' => PartType::None,
+            '+' | '-' if part.typ == PartType::Number && part.text.ends_with(['e', 'E']) => {
+                PartType::Number
+            }
             '+' | '-' | '*' | '/' | '%' | '^' | '!' | '=' | ',' => PartType::Operator,
+            '&' | '|' | '~' => PartType::Operator,
             '(' | ')' | '[' | ']' | '<' | '>' | '{' | '}' => PartType::Operator,
             ';' | '\n' => PartType::Separator,
             '.' => {
@@ -37,6 +77,10 @@ fn parse_parts(chars: &[char]) -> Option<Vec<ParsedPart>> {
                 }
                 PartType::Number
             }
+            // the exponent of a number such as 6.02e23 or 1.5E-9
+            'e' | 'E' if part.typ == PartType::Number && !part.text.contains(['e', 'E']) => {
+                PartType::Number
+            }
             '0'..='9' => {
                 if part.typ == PartType::Variable {
                     PartType::Variable
@@ -44,7 +88,7 @@ fn parse_parts(chars: &[char]) -> Option<Vec<ParsedPart>> {
                     PartType::Number
                 }
             }
-            'a'..='z' => {
+            'a'..='z' | '_' => {
                 if part.typ == PartType::Number {
                     return None;
                 }
@@ -71,12 +115,91 @@ fn parse_parts(chars: &[char]) -> Option<Vec<ParsedPart>> {
     Some(parts)
 }
 
+// the `=` prefix keeps the commands apart from programs named `reset` or `save`
+const RESET_COMMAND: &str = "=reset";
+const SAVE_COMMAND: &str = "=save";
+// holds the previous result, always defined
+const LAST_VARIABLE: &str = "last";
+
+/// The format of the result, requested with `to hex`, `in oct` or a trailing `:b`
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum OutputFormat {
+    Hex,
+    Oct,
+    Bin,
+    Dec,
+    Scientific,
+    Engineering,
+}
+
+/// Splits the requested format of the result from the end of the input.
+fn split_output_format(input: &str) -> (&str, Option<OutputFormat>) {
+    let Some(captures) = OUTPUT_FORMAT_REGEX.captures(input) else {
+        return (input, None);
+    };
+    let format = match &captures[2] {
+        "hex" | "x" => OutputFormat::Hex,
+        "oct" | "o" => OutputFormat::Oct,
+        "bin" | "b" => OutputFormat::Bin,
+        "sci" => OutputFormat::Scientific,
+        "eng" => OutputFormat::Engineering,
+        _ => OutputFormat::Dec,
+    };
+    (captures.get(1).unwrap().as_str(), Some(format))
+}
+
+/// Writes `value` in `format`. Bases other than 10 have a radix prefix, and
+/// negative integers are shown in them as 64 bit two's complement numbers,
+/// like in a programmer calculator.
+fn format_result(value: &Number, format: OutputFormat) -> String {
+    let (radix, prefix, group_size) = match format {
+        OutputFormat::Hex => (16, "0x", 0),
+        OutputFormat::Oct => (8, "0o", 0),
+        OutputFormat::Bin => (2, "0b", 4),
+        OutputFormat::Dec => return remove_zeros_after_dot(&value.to_string_radix(10)),
+        OutputFormat::Scientific | OutputFormat::Engineering => {
+            let notation = if format == OutputFormat::Scientific {
+                Notation::Scientific
+            } else {
+                Notation::Engineering
+            };
+            return value.to_scientific_string(&ScientificFormat {
+                notation,
+                significant_digits: SIGNIFICANT_DIGITS,
+            });
+        }
+    };
+    let mut number_format = NumberFormat {
+        prefix: prefix.to_string(),
+        group_size,
+        ..NumberFormat::new(radix)
+    };
+    let fits_in_i64 = || value.clone().negate().as_u64().is_some_and(|n| n <= 1 << 63);
+    if value.is_negative() && value.is_integer() && fits_in_i64() {
+        number_format.twos_complement = Some(TwosComplement::Unsigned(64));
+    }
+    let text = value.format(&number_format).unwrap();
+    if text.contains('.') {
+        text.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        text
+    }
+}
+
 pub fn accepts_input(input: &str) -> bool {
-    let input = input.trim_ascii();
+    check_input(input, &HashSet::new()).is_some()
+}
+
+/// Checks if the input looks like a calculation rather than a command.
+/// # Returns
+/// `known_vars` plus the variables defined by the input,
+/// or `None` if the input is not accepted
+fn check_input(input: &str, known_vars: &HashSet<String>) -> Option<HashSet<String>> {
+    let (input, _) = split_output_format(input.trim_ascii());
     let chars: Vec<char> = input.chars().collect();
     let len = chars.len();
     if len == 0 {
-        return false;
+        return None;
     }
     if len > 2 && chars[0] == '0' {
         let base = match chars[1] {
@@ -86,87 +209,330 @@ pub fn accepts_input(input: &str) -> bool {
             _ => 0,
         };
         if base != 0 && Number::parse(&input[2..], base).is_some() {
-            return true;
+            return Some(known_vars.clone());
         }
     }
 
-    let parts = parse_parts(&chars);
-    if parts.is_none() {
-        return false;
-    }
-    let mut parts = parts.unwrap();
+    let mut parts = parse_parts(&chars)?;
     parts.push(ParsedPart {
         typ: PartType::Separator,
         text: String::new(),
     });
 
-    let mut defined_vars: HashSet<String> = HashSet::new();
+    let mut defined_vars = known_vars.clone();
+    if parts[0].text == "define" {
+        // function definition, the body is checked by the bc parser
+        return DEFINE_REGEX.is_match(input).then_some(defined_vars);
+    }
     let count = parts.len();
     for n in 0..count - 1 {
         let part = &parts[n];
         let next_part = &parts[n + 1];
         if part.typ == PartType::Variable {
             if next_part.typ != PartType::Operator && next_part.typ != PartType::Separator {
-                return false;
+                return None;
             }
-            if !defined_vars.contains(&part.text) {
+            if part.text != LAST_VARIABLE && !defined_vars.contains(&part.text) {
                 if next_part.text == "=" {
                     defined_vars.insert(part.text.clone());
-                } else if next_part.text == "(" {
+                } else if next_part.text.starts_with('(') {
                     //func call?
+                } else if next_part.text.starts_with('[') {
+                    // arrays are not listed by the interpreter
                 } else {
-                    return false;
+                    return None;
                 }
             }
         } else if part.typ == PartType::Number {
             if !(next_part.typ != PartType::Operator || next_part.typ != PartType::Separator) {
-                return false;
+                return None;
             }
         }
     }
-    true
+    Some(defined_vars)
 }
 
-pub fn evaluate(input: &str) -> String {
-    let input = input.trim_ascii();
-    let chars: Vec<char> = input.chars().collect();
-    let len = chars.len();
-    if len > 2 && chars[0] == '0' {
-        let base = match chars[1] {
-            'x' | 'X' => 16,
-            'o' | 'O' => 8,
-            'b' | 'B' => 2,
-            _ => 0,
+/// A calculator session. Variables, arrays, functions and the
+/// `scale`/`ibase`/`obase` registers are kept between evaluations.
+pub struct Calculator {
+    interpreter: Interpreter,
+    math_lib: bool,
+    files: Option<SessionFiles>,
+    defined_vars: HashSet<String>,
+    // output of loading the session files, shown with the first result
+    pending_output: String,
+    // the output of the evaluation, written by the output sink of the interpreter
+    output: Rc<RefCell<String>>,
+}
+
+/// bc files loaded when a calculator session starts
+#[derive(Clone)]
+pub struct SessionFiles {
+    /// file written by the user, with helper functions and constants
+    pub init_file: PathBuf,
+    /// file written by the `=save` command, loaded after `init_file`
+    pub saved_file: PathBuf,
+}
+
+impl Calculator {
+    pub fn new(math_lib: bool) -> Calculator {
+        Self::create(math_lib, None, false)
+    }
+
+    pub fn with_files(math_lib: bool, files: SessionFiles) -> Calculator {
+        Self::create(math_lib, Some(files), true)
+    }
+
+    fn create(math_lib: bool, files: Option<SessionFiles>, load_saved: bool) -> Calculator {
+        let mut interpreter = if math_lib {
+            // the math library sets scale to 20 itself
+            Interpreter::with_math_library()
+        } else {
+            let mut interpreter = Interpreter::default();
+            let lib = parser::parse_program("scale=20;\n", None).unwrap();
+            interpreter.exec(lib).unwrap();
+            interpreter
         };
-        if base != 0 {
-            let num = Number::parse(&input[2..], base);
-            if num.is_some() {
-                return num.unwrap().to_string(10);
+        interpreter.set_limits(ExecutionLimits {
+            max_statements: Some(MAX_STATEMENTS),
+            max_call_depth: Some(MAX_CALL_DEPTH),
+            max_digits: Some(MAX_DIGITS),
+            timeout: Some(TIMEOUT),
+            cancel: None,
+        });
+        // long results are only split when the width of the console is known
+        interpreter.set_line_length(0);
+        // a `define` of the same name replaces these
+        interpreter.register_function("now", now);
+
+        let mut pending_output = String::new();
+        if let Some(files) = &files {
+            let mut paths = vec![&files.init_file];
+            if load_saved {
+                paths.push(&files.saved_file);
             }
+            for path in paths {
+                if !path.exists() {
+                    continue;
+                }
+                match interpreter.load_file(path, &PARSE_OPTIONS) {
+                    Ok(output) => pending_output.push_str(&output),
+                    Err(e) => {
+                        pending_output.push_str(&e.to_string());
+                        pending_output.push('\n');
+                    }
+                }
+            }
+        }
+        let defined_vars = interpreter.global_variable_names().into_iter().collect();
+
+        let output = Rc::new(RefCell::new(String::new()));
+        let sink_output = output.clone();
+        interpreter.set_output_sink(Some(Box::new(move |text: &str| {
+            let mut output = sink_output.borrow_mut();
+            output.push_str(text);
+            if output.matches('\n').count() > MAX_OUTPUT_LINES {
+                let end = output
+                    .match_indices('\n')
+                    .nth(MAX_OUTPUT_LINES - 1)
+                    .map_or(0, |(i, _)| i + 1);
+                output.truncate(end);
+                return Err(BcError::Custom(format!(
+                    "output truncated after {MAX_OUTPUT_LINES} lines"
+                )));
+            }
+            Ok(())
+        })));
+
+        Calculator {
+            interpreter,
+            math_lib,
+            files,
+            defined_vars,
+            pending_output,
+            output,
+        }
+    }
+
+    pub fn math_lib(&self) -> bool {
+        self.math_lib
+    }
+
+    /// Forgets everything defined in the session, except for what
+    /// is defined in the init file.
+    pub fn reset(&mut self) {
+        let line_length = self.interpreter.line_length();
+        *self = Self::create(self.math_lib, self.files.take(), false);
+        self.interpreter.set_line_length(line_length);
+    }
+
+    /// Splits results longer than `line_length` characters into lines
+    /// ending with a backslash, as bc does. 0 means no splitting.
+    pub fn set_line_length(&mut self, line_length: usize) {
+        self.interpreter.set_line_length(line_length);
+    }
+
+    /// Saves the variables and functions of the session,
+    /// they are restored when the next session starts.
+    fn save(&mut self) -> String {
+        let Some(files) = &self.files else {
+            return "no session file".to_string();
+        };
+        match self.interpreter.save_state(&files.saved_file) {
+            Ok(()) => "calculator session saved".to_string(),
+            Err(e) => e.to_string(),
         }
     }
 
-    let program = input.to_string() + "\n";
-    let mut interpreter = Interpreter::default();
+    pub fn accepts_input(&self, input: &str) -> bool {
+        let input = input.trim_ascii();
+        input == RESET_COMMAND
+            || input == SAVE_COMMAND
+            || check_input(input, &self.defined_vars).is_some()
+    }
 
-    let lib = parser::parse_program("scale=20;\n", None).unwrap();
-    interpreter.exec(lib).unwrap();
+    /// Checks if the input is the start of a longer program, like a function
+    /// definition without its closing brace, that continues on the next line.
+    pub fn is_incomplete(&self, input: &str) -> bool {
+        let (input, _) = split_output_format(input.trim_ascii());
+        let text = program_text(input, self.interpreter.ibase());
+        parser::parse_program_with_options(&text, None, &PARSE_OPTIONS)
+            .is_err_and(|e| e.is_incomplete)
+    }
+
+    pub fn evaluate(&mut self, input: &str) -> String {
+        let output = self.evaluate_input(input.trim_ascii());
+        if self.pending_output.is_empty() {
+            output
+        } else {
+            mem::take(&mut self.pending_output) + &output
+        }
+    }
 
-    match parser::parse_program(&program, None) {
-        Ok(program) => match interpreter.exec(program) {
-            Ok(result) => remove_zeros_after_dot(&result),
-            Err(e) => {
-                format!("{}{}", e.partial_output(), e)
+    fn evaluate_input(&mut self, input: &str) -> String {
+        if input == RESET_COMMAND {
+            self.reset();
+            return "calculator reset".to_string();
+        }
+        if input == SAVE_COMMAND {
+            return self.save();
+        }
+
+        let (input, output_format) = split_output_format(input);
+        let chars: Vec<char> = input.chars().collect();
+        let len = chars.len();
+        if len > 2 && chars[0] == '0' {
+            let base = match chars[1] {
+                'x' | 'X' => 16,
+                'o' | 'O' => 8,
+                'b' | 'B' => 2,
+                _ => 0,
+            };
+            if base != 0 {
+                let num = Number::parse(&input[2..], base);
+                if let Some(num) = num {
+                    let value = format_result(&num, output_format.unwrap_or(OutputFormat::Dec));
+                    // a displayed result is the next `last`
+                    self.interpreter.set_last(num);
+                    return wrap_lines(&value, self.interpreter.line_length());
+                }
             }
-        },
-        Err(e) => e.to_string(),
+        }
+
+        let defined_vars = check_input(input, &self.defined_vars);
+
+        let text = program_text(input, self.interpreter.ibase());
+        match parser::parse_program_with_options(&text, None, &PARSE_OPTIONS) {
+            Ok(program) => {
+                let result = self.interpreter.exec(program);
+                let output = mem::take(&mut *self.output.borrow_mut());
+                if result.is_ok() {
+                    // functions can assign variables too, only the interpreter
+                    // knows them; the input adds the variables set to zero
+                    self.defined_vars.extend(defined_vars.into_iter().flatten());
+                    self.defined_vars.extend(self.interpreter.global_variable_names());
+                }
+                match result {
+                    Ok(_) => match self.interpreter.last_value_output() {
+                        // the value of `last` is formatted again, the rest of
+                        // the output is kept as printed
+                        Some(range) => {
+                            let last = self.interpreter.last();
+                            let value = match output_format {
+                                Some(format) => format_result(last, format),
+                                None => {
+                                    remove_zeros_after_dot(&self.interpreter.format_number(last))
+                                }
+                            };
+                            let value = wrap_lines(&value, self.interpreter.line_length());
+                            let output = format!(
+                                "{}{value}\n{}",
+                                &output[..range.start],
+                                &output[range.end..]
+                            );
+                            output.trim_end().to_string()
+                        }
+                        None => output.trim_end().to_string(),
+                    },
+                    // the output sink stops the program with a message of its own
+                    Err(e) if e.is_aborted() => match e.error() {
+                        BcError::Custom(message) => format!("{output}{message}"),
+                        _ => format!("{output}computation aborted"),
+                    },
+                    Err(e) => match e.diagnostic(&text) {
+                        Some(diagnostic) => {
+                            format!("{output}runtime error: {}", diagnostic.render(&text))
+                        }
+                        None => format!("{output}{e}"),
+                    },
+                }
+            }
+            Err(e) => e
+                .diagnostics(&text)
+                .iter()
+                .map(|diagnostic| format!("syntax error: {}", diagnostic.render(&text)))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
     }
 }
 
+/// `now()`, the number of seconds since 1970-01-01 00:00 UTC
+fn now(args: &[Number]) -> NumericResult {
+    if !args.is_empty() {
+        return Err(BcError::Custom("now takes no arguments".to_string()));
+    }
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    Ok(Number::from(seconds))
+}
+
+/// The bc program of the input, when the numbers are read in `ibase`.
+fn program_text(input: &str, ibase: u64) -> String {
+    // bc only accepts a lowercase `e`, because `E` is a hexadecimal digit.
+    // Exponents are only valid in base 10, in other bases `1E2` is a number
+    if ibase != 10 {
+        return input.to_string() + "\n";
+    }
+    EXPONENT_REGEX.replace_all(input, "${1}e${2}").to_string() + "\n"
+}
+
+/// Joins the lines of an input continued with `^` in cmd, which shows
+/// `More? ` before each new line, with newlines: a function body needs
+/// them between its statements.
+pub fn join_continuation_lines(input: &str) -> String {
+    input.replace(CONTINUATION_PROMPT, "\n")
+}
+
+/// Evaluates the input in a new calculator session.
+pub fn evaluate(input: &str, math_lib: bool) -> String {
+    Calculator::new(math_lib).evaluate(input)
+}
+
 fn remove_zeros_after_dot(s: &str) -> String {
     let mut s = s.trim_end();
-    let re = Regex::new(r"^\d+\.(\d+)?0$").unwrap();
-    if re.is_match(s) {
+    if TRAILING_ZEROS_REGEX.is_match(s) {
         s = s.trim_end_matches('0');
         s = s.trim_end_matches('.');
     }
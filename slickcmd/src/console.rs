@@ -1,6 +1,6 @@
 use crate::app::App;
 use crate::app_state::AppState;
-use crate::calculator;
+use crate::calculator::{self, Calculator, SessionFiles};
 use crate::clock_win::ClockWin;
 use crate::command_hist::CommandHist;
 use crate::command_hist_win::CommandHistWin;
@@ -8,6 +8,7 @@ use crate::dir_complete::DIR_COMPLETER;
 use crate::dir_man::CurDir;
 use crate::global::GLOBAL;
 use crate::keyboard_input::KeyboardInput;
+use crate::options::Options;
 use crate::shell::{CmdShell, PsShell, Shell};
 use crate::win_man::WinMan;
 use slickcmd_common::consts::*;
@@ -39,6 +40,8 @@ pub struct Console {
 
     replacing_command: String,
     custom_command_output: String,
+    // the calculator input is incomplete, cmd asks for the rest with `More?`
+    continuing_input: bool,
 
     context: Rc<RefCell<ConsoleContext>>,
 
@@ -53,6 +56,8 @@ pub struct Console {
     manual_cd_completing: bool,
 
     clock_win: Option<Box<ClockWin>>,
+
+    calculator: Option<Calculator>,
 }
 
 #[derive(Default)]
@@ -310,7 +315,8 @@ impl Console {
         let (_, cur_y) = self.get_xy();
         let command_from_y = iif(cur_y > self.last_command_y, self.last_command_y + 1, 0);
 
-        let (_prompt, input) = self.read_prompt_input(command_from_y, false);
+        let (_prompt, raw_input) = self.read_prompt_raw_input(command_from_y, false);
+        let input = self.clean_multiline_input_continuations(&raw_input);
         logd!("PROMPT: {}, INPUT: {}", _prompt, input);
 
         if input.len() > 3 && input[..3].eq_ignore_ascii_case("cd ") && !self.is_cross_drive_cd() {
@@ -335,8 +341,19 @@ impl Console {
 
         let mut result = false;
         if self.use_calculator() {
-            if calculator::accepts_input(&input) {
-                let output = calculator::evaluate(&input);
+            let (_, _, width) = self.get_xyw();
+            // the lines of the calculator input are kept apart
+            let input = calculator::join_continuation_lines(&raw_input);
+            let calculator = self.calculator();
+            // a line as wide as the console would leave an empty line after it
+            calculator.set_line_length((width as usize).saturating_sub(1));
+            if calculator.accepts_input(&input) {
+                if calculator.is_incomplete(&input) {
+                    // the prompt of the input stays the last command line
+                    self.continuing_input = true;
+                    return true;
+                }
+                let output = calculator.evaluate(&input);
                 self.custom_command_output = output;
                 result = true;
             }
@@ -350,6 +367,18 @@ impl Console {
         GLOBAL.options.direct_calculator() && self.shell.typ() == "cmd"
     }
 
+    fn calculator(&mut self) -> &mut Calculator {
+        let math_lib = GLOBAL.options.calculator_math_lib();
+        if self.calculator.as_ref().map_or(true, |c| c.math_lib() != math_lib) {
+            let files = SessionFiles {
+                init_file: Options::get_calc_init_path().into(),
+                saved_file: Options::get_calc_session_path().into(),
+            };
+            self.calculator = Some(Calculator::with_files(math_lib, files));
+        }
+        self.calculator.as_mut().unwrap()
+    }
+
     pub fn on_key_suppress_end(&mut self) {
         //
     }
@@ -808,6 +837,15 @@ impl Console {
             );
             return true;
         }
+        if self.continuing_input {
+            self.continuing_input = false;
+            // cmd continues a line ending with `^` on the next one
+            let mut ki = KeyboardInput::new();
+            ki.text(" ^");
+            ki.enter();
+            ki.post(self.hwnd_term, true);
+            return true;
+        }
         if !self.replacing_command.is_empty() {
             let command = self.replacing_command.clone();
             self.replacing_command.clear();
@@ -955,6 +993,13 @@ impl Console {
     }
 
     pub fn read_prompt_input(&self, from_y: i16, only_before_cursor: bool) -> (String, String) {
+        let (prompt, input) = self.read_prompt_raw_input(from_y, only_before_cursor);
+        (prompt, self.clean_multiline_input_continuations(&input))
+    }
+
+    /// Reads the prompt and the input like `read_prompt_input`, keeping
+    /// the continuation prompts of the lines after the first one.
+    fn read_prompt_raw_input(&self, from_y: i16, only_before_cursor: bool) -> (String, String) {
         let mut prompt = String::new();
 
         let ca = self.new_console_attach();
@@ -1027,7 +1072,6 @@ impl Console {
             input.insert_str(0, &line);
 
             if prompt_found {
-                input = self.clean_multiline_input_continuations(&input);
                 return (prompt, input);
             }
         }
@@ -9,6 +9,7 @@ pub struct Options {
     run_on_startup: Cell<bool>,
     show_clock: Cell<bool>,
     direct_calculator: Cell<bool>,
+    calculator_math_lib: Cell<bool>,
 }
 
 impl Options {
@@ -17,6 +18,16 @@ impl Options {
         path + "\\slickcmd\\slickcmd.ini"
     }
 
+    pub fn get_calc_init_path() -> String {
+        let path = utils::get_appdata_local_dir();
+        path + "\\slickcmd\\calc_init.bc"
+    }
+
+    pub fn get_calc_session_path() -> String {
+        let path = utils::get_appdata_local_dir();
+        path + "\\slickcmd\\calc_session.bc"
+    }
+
     pub fn save(&self) {
         let ini = Ini::new(Some(&Self::get_ini_path()));
         ini.write("General", "max_recent_dirs", self.max_recent_dirs());
@@ -24,6 +35,7 @@ impl Options {
         ini.write("General", "run_on_startup", self.run_on_startup());
         ini.write("General", "show_clock", self.show_clock());
         ini.write("General", "use_calculator", self.direct_calculator());
+        ini.write("General", "calculator_math_lib", self.calculator_math_lib());
     }
 
     pub fn init(&self) {
@@ -33,6 +45,7 @@ impl Options {
         self.set_run_on_startup(ini.read_or("General", "run_on_startup", true));
         self.set_show_clock(ini.read_or("General", "show_clock", false));
         self.set_direct_calculator(ini.read_or("General", "use_calculator", false));
+        self.set_calculator_math_lib(ini.read_or("General", "calculator_math_lib", false));
     }
 
     pub fn max_recent_dirs(&self) -> u32 {
@@ -74,4 +87,12 @@ impl Options {
     pub fn set_direct_calculator(&self, value: bool) {
         self.direct_calculator.set(value);
     }
+
+    pub fn calculator_math_lib(&self) -> bool {
+        self.calculator_math_lib.get()
+    }
+
+    pub fn set_calculator_math_lib(&self, value: bool) {
+        self.calculator_math_lib.set(value);
+    }
 }
\ No newline at end of file
@@ -1,5 +1,5 @@
 use crate::startup_link::StartupLink;
-use slickcmd_common::consts::{IDC_CHK_CALCULATOR, IDC_CHK_CD_COMPLETION, IDC_CHK_RUN_ON_STARTUP, IDC_CHK_SHOW_CLOCK, IDC_MAX_RECENT_DIRS, IDC_MAX_RECENT_DIRS_SPIN, IDD_OPTIONS};
+use slickcmd_common::consts::{IDC_CHK_CALCULATOR, IDC_CHK_CALC_MATH_LIB, IDC_CHK_CD_COMPLETION, IDC_CHK_RUN_ON_STARTUP, IDC_CHK_SHOW_CLOCK, IDC_MAX_RECENT_DIRS, IDC_MAX_RECENT_DIRS_SPIN, IDD_OPTIONS};
 use slickcmd_common::dlg::{dlg_proc, Dlg};
 use slickcmd_common::{dlg, utils, win32};
 use windows::Win32::Foundation::*;
@@ -16,6 +16,7 @@ pub struct OptionsDlg {
     hwnd_chk_run_on_startup: HWND,
     hwnd_chk_show_clock: HWND,
     hwnd_chk_direct_calculator: HWND,
+    hwnd_chk_calculator_math_lib: HWND,
 }
 
 impl OptionsDlg {
@@ -49,6 +50,7 @@ impl OptionsDlg {
         self.hwnd_chk_run_on_startup = win32::get_dlg_item(self.hwnd, IDC_CHK_RUN_ON_STARTUP);
         self.hwnd_chk_show_clock = win32::get_dlg_item(self.hwnd, IDC_CHK_SHOW_CLOCK);
         self.hwnd_chk_direct_calculator = win32::get_dlg_item(self.hwnd, IDC_CHK_CALCULATOR);
+        self.hwnd_chk_calculator_math_lib = win32::get_dlg_item(self.hwnd, IDC_CHK_CALC_MATH_LIB);
 
         let options = &GLOBAL.options;
         let text = &format!("{}", options.max_recent_dirs());
@@ -58,6 +60,7 @@ impl OptionsDlg {
         self.set_check(self.hwnd_chk_run_on_startup, options.run_on_startup());
         self.set_check(self.hwnd_chk_show_clock, options.show_clock());
         self.set_check(self.hwnd_chk_direct_calculator, options.direct_calculator());
+        self.set_check(self.hwnd_chk_calculator_math_lib, options.calculator_math_lib());
 
         1
     }
@@ -83,6 +86,7 @@ impl OptionsDlg {
         let run_on_startup = self.get_check(self.hwnd_chk_run_on_startup);
         let show_clock = self.get_check(self.hwnd_chk_show_clock);
         let direct_calculator = self.get_check(self.hwnd_chk_direct_calculator);
+        let calculator_math_lib = self.get_check(self.hwnd_chk_calculator_math_lib);
 
         let options = &GLOBAL.options;
         options.set_max_recent_dirs(max_recent_dirs);
@@ -90,6 +94,7 @@ impl OptionsDlg {
         options.set_run_on_startup(run_on_startup);
         options.set_show_clock(show_clock);
         options.set_direct_calculator(direct_calculator);
+        options.set_calculator_math_lib(calculator_math_lib);
         options.save();
 
         //
@@ -1,8 +1,302 @@
 use slickcmd::calculator;
+use slickcmd::calculator::{Calculator, SessionFiles};
 
 #[test]
 fn test_accepts_input() {
     let input = "a=2;a";
     let result = calculator::accepts_input(input);
     assert!(result);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_evaluate_boolean_expressions() {
+    let input = "(3 > 2) + (1 && 0 || !0)";
+    assert!(calculator::accepts_input(input));
+    assert_eq!(calculator::evaluate(input, false), "2");
+}
+
+#[test]
+fn test_evaluate_with_math_lib() {
+    let input = "s(0)+l(1)";
+    assert!(calculator::accepts_input(input));
+    let result = calculator::evaluate(input, true);
+    assert_eq!(result, "0");
+}
+
+#[test]
+fn test_evaluate_aborts_long_computations() {
+    assert_eq!(calculator::evaluate("while(1){}", false), "computation aborted");
+    assert_eq!(calculator::evaluate("2^99999999", false), "computation aborted");
+    assert_eq!(calculator::evaluate("scale=10000; a(0.3)", true), "computation aborted");
+}
+
+#[test]
+fn test_calculator_session_keeps_state() {
+    let mut calculator = Calculator::new(false);
+    assert!(calculator.accepts_input("a=5"));
+    assert_eq!(calculator.evaluate("a=5"), "");
+    assert!(calculator.accepts_input("a*2"));
+    assert_eq!(calculator.evaluate("a*2"), "10");
+    assert!(calculator.accepts_input("define f(x) { return (x+1) }"));
+    calculator.evaluate("define f(x) { return (x+1) }");
+    assert_eq!(calculator.evaluate("f(a)"), "6");
+
+    assert!(calculator.accepts_input("=reset"));
+    calculator.evaluate("=reset");
+    assert!(!calculator.accepts_input("a*2"));
+    assert_eq!(
+        calculator.evaluate("f(1)"),
+        "runtime error: undefined function\nf(1)\n^"
+    );
+}
+
+#[test]
+fn test_calculator_leaves_commands_to_the_shell() {
+    let calculator = Calculator::new(false);
+    assert!(!calculator.accepts_input("reset"));
+    assert!(!calculator.accepts_input("save"));
+    assert!(!calculator.accepts_input("define"));
+    assert!(!calculator.accepts_input("define.exe /x"));
+    assert!(calculator.accepts_input("define f() {"));
+}
+
+#[test]
+fn test_calculator_keeps_only_variables_of_successful_inputs() {
+    let mut calculator = Calculator::new(false);
+    calculator.evaluate("b = (1");
+    assert!(!calculator.accepts_input("b * 2"));
+    calculator.evaluate("c = 1; 1 / 0");
+    assert!(!calculator.accepts_input("c * 2"));
+    calculator.evaluate("c = 1");
+    assert!(calculator.accepts_input("c * 2"));
+}
+
+#[test]
+fn test_calculator_knows_arrays_and_variables_set_by_functions() {
+    let mut calculator = Calculator::new(false);
+    assert!(calculator.accepts_input("a[1]=5"));
+    assert_eq!(calculator.evaluate("a[1]=5"), "");
+    assert!(calculator.accepts_input("a[1]"));
+    assert_eq!(calculator.evaluate("a[1] * 2"), "10");
+
+    calculator.evaluate("define g(x) { total = x * 3; return (x) }");
+    assert!(!calculator.accepts_input("total"));
+    assert_eq!(calculator.evaluate("g(4)"), "4");
+    assert!(calculator.accepts_input("total"));
+    assert_eq!(calculator.evaluate("total"), "12");
+}
+
+#[test]
+fn test_calculator_detects_incomplete_input() {
+    let calculator = Calculator::new(false);
+    assert!(calculator.is_incomplete("define f(x) {"));
+    assert!(calculator.is_incomplete("define f(x) { return (x)"));
+    assert!(calculator.is_incomplete("print \"a"));
+    assert!(!calculator.is_incomplete("define f(x) { return (x) }"));
+    assert!(!calculator.is_incomplete("1 +* 2"));
+}
+
+#[test]
+fn test_calculator_output_bases_above_16_write_decimal_digits() {
+    let mut calculator = Calculator::new(false);
+    assert_eq!(calculator.evaluate("obase=20"), "");
+    assert_eq!(calculator.evaluate("255"), " 12 15");
+    assert_eq!(calculator.evaluate("-255.5"), "- 12 15.10");
+    // the format suffixes only have bases up to 16
+    assert_eq!(calculator.evaluate("255 :x"), "0xFF");
+    assert!(!calculator.accepts_input("255 :z"));
+}
+
+#[test]
+fn test_calculator_joins_continuation_lines() {
+    let mut calculator = Calculator::new(false);
+    // the console reads the lines continued with ` ^` as one
+    let input = "define f(x) { ^More? auto y ^More? y = x*2 ^More? return (y) }";
+    let input = calculator::join_continuation_lines(input);
+    assert!(calculator.accepts_input(&input));
+    assert!(!calculator.is_incomplete(&input));
+    assert_eq!(calculator.evaluate(&input), "");
+    assert_eq!(calculator.evaluate("f(21)"), "42");
+    assert!(calculator.is_incomplete(&calculator::join_continuation_lines(
+        "define g(x) { ^More? auto y ^More? y = x"
+    )));
+}
+
+#[test]
+fn test_calculator_functions() {
+    let mut calculator = Calculator::new(false);
+    assert!(calculator.accepts_input("now()"));
+    assert_eq!(calculator.evaluate("now() > 1700000000"), "1");
+    calculator.evaluate("define now() { return (5) }");
+    assert_eq!(calculator.evaluate("now()"), "5");
+}
+
+#[test]
+fn test_calculator_errors_point_at_the_failing_token() {
+    assert_eq!(
+        calculator::evaluate("1 + 2 / 0", false),
+        "runtime error: division by zero\n1 + 2 / 0\n      ^"
+    );
+    assert_eq!(
+        calculator::evaluate("2 ^ 0.5", false),
+        "runtime error: exponent has to be an integer\n2 ^ 0.5\n  ^"
+    );
+    assert_eq!(
+        calculator::evaluate("1 +* 2", false),
+        "syntax error: expected '!' or expression\n1 +* 2\n   ^"
+    );
+}
+
+#[test]
+fn test_calculator_last_result() {
+    let mut calculator = Calculator::new(false);
+    assert_eq!(calculator.evaluate("2+3"), "5");
+    assert!(calculator.accepts_input("last * 1.2"));
+    assert_eq!(calculator.evaluate("last * 1.2"), "6");
+    assert!(calculator.accepts_input(". + 1"));
+    assert_eq!(calculator.evaluate(". + 1"), "7");
+    // radix literals are results too
+    assert_eq!(calculator.evaluate("0xff"), "255");
+    assert_eq!(calculator.evaluate(". + 1"), "256");
+    assert_eq!(calculator.evaluate("0b101 :x"), "0x5");
+    assert_eq!(calculator.evaluate("last * 2"), "10");
+}
+
+#[test]
+fn test_calculator_long_names() {
+    let mut calculator = Calculator::new(false);
+    assert!(calculator.accepts_input("tax_rate=0.2"));
+    calculator.evaluate("tax_rate=0.2");
+    calculator.evaluate("define tax(amount) { return (amount*tax_rate) }");
+    assert!(calculator.accepts_input("tax(50)+tax_rate"));
+    assert_eq!(calculator.evaluate("tax(50)+tax_rate"), "10.2");
+}
+
+#[test]
+fn test_calculator_session_files() {
+    let dir = std::env::temp_dir().join("slickcmd_calculator_test");
+    std::fs::create_dir_all(&dir).unwrap();
+    let files = SessionFiles {
+        init_file: dir.join("calc_init.bc"),
+        saved_file: dir.join("calc_session.bc"),
+    };
+    std::fs::write(&files.init_file, "define d(x) {\n return (x*2)\n}\nr = 3\n").unwrap();
+    let _ = std::fs::remove_file(&files.saved_file);
+
+    let mut calculator = Calculator::with_files(false, files.clone());
+    assert!(calculator.accepts_input("r*2"));
+    assert_eq!(calculator.evaluate("d(r)"), "6");
+    calculator.evaluate("t=42");
+    calculator.evaluate("define f(x) { return (x & 1e1) }");
+    assert_eq!(calculator.evaluate("=save"), "calculator session saved");
+
+    let mut calculator = Calculator::with_files(false, files.clone());
+    assert!(calculator.accepts_input("t+1"));
+    assert_eq!(calculator.evaluate("t+1"), "43");
+    assert_eq!(calculator.evaluate("f(15)"), "10");
+    calculator.evaluate("=reset");
+    assert!(!calculator.accepts_input("t+1"));
+    assert_eq!(calculator.evaluate("r"), "3");
+
+    std::fs::write(&files.init_file, "r = (3\n").unwrap();
+    let mut calculator = Calculator::with_files(false, files);
+    let result = calculator.evaluate("1");
+    assert!(result.contains("calc_init.bc:1:"), "{result}");
+    assert!(result.ends_with("\n1"));
+}
+
+#[test]
+fn test_calculator_bitwise_operators() {
+    let mut calculator = Calculator::new(false);
+    assert!(calculator.accepts_input("12 & 10"));
+    assert!(calculator.accepts_input("~last"));
+    assert_eq!(calculator.evaluate("12 & 10"), "8");
+    assert_eq!(calculator.evaluate("12 | 3 ^^ 1"), "14");
+    assert_eq!(calculator.evaluate("1 << 4 + 1"), "32");
+    assert_eq!(calculator.evaluate("~last"), "-33");
+    assert_eq!(calculator.evaluate("2 ^ 3"), "8");
+    assert_eq!(
+        calculator.evaluate("1.5 >> 1"),
+        "runtime error: bitwise operators need integer operands\n1.5 >> 1\n    ^~"
+    );
+}
+
+#[test]
+fn test_calculator_output_bases() {
+    let mut calculator = Calculator::new(false);
+    assert!(calculator.accepts_input("255 to hex"));
+    assert!(calculator.accepts_input("0b1010 in oct"));
+    assert_eq!(calculator.evaluate("255 to hex"), "0xFF");
+    assert_eq!(calculator.evaluate("0b1010 in oct"), "0o12");
+    assert_eq!(calculator.evaluate("0x1F:d"), "31");
+    assert_eq!(calculator.evaluate("16 + 15:d"), "31");
+    assert_eq!(calculator.evaluate("last * 2:x"), "0x3E");
+    assert_eq!(calculator.evaluate("0xD6 to bin"), "0b1101 0110");
+    assert_eq!(calculator.evaluate("-1 to hex"), "0xFFFFFFFFFFFFFFFF");
+    assert_eq!(calculator.evaluate("5/2 to hex"), "0x2.8");
+    assert_eq!(calculator.evaluate("x = 3; x + 1; x * 4 to bin"), "4\n0b1100");
+}
+
+#[test]
+fn test_calculator_scientific_notation() {
+    let mut calculator = Calculator::new(false);
+    assert!(calculator.accepts_input("6.02e23"));
+    assert!(calculator.accepts_input("1.5E-9 * 2"));
+    assert!(!calculator.accepts_input("1.5E-9e"));
+    assert_eq!(calculator.evaluate("6.02e23 / 2"), "301000000000000000000000");
+    assert_eq!(calculator.evaluate("1.5E-9 * 2"), "0.000000003");
+    assert_eq!(calculator.evaluate("6.02e23 / 7 to sci"), "8.6e22");
+    assert_eq!(calculator.evaluate("2^70:eng"), "1.18059162071741e21");
+    assert_eq!(calculator.evaluate("1.5E-9 * 2 in eng"), "3e-9");
+    // `E` is a digit in other bases
+    calculator.evaluate("ibase=16");
+    assert_eq!(calculator.evaluate("1E+2"), "32");
+    assert_eq!(calculator.evaluate("1E2"), "482");
+    calculator.evaluate("ibase=A");
+    assert_eq!(calculator.evaluate("1E2"), "100");
+}
+
+#[test]
+fn test_calculator_stops_after_a_screen_of_output() {
+    let mut calculator = Calculator::new(false);
+    let result = calculator.evaluate("for (i = 1; i < 1000; ++i) i");
+    assert!(result.starts_with("1\n2\n3\n"), "{result}");
+    assert!(
+        result.ends_with("\n50\noutput truncated after 50 lines"),
+        "{result}"
+    );
+    assert_eq!(result.lines().count(), 51, "{result}");
+    // the next evaluation starts with an empty output
+    assert_eq!(calculator.evaluate("i"), "51");
+}
+
+#[test]
+fn test_calculator_splits_long_results() {
+    let mut calculator = Calculator::new(false);
+    assert_eq!(calculator.evaluate("2^100"), "1267650600228229401496703205376");
+    calculator.set_line_length(12);
+    assert_eq!(
+        calculator.evaluate("2^100"),
+        "12676506002\\\n28229401496\\\n703205376"
+    );
+    calculator.evaluate("=reset");
+    assert_eq!(calculator.evaluate("2^40"), "10995116277\\\n76");
+}
+
+#[test]
+fn test_calculator_formats_results_before_splitting_them() {
+    let mut calculator = Calculator::new(false);
+    calculator.set_line_length(20);
+    let hex = calculator.evaluate("2^300 :x");
+    assert_eq!(hex.replace("\\\n", ""), format!("0x1{}", "0".repeat(75)));
+    assert!(hex.lines().all(|line| line.len() <= 20), "{hex}");
+    // the values printed before the last one are kept
+    assert_eq!(
+        calculator.evaluate("print \"a\\n\"; 2^70; 255 :x"),
+        "a\n1180591620717411303\\\n424\n0xFF"
+    );
+    // the zeros are removed before splitting the result
+    assert_eq!(calculator.evaluate("scale=100; 1/4"), "0.25");
+    assert_eq!(calculator.evaluate("-1.500"), "-1.5");
+    assert_eq!(calculator.evaluate("-1/4"), "-0.25");
+}
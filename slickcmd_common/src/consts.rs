@@ -16,6 +16,7 @@ pub const IDC_CHK_RUN_ON_STARTUP: u16 = 1006;
 pub const IDC_SYSLINK_SITE: u16 = 1007;
 pub const IDC_CHK_SHOW_CLOCK: u16 = 1008;
 pub const IDC_CHK_CALCULATOR: u16 = 1009;
+pub const IDC_CHK_CALC_MATH_LIB: u16 = 1010;
 
 //
 pub const WM_USER: u32 = 0x0400;